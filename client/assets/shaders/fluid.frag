@@ -0,0 +1,17 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(set = 0, binding = 2) uniform texture2DArray blockTextures;
+layout(set = 0, binding = 3) uniform sampler blockSampler;
+
+layout(location = 0) in vec3 aTexCoord;
+layout(location = 1) in float aLight;
+
+layout(location = 0) out vec4 outColor;
+
+// Blended over whatever is behind, see the fluid pipeline. How see-through it is
+// comes from the texture
+void main() {
+    vec4 color = texture(sampler2DArray(blockTextures, blockSampler), aTexCoord);
+    outColor = vec4(color.rgb * aLight, color.a);
+}
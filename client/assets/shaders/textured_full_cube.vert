@@ -5,10 +5,16 @@ layout(push_constant) uniform PerFrame {
     mat4 mvp;
 } per_frame;
 
+// Three words per face: the face itself, the light at its vertices and the rest of the shading
 layout(set = 0, binding = 0) readonly buffer Faces {
 	uint arr[];
 } faces;
 
+// One per draw, and every chunk gets a draw per axis group
+layout(set = 0, binding = 1) readonly buffer ChunkOrigins {
+	ivec4 arr[];
+} chunk_origins;
+
 vec3 offsetCcw(uint i, uint face_mask) {
     float x = (0xCA0CA0C >> i >> ((face_mask - 1) << 2)) & 1;
     float y = (0xCA0CA0C >> i >> ((face_mask    ) << 2)) & 1;
@@ -23,24 +29,58 @@ vec3 offsetCw(uint i, uint face_mask) {
     return vec3(x, y, z);
 }
 
-layout(location = 0) out vec3 aColor;
+// Texture coordinates in blocks, so that the texture repeats over greedy meshed faces,
+// and the layer of the block texture array
+layout(location = 0) out vec3 aTexCoord;
+layout(location = 1) out float aLight;
 
 void main() {
-    uint face = faces.arr[gl_VertexIndex >> 2];
+    uint face_idx = (gl_VertexIndex >> 2) * 3;
+    uint face = faces.arr[face_idx];
+    uint vertex_light = faces.arr[face_idx + 1];
+    uint shading = faces.arr[face_idx + 2];
 
     // MSB [XXXX XYYY][YYZZ ZZZF][NN?? ??II][IIII IIII] LSB
     vec3 pos = vec3(face >> 27, (face >> 22) & 0x1F, (face >> 17) & 0x1F);
     bool flip = (face & 0x10000) != 0; // 1 << 16 = 0x10000
     uint normal_bits = (face >> 14) & 3;
-    uint tex_id = face & 0x3FF;
+    uint texture_layer = face & 0x3FF;
 
     uint v_idx = gl_VertexIndex & 3;
-    if (flip) {
-        pos += 1.0 - offsetCcw(v_idx, normal_bits);
-    } else {
-        pos += offsetCw(v_idx, normal_bits);
+    // Quad flip: 0 1 2 3 -> 1 3 0 2 goes around the quad by one vertex, so the
+    // triangles share the other diagonal and keep their winding
+    if ((shading & 0x100) != 0) {
+        v_idx = (0x8D >> (v_idx * 2)) & 3;
     }
+    vec3 corner = flip ? 1.0 - offsetCcw(v_idx, normal_bits) : offsetCw(v_idx, normal_bits);
+
+    // Greedy meshed faces cover more than one block. W goes along the first coordinate
+    // that varies in the plane, H along the second
+    float width = float(((shading >> 9) & 0xF) + 1);
+    float height = float(((shading >> 13) & 0xF) + 1);
+    vec3 size = normal_bits == 1 ? vec3(width, 1.0, height)  // XZ
+              : normal_bits == 2 ? vec3(1.0, width, height)  // YZ
+              : vec3(width, height, 1.0);                    // XY
+    vec3 local = corner * size;
+    pos += local;
+
+    // Textures are upright on the sides, so v goes down from the top of the face there
+    vec2 uv = normal_bits == 1 ? local.xz
+            : normal_bits == 2 ? vec2(local.z, size.y - local.y)
+            : vec2(local.x, size.y - local.y);
+    aTexCoord = vec3(uv, float(texture_layer));
+
+    // [SSSS BBBB] per vertex, never completely dark
+    uint light_bits = (vertex_light >> (v_idx * 8)) & 0xFF;
+    float sky_light = float(light_bits >> 4) / 15.0;
+    float block_light = float(light_bits & 0xF) / 15.0;
+    float light = mix(0.05, 1.0, max(sky_light, block_light));
+
+    const float AO_CURVE[4] = float[](0.45, 0.65, 0.85, 1.0);
+    light *= AO_CURVE[(shading >> (v_idx * 2)) & 3];
+
+    aLight = light;
+    pos += vec3(chunk_origins.arr[gl_InstanceIndex].xyz * 16);
 
     gl_Position = per_frame.mvp * vec4(pos, 1.0);
-    aColor = pos / 32.0;
 }
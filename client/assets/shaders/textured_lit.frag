@@ -1,10 +1,15 @@
 #version 450
 #extension GL_ARB_separate_shader_objects : enable
 
-layout(location = 0) in vec3 aColor;
+layout(set = 0, binding = 2) uniform texture2DArray blockTextures;
+layout(set = 0, binding = 3) uniform sampler blockSampler;
+
+layout(location = 0) in vec3 aTexCoord;
+layout(location = 1) in float aLight;
 
 layout(location = 0) out vec4 outColor;
 
 void main() {
-    outColor = vec4(aColor, 1.0);
+    vec3 color = texture(sampler2DArray(blockTextures, blockSampler), aTexCoord).rgb;
+    outColor = vec4(color * aLight, 1.0);
 }
@@ -0,0 +1,133 @@
+// Compiles the GLSL shaders in assets/shaders to SPIR-V, every time one of them changes,
+// so that the compiled ones can't go stale. Every shader gets two variants:
+// - debug_<name>.spv, with names for debuggers and validation layers,
+// - <name>.spv, without,
+// see `assets::shader_path()` for which one gets loaded.
+//
+// They go into OUT_DIR/shaders, which is where the game finds them when it runs from the
+// source tree, see `assets::BUILT_SHADER_DIR`. That's also what makes hot reloading work:
+// while the game is running, edit a shader and run `cargo build`, and the game picks up
+// the new SPIR-V. To ship them, copy them into the assets with the copy_shaders binary.
+
+use std::{
+    env,
+    fs,
+    path::Path,
+    process,
+};
+
+use codespan_reporting::{
+    diagnostic::{Diagnostic, Label},
+    files::SimpleFile,
+    term::{self, termcolor::NoColor},
+};
+use naga::{
+    back::spv,
+    front::glsl,
+    valid::{Capabilities, ValidationFlags, Validator},
+    ShaderStage,
+};
+
+fn main() {
+    let shader_dir = Path::new(env!("CARGO_MANIFEST_DIR")).parent().unwrap().join("assets/shaders");
+    // Cargo looks at everything in there, which catches new shaders
+    println!("cargo:rerun-if-changed={}", shader_dir.display());
+
+    let mut shaders = fs::read_dir(&shader_dir)
+        .unwrap_or_else(|e| fail(&format!("Couldn't read {}: {e}", shader_dir.display())))
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap_or_else(|e| fail(&format!("Couldn't read {}: {e}", shader_dir.display())));
+    shaders.retain(|path| stage(path).is_some());
+    shaders.sort();
+
+    let out_dir = Path::new(&env::var_os("OUT_DIR").unwrap()).join("shaders");
+    if let Err(e) = fs::create_dir_all(&out_dir) {
+        fail(&format!("Couldn't create {}: {e}", out_dir.display()));
+    }
+
+    // All of them, so that every broken shader shows up at once
+    let mut failed = 0;
+    for path in &shaders {
+        println!("cargo:rerun-if-changed={}", path.display());
+        if let Err(message) = compile(path, &out_dir) {
+            eprintln!("{message}");
+            failed += 1;
+        }
+    }
+    if failed > 0 {
+        fail(&format!("{failed} shader(s) failed to compile"));
+    }
+}
+
+fn stage(path: &Path) -> Option<ShaderStage> {
+    match path.extension()?.to_str()? {
+        "vert" => Some(ShaderStage::Vertex),
+        "frag" => Some(ShaderStage::Fragment),
+        "comp" => Some(ShaderStage::Compute),
+        _ => None,
+    }
+}
+
+// Returns the diagnostics to show if it doesn't
+fn compile(path: &Path, out_dir: &Path) -> Result<(), String> {
+    let source = fs::read_to_string(path).map_err(|e| format!("error: Couldn't read {}: {e}", path.display()))?;
+    let display_path = path.display().to_string();
+
+    let module = glsl::Parser::default()
+        .parse(&glsl::Options::from(stage(path).unwrap()), &source)
+        .map_err(|errors| {
+            let file = SimpleFile::new(&display_path, &source);
+            errors
+                .into_iter()
+                .map(|error| {
+                    let mut diagnostic = Diagnostic::error().with_message(error.kind.to_string());
+                    if let Some(range) = error.meta.to_range() {
+                        diagnostic = diagnostic.with_labels(vec![Label::primary((), range)]);
+                    }
+                    emit(&file, &diagnostic)
+                })
+                .collect::<String>()
+        })?;
+
+    let info = Validator::new(ValidationFlags::all(), Capabilities::all())
+        .validate(&module)
+        .map_err(|error| error.emit_to_string_with_path(&source, &display_path))?;
+
+    let file_name = path.file_name().unwrap().to_string_lossy();
+    for (prefix, debug) in [("debug_", true), ("", false)] {
+        let mut flags = spv::WriterFlags::LABEL_VARYINGS | spv::WriterFlags::CLAMP_FRAG_DEPTH;
+        if debug {
+            flags |= spv::WriterFlags::DEBUG;
+        }
+        // No ADJUST_COORDINATE_SPACE, the viewport is flipped already
+        let options = spv::Options { flags, ..Default::default() };
+        let words = spv::write_vec(&module, &info, &options, None)
+            .map_err(|e| format!("error: Couldn't generate SPIR-V for {display_path}: {e}"))?;
+
+        let out_path = out_dir.join(format!("{prefix}{file_name}.spv"));
+        write_if_changed(&out_path, words.iter().flat_map(|word| word.to_le_bytes()).collect())
+            .map_err(|e| format!("error: Couldn't write {}: {e}", out_path.display()))?;
+    }
+    Ok(())
+}
+
+fn emit(file: &SimpleFile<&String, &String>, diagnostic: &Diagnostic<()>) -> String {
+    let mut writer = NoColor::new(Vec::new());
+    term::emit(&mut writer, &term::Config::default(), file, diagnostic).expect("Writing to a Vec can't fail");
+    String::from_utf8(writer.into_inner()).unwrap()
+}
+
+// Leaves the file alone if it's the same, so that a build that didn't change anything
+// doesn't make a running game reload the shaders
+fn write_if_changed(path: &Path, bytes: Vec<u8>) -> std::io::Result<()> {
+    if matches!(fs::read(path), Ok(old) if old == bytes) {
+        return Ok(());
+    }
+    fs::write(path, bytes)
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("error: {message}");
+    process::exit(1);
+}
@@ -0,0 +1,307 @@
+// Everything the game loads besides the world: shaders, textures and such. Assets are
+// looked up by their path from the asset root, with forward slashes, like
+// "textures/blocks/stone.png", and come from one of
+// - a directory laid out like client/assets,
+// - a tar archive of one (tar -cf assets.tar -C client/assets .),
+// - the executable itself, with the `embed` feature, as a fallback for when neither
+//   is around.
+//
+// The compiled shaders aren't in client/assets, build.rs puts them in OUT_DIR. Directories
+// without them get them from there, which is how running from the source tree works. To
+// put them into a directory (or archive) of their own, run the copy_shaders binary.
+//
+// In debug builds, whatever gets read from a directory is watched, so that shaders and
+// textures can be reloaded while the game is running, see `Assets::poll_changes()`.
+
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    fmt,
+    fs::File,
+    io::{self, ErrorKind, Read},
+    path::{Component, Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, Instant, SystemTime},
+};
+
+use anyhow::{bail, Context};
+use log::info;
+
+mod tests;
+
+/// Points to the asset directory or archive to use, before anything else is tried.
+pub const ASSETS_ENV_VAR: &str = "GAME_ASSETS";
+
+// Where the assets are in a checkout, for running straight out of the source tree
+const SOURCE_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../assets");
+
+/// Where build.rs puts the compiled shaders. Only there on the machine that built the game.
+pub const BUILT_SHADER_DIR: &str = concat!(env!("OUT_DIR"), "/shaders");
+
+/// Where the compiled shaders go inside the assets.
+pub const SHADER_DIR: &str = "shaders/bin";
+
+// Checking every watched file every frame would be a bit much
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+pub mod shaders {
+    // The vertex shader for all full cubes with textures, i.e the main
+    // vertex shader that renders the majority of the world
+    pub const TEXTURED_FULL_CUBE_VERT: &str = "textured_full_cube.vert";
+
+    // The fragment shader for all textured + lit geometry
+    pub const TEXTURED_LIT_FRAG: &str = "textured_lit.frag";
+
+    // The fragment shader for fluids, which are drawn translucent after everything else
+    pub const FLUID_FRAG: &str = "fluid.frag";
+
+    // Builds a level of the Hi-Z pyramid used for occlusion culling
+    pub const HIZ_DOWNSAMPLE_COMP: &str = "hiz_downsample.comp";
+
+    // Finds the chunk draws that are hidden behind last frame's depth
+    pub const OCCLUSION_CULL_COMP: &str = "occlusion_cull.comp";
+
+    // Throws those out of the draws that get drawn
+    pub const OCCLUSION_COMPACT_COMP: &str = "occlusion_compact.comp";
+}
+
+/// Where the compiled SPIR-V of a shader is, with debug info in debug builds.
+pub fn shader_path(name: &str) -> String {
+    if cfg!(debug_assertions) {
+        format!("{SHADER_DIR}/debug_{name}.spv")
+    } else {
+        format!("{SHADER_DIR}/{name}.spv")
+    }
+}
+
+enum Source {
+    Dir(PathBuf),
+    Archive {
+        path: PathBuf,
+        files: HashMap<String, Box<[u8]>>,
+    },
+    #[cfg(feature = "embed")]
+    Embedded,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Dir(path) => write!(f, "directory {}", path.display()),
+            Source::Archive { path, .. } => write!(f, "archive {}", path.display()),
+            #[cfg(feature = "embed")]
+            Source::Embedded => write!(f, "embedded assets"),
+        }
+    }
+}
+
+pub struct Assets {
+    source: Source,
+    // Modification times of everything read from the directory so far (None if it
+    // didn't exist), to notice when it changes. Only in debug builds
+    watched: Mutex<HashMap<String, Option<SystemTime>>>,
+    last_poll: Instant,
+}
+
+impl Assets {
+    pub fn from_dir(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        if !path.is_dir() {
+            bail!("{} isn't a directory", path.display());
+        }
+        Ok(Self::new(Source::Dir(path.to_owned())))
+    }
+
+    /// The whole archive is read up front.
+    pub fn from_archive(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let file = File::open(path).with_context(|| format!("Couldn't open {}", path.display()))?;
+        let files = read_archive(file).with_context(|| format!("Couldn't read the archive {}", path.display()))?;
+        Ok(Self::new(Source::Archive { path: path.to_owned(), files }))
+    }
+
+    #[cfg(feature = "embed")]
+    pub fn embedded() -> Self {
+        Self::new(Source::Embedded)
+    }
+
+    /// Tries, in this order: whatever `ASSETS_ENV_VAR` points to, an `assets` directory
+    /// or `assets.tar` archive in the working directory or next to the executable, the
+    /// assets in the source tree, and finally the embedded assets, if they were built in.
+    pub fn find() -> anyhow::Result<Self> {
+        if let Some(path) = std::env::var_os(ASSETS_ENV_VAR) {
+            let path = PathBuf::from(path);
+            let assets = if path.is_dir() { Self::from_dir(&path) } else { Self::from_archive(&path) };
+            return assets.with_context(|| format!("{ASSETS_ENV_VAR} is set to {}", path.display()));
+        }
+
+        let exe_dir = std::env::current_exe().ok().and_then(|exe| exe.parent().map(Path::to_owned));
+        let mut candidates = Vec::new();
+        for dir in [Some(PathBuf::from(".")), exe_dir].into_iter().flatten() {
+            candidates.push(dir.join("assets"));
+            candidates.push(dir.join("assets.tar"));
+        }
+        candidates.push(PathBuf::from(SOURCE_DIR));
+
+        for path in candidates {
+            let assets = if path.is_dir() {
+                Self::from_dir(&path)?
+            } else if path.is_file() {
+                Self::from_archive(&path)?
+            } else {
+                continue;
+            };
+            info!("Loading assets from the {}", assets.source);
+            return Ok(assets);
+        }
+
+        #[cfg(feature = "embed")]
+        {
+            info!("Using the embedded assets");
+            Ok(Self::embedded())
+        }
+        #[cfg(not(feature = "embed"))]
+        {
+            bail!("Couldn't find the assets, point {ASSETS_ENV_VAR} to them")
+        }
+    }
+
+    fn new(source: Source) -> Self {
+        Self {
+            source,
+            watched: Mutex::new(HashMap::new()),
+            last_poll: Instant::now(),
+        }
+    }
+
+    /// Fails with `ErrorKind::NotFound` if there's no such asset.
+    pub fn read(&self, path: &str) -> io::Result<Cow<'_, [u8]>> {
+        let not_found = || io::Error::new(ErrorKind::NotFound, format!("No {path} in the {}", self.source));
+        match &self.source {
+            Source::Dir(dir) => {
+                let file_path = file_path(dir, path);
+                if cfg!(debug_assertions) {
+                    self.watched.lock().unwrap().insert(path.to_owned(), modified(&file_path));
+                }
+                match std::fs::read(&file_path) {
+                    Ok(bytes) => Ok(Cow::Owned(bytes)),
+                    Err(e) if e.kind() == ErrorKind::NotFound => Err(not_found()),
+                    Err(e) => Err(e),
+                }
+            }
+            Source::Archive { files, .. } => files.get(path).map(|bytes| Cow::Borrowed(&**bytes)).ok_or_else(not_found),
+            #[cfg(feature = "embed")]
+            Source::Embedded => embedded::FILES
+                .iter()
+                .find(|(name, _)| *name == path)
+                .map(|(_, bytes)| Cow::Borrowed(*bytes))
+                .ok_or_else(not_found),
+        }
+    }
+
+    /// The compiled shader, see `shader_path()`.
+    pub fn shader(&self, name: &str) -> anyhow::Result<Cow<'_, [u8]>> {
+        let path = shader_path(name);
+        self.read(&path).with_context(|| format!("Couldn't load the shader {path}"))
+    }
+
+    /// The assets that changed since they were read, or since the last time they were
+    /// returned by this. Only ever finds anything in debug builds, for assets read from a
+    /// directory, and checks at most every `POLL_INTERVAL`, so it's fine to call every frame.
+    pub fn poll_changes(&mut self) -> Vec<String> {
+        if self.last_poll.elapsed() < POLL_INTERVAL {
+            return Vec::new();
+        }
+        self.last_poll = Instant::now();
+
+        let Source::Dir(dir) = &self.source else {
+            return Vec::new();
+        };
+        let mut changed = Vec::new();
+        for (path, last_modified) in self.watched.get_mut().unwrap().iter_mut() {
+            let modified = modified(&file_path(dir, path));
+            if modified != *last_modified {
+                *last_modified = modified;
+                changed.push(path.clone());
+            }
+        }
+        changed
+    }
+}
+
+// Shaders that weren't copied into the directory come from the build
+fn file_path(dir: &Path, path: &str) -> PathBuf {
+    let file_path = dir.join(path);
+    match path.strip_prefix(SHADER_DIR).and_then(|rest| rest.strip_prefix('/')) {
+        Some(name) if !file_path.exists() => Path::new(BUILT_SHADER_DIR).join(name),
+        _ => file_path,
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+// Every file in the archive, by its path without the leading "./" that tar puts in
+fn read_archive(reader: impl Read) -> anyhow::Result<HashMap<String, Box<[u8]>>> {
+    let mut archive = tar::Archive::new(reader);
+    let mut files = HashMap::new();
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+
+        let mut path = Vec::new();
+        for component in entry.path()?.components() {
+            match component {
+                Component::CurDir => {}
+                Component::Normal(name) => path.push(name.to_string_lossy().into_owned()),
+                _ => bail!("Unexpected path {} in the archive", entry.path()?.display()),
+            }
+        }
+
+        let mut bytes = Vec::with_capacity(entry.size() as usize);
+        entry.read_to_end(&mut bytes)?;
+        files.insert(path.join("/"), bytes.into_boxed_slice());
+    }
+    Ok(files)
+}
+
+// Everything the game ships with. Both variants of every shader, they're tiny anyway
+#[cfg(feature = "embed")]
+mod embedded {
+    macro_rules! embed {
+        (shaders: [$($shader:literal),* $(,)?], files: [$($path:literal),* $(,)?] $(,)?) => {
+            &[
+                $(
+                    (concat!("shaders/bin/", $shader, ".spv"), include_bytes!(concat!(env!("OUT_DIR"), "/shaders/", $shader, ".spv")) as &[u8]),
+                    (concat!("shaders/bin/debug_", $shader, ".spv"), include_bytes!(concat!(env!("OUT_DIR"), "/shaders/debug_", $shader, ".spv")) as &[u8]),
+                )*
+                $(($path, include_bytes!(concat!("../../../assets/", $path)) as &[u8]),)*
+            ]
+        };
+    }
+
+    pub const FILES: &[(&str, &[u8])] = embed! {
+        shaders: [
+            "textured_full_cube.vert",
+            "textured_lit.frag",
+            "fluid.frag",
+            "hiz_downsample.comp",
+            "occlusion_cull.comp",
+            "occlusion_compact.comp",
+        ],
+        files: [
+            "textures/blocks/dirt.png",
+            "textures/blocks/grass_side.png",
+            "textures/blocks/grass_top.png",
+            "textures/blocks/lamp.png",
+            "textures/blocks/sand.png",
+            "textures/blocks/snow.png",
+            "textures/blocks/stone.png",
+            "textures/blocks/test.png",
+            "textures/blocks/water.png",
+        ],
+    };
+}
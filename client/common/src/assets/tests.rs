@@ -0,0 +1,64 @@
+#![cfg(test)]
+
+use std::time::Instant;
+
+use super::{read_archive, shader_path, shaders, Assets, POLL_INTERVAL, SHADER_DIR};
+
+#[test]
+fn archive_paths_are_relative_to_the_root() {
+    let mut builder = tar::Builder::new(Vec::new());
+    for (path, contents) in [("./shaders/bin/a.spv", &b"spirv"[..]), ("textures/blocks/b.png", b"png")] {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(contents.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append_data(&mut header, path, contents).unwrap();
+    }
+    let archive = builder.into_inner().unwrap();
+
+    let files = read_archive(&archive[..]).unwrap();
+    assert_eq!(files.len(), 2);
+    assert_eq!(&*files["shaders/bin/a.spv"], b"spirv");
+    assert_eq!(&*files["textures/blocks/b.png"], b"png");
+}
+
+#[test]
+fn changed_files_are_noticed() {
+    let dir = std::env::temp_dir().join(format!("assets_test_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("a.txt"), "a").unwrap();
+
+    let mut assets = Assets::from_dir(&dir).unwrap();
+    let poll = |assets: &mut Assets| {
+        assets.last_poll = Instant::now() - POLL_INTERVAL;
+        assets.poll_changes()
+    };
+    assert_eq!(&*assets.read("a.txt").unwrap(), b"a");
+    assert!(assets.read("b.txt").is_err());
+    assert!(poll(&mut assets).is_empty());
+
+    // Missing files are watched too, for when they show up
+    std::fs::remove_file(dir.join("a.txt")).unwrap();
+    std::fs::write(dir.join("b.txt"), "b").unwrap();
+    let mut changed = poll(&mut assets);
+    let unchanged = poll(&mut assets);
+    std::fs::remove_dir_all(&dir).unwrap();
+    changed.sort();
+    assert_eq!(changed, ["a.txt", "b.txt"]);
+    assert!(unchanged.is_empty());
+}
+
+#[test]
+fn shaders_come_from_the_build_unless_copied() {
+    let dir = std::env::temp_dir().join(format!("assets_shader_test_{}", std::process::id()));
+    std::fs::create_dir_all(dir.join(SHADER_DIR)).unwrap();
+
+    let assets = Assets::from_dir(&dir).unwrap();
+    let built = assets.shader(shaders::FLUID_FRAG).unwrap().into_owned();
+    assert!(!built.is_empty());
+
+    std::fs::write(dir.join(shader_path(shaders::FLUID_FRAG)), "copied").unwrap();
+    let copied = assets.shader(shaders::FLUID_FRAG).unwrap().into_owned();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(copied, b"copied");
+}
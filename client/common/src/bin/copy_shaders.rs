@@ -0,0 +1,28 @@
+// Copies the shaders build.rs compiled into an asset directory, for running the game
+// anywhere but the source tree:
+//   cargo run -p common --bin copy_shaders -- path/to/assets
+
+use std::{fs, path::Path};
+
+use anyhow::{bail, Context};
+use common::assets::{BUILT_SHADER_DIR, SHADER_DIR};
+
+fn main() -> anyhow::Result<()> {
+    let mut args = std::env::args_os().skip(1);
+    let (Some(assets_dir), None) = (args.next(), args.next()) else {
+        bail!("Usage: copy_shaders <assets directory>");
+    };
+
+    let to = Path::new(&assets_dir).join(SHADER_DIR);
+    fs::create_dir_all(&to).with_context(|| format!("Couldn't create {}", to.display()))?;
+
+    let mut copied = 0;
+    for entry in fs::read_dir(BUILT_SHADER_DIR).with_context(|| format!("Couldn't read {BUILT_SHADER_DIR}"))? {
+        let from = entry?.path();
+        let to = to.join(from.file_name().unwrap());
+        fs::copy(&from, &to).with_context(|| format!("Couldn't copy {} to {}", from.display(), to.display()))?;
+        copied += 1;
+    }
+    println!("Copied {copied} shaders to {}", to.display());
+    Ok(())
+}
@@ -0,0 +1,98 @@
+use quinn::{RecvStream, SendStream};
+use tokio::sync::mpsc::{Receiver, Sender, UnboundedReceiver};
+
+use crate::util::receive_bytes;
+
+pub(super) mod chat {
+    use super::*;
+
+    /// Forwards received `ChatEvent`s to the main thread, still encoded.
+    pub async fn recv_driver(
+        mut incoming: RecvStream,
+        to_main: Sender<Box<[u8]>>,
+    ) -> anyhow::Result<()> {
+        let mut buf = Vec::new();
+        loop {
+            let stream = receive_bytes(&mut incoming, &mut buf).await?;
+            if to_main.send(stream.bytes().into()).await.is_err() {
+                return Ok(()); // main thread is gone
+            }
+        }
+    }
+
+    /// Writes out already-encoded chat messages, framed with the message length.
+    pub async fn send_driver(
+        mut outgoing: SendStream,
+        mut messages: Receiver<Box<[u8]>>,
+    ) -> anyhow::Result<()> {
+        while let Some(message) = messages.recv().await {
+            outgoing.write_all(&u16::to_le_bytes(message.len() as u16)).await?;
+            outgoing.write_all(&message).await?;
+        }
+        Ok(())
+    }
+}
+
+pub(super) mod chunks {
+    use super::*;
+
+    /// Forwards received chunks (position + chunk data) to the main thread as-is.
+    /// Blocks when the main thread falls behind, which in turn makes the server
+    /// slow down thanks to QUIC flow control.
+    pub async fn recv_driver(
+        mut incoming: RecvStream,
+        to_main: Sender<Box<[u8]>>,
+    ) -> anyhow::Result<()> {
+        let mut buf = Vec::new();
+        loop {
+            let stream = receive_bytes(&mut incoming, &mut buf).await?;
+            if to_main.send(stream.bytes().into()).await.is_err() {
+                return Ok(()); // main thread is gone
+            }
+        }
+    }
+
+    /// Writes out already-encoded requests, framed with the message length.
+    pub async fn send_driver(
+        mut outgoing: SendStream,
+        mut requests: UnboundedReceiver<Box<[u8]>>,
+    ) -> anyhow::Result<()> {
+        while let Some(request) = requests.recv().await {
+            debug_assert!(request.len() <= u16::MAX as usize, "Chunk request too long! ({} bytes)", request.len());
+
+            outgoing.write_all(&u16::to_le_bytes(request.len() as u16)).await?;
+            outgoing.write_all(&request).await?;
+        }
+        Ok(())
+    }
+}
+
+pub(super) mod state {
+    use super::*;
+
+    /// Forwards received `GameEvent`s to the main thread, still encoded.
+    pub async fn recv_driver(
+        mut incoming: RecvStream,
+        to_main: Sender<Box<[u8]>>,
+    ) -> anyhow::Result<()> {
+        let mut buf = Vec::new();
+        loop {
+            let stream = receive_bytes(&mut incoming, &mut buf).await?;
+            if to_main.send(stream.bytes().into()).await.is_err() {
+                return Ok(()); // main thread is gone
+            }
+        }
+    }
+
+    /// Writes out already-encoded movement, framed with the message length.
+    pub async fn send_driver(
+        mut outgoing: SendStream,
+        mut messages: Receiver<Box<[u8]>>,
+    ) -> anyhow::Result<()> {
+        while let Some(message) = messages.recv().await {
+            outgoing.write_all(&u16::to_le_bytes(message.len() as u16)).await?;
+            outgoing.write_all(&message).await?;
+        }
+        Ok(())
+    }
+}
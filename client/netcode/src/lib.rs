@@ -1,3 +1,4 @@
+mod channels;
 pub mod login;
 pub mod message;
 pub mod net_thread;
@@ -6,10 +7,17 @@ mod util;
 use std::{net::SocketAddr, thread::JoinHandle};
 
 use flexstr::SharedStr;
+use glam::{Vec2, Vec3};
+use log::warn;
 use login::LoginResponse;
+use message::OutMsg;
 use net_thread::NetChannels;
+use shared::{
+    net::{ChatEvent, ChunkRequest, GameEvent, MAX_CHAT_MESSAGE_LEN},
+    serialization::{ByteReader, ByteWriter},
+};
 use tokio::sync::{
-    mpsc::{channel, Receiver, Sender},
+    mpsc::{channel, unbounded_channel, Receiver, Sender, UnboundedSender},
     oneshot,
 };
 
@@ -17,9 +25,14 @@ use tokio::sync::{
 pub struct Channels {
     // Net -> Main
     incoming: Receiver<Box<[u8]>>,
+    chunks: Receiver<Box<[u8]>>,
+    chat_messages: Receiver<Box<[u8]>>,
 
     // Main -> Net
-    pub chat: Sender<Box<[u8]>>,
+    chat: Sender<Box<[u8]>>,
+    // Unbounded, because a dropped request would leave the client waiting for chunks forever
+    chunk_requests: UnboundedSender<Box<[u8]>>,
+    state: Sender<Box<[u8]>>,
     stop: Option<oneshot::Sender<()>>,
 }
 
@@ -35,8 +48,75 @@ impl ServerConnection {
         !self.handle.is_finished()
     }
 
-    pub fn poll(&mut self) -> Option<Box<[u8]>> {
-        self.channels.incoming.try_recv().ok()
+    /// Returns the next thing that happened to the player, like being teleported.
+    pub fn poll_game_event(&mut self) -> Option<GameEvent> {
+        loop {
+            let bytes = self.channels.incoming.try_recv().ok()?;
+            match GameEvent::decode(&mut ByteReader::new(&bytes)) {
+                Some(event) => return Some(event),
+                None => warn!("Received a malformed game event"),
+            }
+        }
+    }
+
+    /// Returns the next received chunk: the chunk position (3 x i32) followed by the
+    /// encoded chunk, see `Chunk::decode()`.
+    pub fn poll_chunk(&mut self) -> Option<Box<[u8]>> {
+        self.channels.chunks.try_recv().ok()
+    }
+
+    pub fn request_chunks(&mut self, request: &ChunkRequest) {
+        let msg = OutMsg::ChunkRequest(request);
+        let mut buf = vec![0; msg.encoded_len()];
+        msg.encode(&mut ByteWriter::new(&mut buf));
+        _ = self.channels.chunk_requests.send(buf.into_boxed_slice());
+    }
+
+    /// Returns false if the message couldn't be sent: it's empty or longer than
+    /// `MAX_CHAT_MESSAGE_LEN`, or a lot of them are still waiting to be sent.
+    /// The server sends it back, along with everyone else's, see `poll_chat()`.
+    /// Starting with a slash makes it a command.
+    pub fn send_chat(&mut self, text: &str) -> bool {
+        if text.is_empty() || text.len() > MAX_CHAT_MESSAGE_LEN {
+            return false;
+        }
+        self.send_on_chat_stream(&OutMsg::Chat(text))
+    }
+
+    /// Asks for tab completions for the chat line `input`, which come back as a
+    /// `ChatEvent::Completions`.
+    pub fn request_completions(&mut self, input: &str) -> bool {
+        if input.len() > MAX_CHAT_MESSAGE_LEN {
+            return false;
+        }
+        self.send_on_chat_stream(&OutMsg::Complete(input))
+    }
+
+    /// Tells the server where the player is now, which is what it streams chunks around.
+    /// Returns false if it couldn't be sent because too many are still waiting, try again
+    /// later.
+    pub fn send_movement(&mut self, position: Vec3, head_rotation: Vec2) -> bool {
+        let msg = OutMsg::Move { position, head_rotation };
+        let mut buf = vec![0; msg.encoded_len()];
+        msg.encode(&mut ByteWriter::new(&mut buf));
+        self.channels.state.try_send(buf.into_boxed_slice()).is_ok()
+    }
+
+    fn send_on_chat_stream(&mut self, msg: &OutMsg) -> bool {
+        let mut buf = vec![0; msg.encoded_len()];
+        msg.encode(&mut ByteWriter::new(&mut buf));
+        self.channels.chat.try_send(buf.into_boxed_slice()).is_ok()
+    }
+
+    /// Returns the next chat message, from any player or the server, or tab completions.
+    pub fn poll_chat(&mut self) -> Option<ChatEvent> {
+        loop {
+            let bytes = self.channels.chat_messages.try_recv().ok()?;
+            match ChatEvent::decode(&mut ByteReader::new(&bytes)) {
+                Some(event) => return Some(event),
+                None => warn!("Received a malformed chat event"),
+            }
+        }
     }
 
     pub fn stop(&mut self) {
@@ -86,22 +166,36 @@ impl Connecting {
     }
 }
 
-pub fn try_connect(address: SocketAddr, username: SharedStr) -> Connecting {
+/// `block_checksum` is `BlockRegistry::checksum()`, the server turns the login down
+/// if its block definitions don't match.
+pub fn try_connect(address: SocketAddr, username: SharedStr, block_checksum: u64) -> Connecting {
     let (incoming_send, incoming_recv) = channel(128);
+    let (chunks_send, chunks_recv) = channel(256);
     let (chat_send, chat_recv) = channel(128);
+    let (chat_messages_send, chat_messages_recv) = channel(128);
+    let (chunk_requests_send, chunk_requests_recv) = unbounded_channel();
+    let (state_send, state_recv) = channel(16);
     let (stop_send, stop_recv) = oneshot::channel();
 
     let channels = Channels {
         incoming: incoming_recv,
+        chunks: chunks_recv,
+        chat_messages: chat_messages_recv,
 
         chat: chat_send,
+        chunk_requests: chunk_requests_send,
+        state: state_send,
         stop: Some(stop_send),
     };
 
     let net_channels = NetChannels {
         incoming: incoming_send,
-        
+        chunks: chunks_send,
+        chat_messages: chat_messages_send,
+
         chat: chat_recv,
+        chunk_requests: chunk_requests_recv,
+        state: state_recv,
         stop: stop_recv,
     };
 
@@ -109,7 +203,7 @@ pub fn try_connect(address: SocketAddr, username: SharedStr) -> Connecting {
 
     let handle = std::thread::Builder::new()
         .name("Network Thread".to_owned())
-        .spawn(move || net_thread::start(address, username, net_channels, on_connect_send))
+        .spawn(move || net_thread::start(address, username, block_checksum, net_channels, on_connect_send))
         .unwrap();
 
     Connecting {
@@ -25,6 +25,7 @@ pub struct LoginResponse {
 pub async fn try_connect(
     server_address: SocketAddr,
     username: &SharedStr,
+    block_checksum: u64,
 ) -> anyhow::Result<(Endpoint, Connection, LoginResponse)> {
     let endpoint = setup::make_client_endpoint().unwrap();
 
@@ -36,6 +37,7 @@ pub async fn try_connect(
     writer.write_u16(shared::net::PROTOCOL_MAGIC);
     writer.write_u16(shared::net::PROTOCOL_VERSION);
     writer.write_str(username.as_str());
+    writer.write_u64(block_checksum);
     writer.write_message_len();
 
     let (mut hello_send, mut hello_recv) = conn.open_bi().await?;
@@ -1 +1,45 @@
+use glam::{Vec2, Vec3};
+use shared::{net::ChunkRequest, serialization::ByteWriter};
 
+// Other end to the server's InMsg
+pub enum OutMsg<'a> {
+    Chat(&'a str),
+    ChunkRequest(&'a ChunkRequest),
+    Complete(&'a str),
+    Move { position: Vec3, head_rotation: Vec2 },
+}
+
+impl OutMsg<'_> {
+    const CHAT: u8 = 1;
+    const CHUNK_REQUEST: u8 = 2;
+    const COMPLETE: u8 = 3;
+    const MOVE: u8 = 4;
+
+    pub fn encoded_len(&self) -> usize {
+        1 + match self {
+            OutMsg::Chat(msg) => 2 + msg.len(),
+            OutMsg::ChunkRequest(request) => request.encoded_len(),
+            OutMsg::Complete(input) => 2 + input.len(),
+            OutMsg::Move { .. } => 5 * 4,
+        }
+    }
+
+    pub fn encode(&self, dst: &mut ByteWriter) {
+        match self {
+            OutMsg::Chat(msg) => { dst.write_u8(Self::CHAT).write_str(msg); },
+            OutMsg::ChunkRequest(request) => {
+                dst.write_u8(Self::CHUNK_REQUEST);
+                request.encode(dst);
+            },
+            OutMsg::Complete(input) => { dst.write_u8(Self::COMPLETE).write_str(input); },
+            OutMsg::Move { position, head_rotation } => {
+                dst.write_u8(Self::MOVE)
+                    .write_f32(position.x)
+                    .write_f32(position.y)
+                    .write_f32(position.z)
+                    .write_f32(head_rotation.x)
+                    .write_f32(head_rotation.y);
+            },
+        };
+    }
+}
@@ -2,17 +2,23 @@ use std::net::SocketAddr;
 
 use flexstr::SharedStr;
 use log::{error, debug};
-use tokio::sync::{oneshot, mpsc::{Sender, Receiver}};
+use quinn::{Connection, SendStream, RecvStream};
+use shared::net::stream_id;
+use tokio::{task, sync::{oneshot, mpsc::{Sender, Receiver, UnboundedReceiver}}};
 
-use crate::login::{LoginResponse, self};
+use crate::{login::{LoginResponse, self}, channels};
 
 // Other end to lib::Channels
 pub struct NetChannels {
     // Net -> Main
     pub incoming: Sender<Box<[u8]>>,
+    pub chunks: Sender<Box<[u8]>>,
+    pub chat_messages: Sender<Box<[u8]>>,
 
     // Main -> Net
     pub chat: Receiver<Box<[u8]>>,
+    pub chunk_requests: UnboundedReceiver<Box<[u8]>>,
+    pub state: Receiver<Box<[u8]>>,
     pub stop: oneshot::Receiver<()> // command to terminate network thread
 }
 
@@ -20,10 +26,11 @@ pub struct NetChannels {
 async fn net_main(
     server_address: SocketAddr,
     username: SharedStr,
+    block_checksum: u64,
     channels: NetChannels,
     on_connect: oneshot::Sender<Result<LoginResponse, Box<str>>>,
 ) -> anyhow::Result<()> {
-    let (endpoint, mut _connection, response) = match login::try_connect(server_address, &username).await {
+    let (endpoint, connection, response) = match login::try_connect(server_address, &username, block_checksum).await {
         Ok(tuple) => tuple,
         Err(e) => {
             let _ = on_connect.send(Err(format!("Connection failed: {e}").into_boxed_str()));
@@ -31,16 +38,61 @@ async fn net_main(
         }
     };
 
+    let (chunk_send, chunk_recv) = match open_stream(&connection, stream_id::CHUNKS).await {
+        Ok(streams) => streams,
+        Err(e) => {
+            let _ = on_connect.send(Err(format!("Failed to open chunk stream: {e}").into_boxed_str()));
+            return Ok(());
+        }
+    };
+
+    let (chat_send, chat_recv) = match open_stream(&connection, stream_id::CHAT).await {
+        Ok(streams) => streams,
+        Err(e) => {
+            let _ = on_connect.send(Err(format!("Failed to open chat stream: {e}").into_boxed_str()));
+            return Ok(());
+        }
+    };
+
+    let (state_send, state_recv) = match open_stream(&connection, stream_id::STATE).await {
+        Ok(streams) => streams,
+        Err(e) => {
+            let _ = on_connect.send(Err(format!("Failed to open state stream: {e}").into_boxed_str()));
+            return Ok(());
+        }
+    };
+
     if on_connect.send(Ok(response)).is_err() {
         debug!("Main thread dropped on_connect channel");
         return Ok(());
     }
-    
+
+    let mut chunk_recv_driver = task::spawn(channels::chunks::recv_driver(chunk_recv, channels.chunks));
+    let mut chunk_send_driver = task::spawn(channels::chunks::send_driver(chunk_send, channels.chunk_requests));
+    let mut chat_recv_driver = task::spawn(channels::chat::recv_driver(chat_recv, channels.chat_messages));
+    let mut chat_send_driver = task::spawn(channels::chat::send_driver(chat_send, channels.chat));
+    let mut state_recv_driver = task::spawn(channels::state::recv_driver(state_recv, channels.incoming));
+    let mut state_send_driver = task::spawn(channels::state::send_driver(state_send, channels.state));
+
     let disconnect = channels.stop;
     tokio::select!(
         _ = disconnect => {}
+        e = connection.closed() => debug!("Connection closed: {e}"),
+        res = &mut chunk_recv_driver => debug!("Chunk receive driver stopped: {res:?}"),
+        res = &mut chunk_send_driver => debug!("Chunk send driver stopped: {res:?}"),
+        res = &mut chat_recv_driver => debug!("Chat receive driver stopped: {res:?}"),
+        res = &mut chat_send_driver => debug!("Chat send driver stopped: {res:?}"),
+        res = &mut state_recv_driver => debug!("State receive driver stopped: {res:?}"),
+        res = &mut state_send_driver => debug!("State send driver stopped: {res:?}"),
     );
 
+    chunk_recv_driver.abort();
+    chunk_send_driver.abort();
+    chat_recv_driver.abort();
+    chat_send_driver.abort();
+    state_recv_driver.abort();
+    state_send_driver.abort();
+
     debug!("Stopping network thread");
     endpoint.close(quinn::VarInt::from_u32(1), &[]); // Notify server
     endpoint.wait_idle().await; // Wait for clean shutdown
@@ -48,13 +100,21 @@ async fn net_main(
     Ok(())
 }
 
+/// Opens a stream to the server, writing the id byte that tells the server what it is for.
+async fn open_stream(connection: &Connection, id: u8) -> anyhow::Result<(SendStream, RecvStream)> {
+    let (mut outgoing, incoming) = connection.open_bi().await?;
+    outgoing.write_all(&[id]).await?;
+    Ok((outgoing, incoming))
+}
+
 pub fn start(
     server_address: SocketAddr,
     username: SharedStr,
+    block_checksum: u64,
     channels: NetChannels,
     on_connect: oneshot::Sender<Result<LoginResponse, Box<str>>>,
 ) {
-    if let Err(e) = net_main(server_address, username, channels, on_connect) {
+    if let Err(e) = net_main(server_address, username, block_checksum, channels, on_connect) {
         error!("Error in network thread: {}", e);
     }
 }
@@ -1,6 +1,6 @@
 use std::f32::consts::{PI, TAU};
 
-use glam::{Mat4, Vec2, Vec3};
+use glam::{Mat4, Vec2, Vec3, Vec4};
 
 // This is in the renderer crate for two reasons:
 // 1. Both renderer and the client need access, and dumping this into a crate
@@ -110,6 +110,11 @@ impl Camera {
         self.proj_view
     }
 
+    /// As of the last `update()`.
+    pub fn frustum(&self) -> Frustum {
+        Frustum::from_proj_view(self.proj_view)
+    }
+
     pub fn projection_matrix(&self) -> Mat4 {
         self.projection
     }
@@ -123,6 +128,36 @@ impl Camera {
     }
 }
 
+/// The volume visible to the camera, for throwing out things that are off screen.
+pub struct Frustum {
+    // ax + by + cz + d >= 0 for points on the inside. Not normalized: only
+    // the sign of the distance is needed.
+    planes: [Vec4; 6],
+}
+
+impl Frustum {
+    /// Extracts the planes from the projection-view matrix (Gribb & Hartmann). A point
+    /// is visible if -w <= x <= w, -w <= y <= w and 0 <= z <= w in clip space. With
+    /// the infinite projection, one of the z planes ends up with a zero normal and a
+    /// positive distance, so it lets everything through, as it should.
+    pub fn from_proj_view(proj_view: Mat4) -> Self {
+        let (r0, r1, r2, r3) = (proj_view.row(0), proj_view.row(1), proj_view.row(2), proj_view.row(3));
+        Self {
+            planes: [r3 + r0, r3 - r0, r3 + r1, r3 - r1, r2, r3 - r2],
+        }
+    }
+
+    /// Whether any part of the box might be visible. Conservative: boxes near the
+    /// corners of the frustum can pass even if they are just outside of it.
+    pub fn intersects_aabb(&self, min: Vec3, max: Vec3) -> bool {
+        self.planes.iter().all(|plane| {
+            // The corner furthest along the plane normal is the last one to leave
+            let corner = Vec3::select(plane.truncate().cmpge(Vec3::ZERO), max, min);
+            plane.truncate().dot(corner) + plane.w >= 0.0
+        })
+    }
+}
+
 pub fn euler_to_vec(yaw_rad: f32, pitch_rad: f32) -> Vec3 {
     let (yaw_sin, yaw_cos) = yaw_rad.sin_cos();
     let (pitch_sin, pitch_cos) = pitch_rad.sin_cos();
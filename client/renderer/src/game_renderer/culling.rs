@@ -0,0 +1,31 @@
+// Deciding what doesn't need to be drawn, on the CPU side.
+
+use glam::{IVec3, Vec3};
+
+use crate::camera::Frustum;
+
+/// Which axis groups of the chunk (in the usual +X, -X, +Y, -Y, +Z, -Z order) can
+/// have faces facing the camera. A +X face is only visible from the +X side of its
+/// plane, and the planes of all +X faces in a chunk are somewhere within the chunk's
+/// bounds, so if the camera is below the lower x bound, none of them are visible.
+/// Same for the other directions.
+///
+/// From any position outside of the chunk's bounds at least one, and at most three,
+/// of each opposite pair get dropped.
+pub fn visible_axis_groups(chunk_pos: IVec3, camera_pos: Vec3) -> [bool; 6] {
+    let min = (chunk_pos * 16).as_vec3();
+    let max = min + 16.0;
+    [
+        camera_pos.x > min.x,
+        camera_pos.x < max.x,
+        camera_pos.y > min.y,
+        camera_pos.y < max.y,
+        camera_pos.z > min.z,
+        camera_pos.z < max.z,
+    ]
+}
+
+pub fn chunk_in_frustum(chunk_pos: IVec3, frustum: &Frustum) -> bool {
+    let min = (chunk_pos * 16).as_vec3();
+    frustum.intersects_aabb(min, min + 16.0)
+}
@@ -1,44 +1,79 @@
 use ash::vk;
+use common::assets::Assets;
 use glam::IVec3;
 
 use crate::camera::Camera;
 
-use self::{state::State, world::RenderWorld};
+use self::{occlusion::{DrawStats, OcclusionCuller}, state::State, textures::BlockTextures, world::RenderWorld};
 
 use super::RendererBase;
 
+pub mod textures;
 pub mod world;
+mod culling;
+mod occlusion;
 mod state;
 
+mod tests;
+
 pub struct GameRenderer {
     pub world: RenderWorld,
+    pub textures: BlockTextures,
+    occlusion: OcclusionCuller,
     state: State
 }
 
 impl GameRenderer {
-    pub fn new(player_chunk_pos: IVec3, renderer: &mut RendererBase) -> anyhow::Result<Self> {
-        let state = state::init(&mut renderer.vk)?;
+    /// The block textures are `texture_size` x `texture_size` RGBA8 layers, see `BlockTextures`.
+    pub fn new(
+        player_chunk_pos: IVec3,
+        assets: &Assets,
+        texture_size: u32,
+        texture_pixels: &[u8],
+        renderer: &mut RendererBase,
+    ) -> anyhow::Result<Self> {
+        let state = state::init(&mut renderer.vk, assets)?;
+        let textures = BlockTextures::new(&mut renderer.vk, &state, texture_size, texture_pixels)?;
+        let mut occlusion = OcclusionCuller::new(&mut renderer.vk, &state, assets)?;
         let world = RenderWorld::new(player_chunk_pos, renderer, &state)?;
+        occlusion.set_draw_source(&renderer.vk, world.draw_buffers());
 
         Ok(Self {
             world,
+            textures,
+            occlusion,
             state
         })
     }
+
+    /// How many chunk draws survived culling, a couple of frames ago.
+    pub fn draw_stats(&self) -> DrawStats {
+        self.occlusion.stats()
+    }
 }
 
 impl GameRenderer {
     fn render_inner(&mut self, camera: &Camera, renderer: &RendererBase, cmd: vk::CommandBuffer, image_index: usize) -> anyhow::Result<()> {
         let vk = &renderer.vk;
         let state = &self.state;
+        let frame = renderer.frame_in_flight();
+
+        let draw_count = self.world.prepare_draws(camera, frame);
+        self.occlusion.cull(cmd, vk, frame, draw_count);
 
         unsafe {
             vk.device.cmd_begin_render_pass(cmd, &vk::RenderPassBeginInfo::builder()
-                .clear_values(&[vk::ClearValue{
-                    color: vk::ClearColorValue {
-                        float32: [0.0, 0.0, 0.0, 1.0]
-                    }
-                }])
+                .clear_values(&[
+                    vk::ClearValue {
+                        // Reverse-Z, so 0 is far away
+                        depth_stencil: vk::ClearDepthStencilValue { depth: 0.0, stencil: 0 }
+                    },
+                    vk::ClearValue {
+                        color: vk::ClearColorValue {
+                            float32: [0.0, 0.0, 0.0, 1.0]
+                        }
+                    },
+                ])
                 .framebuffer(state.main_pass_framebuffers[image_index])
                 .render_pass(state.main_render_pass)
                 .render_area(vk::Rect2D {
@@ -51,15 +86,21 @@ impl GameRenderer {
             let mvp_bytes = bytemuck::cast_slice(&mvp);
             vk.device.cmd_push_constants(cmd, state.full_block_pipeline.layout, vk::ShaderStageFlags::VERTEX, 0, mvp_bytes);
 
-            self.world.render(cmd, vk, state)?;
+            self.world.render(cmd, vk, state, &self.occlusion.visible_draws(frame))?;
+            self.world.render_fluids(cmd, vk, state, frame);
 
             vk.device.cmd_end_render_pass(cmd);
         }
 
+        // For the next frame's occlusion culling
+        self.occlusion.build_hiz(cmd, vk, camera.proj_view_matrix());
+
         Ok(())
     }
 
     pub fn render(&mut self, camera: &Camera, renderer: &mut RendererBase) -> anyhow::Result<()> {
+        self.world.set_player_chunk_pos((camera.pos() / 16.0).floor().as_ivec3());
+
         renderer.render(|renderer, commands, image_index| {
             self.render_inner(camera, renderer, commands, image_index)
         })
@@ -71,4 +112,20 @@ impl GameRenderer {
     pub fn handle_window_resize(&mut self, renderer: &RendererBase) {
         self.state.handle_window_resize(&renderer.vk);
     }
+
+    /// For hot reloading. Whatever fails to load keeps using the old shaders.
+    #[cold]
+    pub fn reload_shaders(&mut self, renderer: &RendererBase, assets: &Assets) -> anyhow::Result<()> {
+        self.state.reload_shaders(&renderer.vk, assets)?;
+        self.occlusion.reload_shaders(&renderer.vk, assets)
+    }
+
+    /// For hot reloading, takes the same textures as `new()`, with new pixels.
+    #[cold]
+    pub fn reload_textures(&mut self, renderer: &mut RendererBase, texture_size: u32, texture_pixels: &[u8]) -> anyhow::Result<()> {
+        let vk = &mut renderer.vk;
+        unsafe { vk.device.device_wait_idle()? };
+        let textures = BlockTextures::new(vk, &self.state, texture_size, texture_pixels)?;
+        std::mem::replace(&mut self.textures, textures).destroy(vk)
+    }
 }
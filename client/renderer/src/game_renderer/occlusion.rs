@@ -0,0 +1,564 @@
+// GPU occlusion culling. After the main pass, the depth buffer is reduced into a Hi-Z
+// pyramid, where every texel holds the furthest depth of the area it covers. Before the
+// next frame's main pass, a compute shader tests the bounds of every candidate chunk
+// draw against it, and a second one copies the draws that might be visible into the
+// buffer that actually gets drawn, compacted.
+//
+// The pyramid is a frame old by the time it's used, so the test projects the chunks
+// with that frame's matrices. Something that just came out from behind a wall can
+// therefore pop in a frame late. Anything that was off screen last frame counts as
+// visible, which takes care of turning around.
+//
+// No GPU needed to check that it does anything: run the client on lavapipe
+// (VK_ICD_FILENAMES=/usr/share/vulkan/icd.d/lvp_icd.x86_64.json), and watch
+// `GameRenderer::draw_stats()` while looking at a hill with caves under it.
+
+use ash::vk;
+use bytemuck::{Pod, Zeroable};
+use common::assets::{shaders, Assets};
+use glam::Mat4;
+use gpu_allocator::MemoryLocation;
+
+use crate::{
+    vulkan::{
+        self,
+        util::{make_image_view, make_shader_module, make_shader_stage_create_info, GpuBuffer, GpuImage},
+        Vk,
+    },
+    FRAME_OVERLAP,
+};
+
+use super::{
+    state::{DescriptorSet, Pipeline, State},
+    world::{IndirectDraws, MAX_DRAWS},
+};
+
+const CULL_GROUP_SIZE: u32 = 64;
+// Per frame in flight, enough for MAX_DRAWS
+const MAX_CULL_GROUPS: usize = MAX_DRAWS / CULL_GROUP_SIZE as usize;
+const DOWNSAMPLE_GROUP_SIZE: u32 = 8;
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct CullParams {
+    proj_view: [f32; 16],
+    depth_size: [i32; 2],
+    first_draw: u32,
+    draw_count: u32,
+    hiz_levels: u32,
+    frame: u32,
+    first_group: u32,
+}
+
+/// How many chunk draws were left after each culling step.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DrawStats {
+    /// After frustum and backface culling on the CPU
+    pub candidates: u32,
+    /// After occlusion culling
+    pub visible: u32,
+}
+
+pub struct OcclusionCuller {
+    hiz: GpuImage,
+    // One view per level, for writing to that level and reading from it for the next
+    hiz_level_views: Vec<vk::ImageView>,
+    depth_size: vk::Extent2D,
+
+    downsample_pipeline: Pipeline,
+    // Level i reads level i - 1 (or the depth buffer) and writes level i
+    downsample_sets: Vec<DescriptorSet>,
+
+    // Both passes use the same set
+    cull_pipeline: Pipeline,
+    compact_pipeline: Pipeline,
+    cull_set: DescriptorSet,
+
+    // FRAME_OVERLAP blocks of MAX_DRAWS, same as the candidates
+    visible_draws: GpuBuffer,
+    // A u32 per frame in flight. Host-visible, so that the stats can be read back
+    draw_counts: GpuBuffer,
+
+    // Of the frame the pyramid was built from. None until there is a pyramid
+    hiz_proj_view: Option<Mat4>,
+    hiz_initialized: bool,
+
+    candidate_counts: [u32; FRAME_OVERLAP],
+    stats: DrawStats,
+}
+
+impl OcclusionCuller {
+    /// Has to be created before the `RenderWorld`, which takes all the memory that's left.
+    /// The culling can't be used before `set_draw_source()` has been called.
+    pub fn new(vk: &mut Vk, state: &State, assets: &Assets) -> anyhow::Result<Self> {
+        let depth_size = state.depth_image.extent;
+        let hiz_extent = vk::Extent2D {
+            width: (depth_size.width / 2).max(1),
+            height: (depth_size.height / 2).max(1),
+        };
+        let hiz_levels = 32 - hiz_extent.width.max(hiz_extent.height).leading_zeros();
+
+        let hiz = vulkan::util::allocate_image_and_bind(
+            "Hi-Z pyramid",
+            &vk.device,
+            &mut vk.allocator,
+            vk::Format::R32_SFLOAT,
+            hiz_extent,
+            hiz_levels,
+            vk::ImageUsageFlags::STORAGE | vk::ImageUsageFlags::SAMPLED,
+        )?;
+        let hiz_level_views = (0..hiz_levels)
+            .map(|level| {
+                make_image_view(&vk.device, hiz.handle, vk::Format::R32_SFLOAT, vk::ImageAspectFlags::COLOR, level, 1)
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let visible_draws = vulkan::util::allocate_buffer_and_bind(
+            "Visible draw buffer",
+            &vk.device,
+            &mut vk.allocator,
+            (FRAME_OVERLAP * MAX_DRAWS * std::mem::size_of::<vk::DrawIndexedIndirectCommand>()) as u32,
+            vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::INDIRECT_BUFFER,
+            MemoryLocation::GpuOnly,
+        )?;
+        let draw_counts = vulkan::util::allocate_buffer_and_bind(
+            "Draw count buffer",
+            &vk.device,
+            &mut vk.allocator,
+            (FRAME_OVERLAP * std::mem::size_of::<u32>()) as u32,
+            vk::BufferUsageFlags::STORAGE_BUFFER
+                | vk::BufferUsageFlags::INDIRECT_BUFFER
+                | vk::BufferUsageFlags::TRANSFER_DST,
+            MemoryLocation::CpuToGpu,
+        )?;
+        // What the first pass leaves for the second: a u32 per candidate, laid out like
+        // the candidates, and how many are visible per workgroup, FRAME_OVERLAP blocks
+        // of MAX_CULL_GROUPS. Only the shaders need them after this
+        let visibility = vulkan::util::allocate_buffer_and_bind(
+            "Draw visibility buffer",
+            &vk.device,
+            &mut vk.allocator,
+            (FRAME_OVERLAP * MAX_DRAWS * std::mem::size_of::<u32>()) as u32,
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+            MemoryLocation::GpuOnly,
+        )?;
+        let group_counts = vulkan::util::allocate_buffer_and_bind(
+            "Cull group count buffer",
+            &vk.device,
+            &mut vk.allocator,
+            (FRAME_OVERLAP * MAX_CULL_GROUPS * std::mem::size_of::<u32>()) as u32,
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+            MemoryLocation::GpuOnly,
+        )?;
+
+        let pool = unsafe {
+            vk.device.create_descriptor_pool(
+                &vk::DescriptorPoolCreateInfo::builder()
+                    .pool_sizes(&[
+                        vk::DescriptorPoolSize::builder().descriptor_count(hiz_levels + 1).ty(vk::DescriptorType::SAMPLED_IMAGE).build(),
+                        vk::DescriptorPoolSize::builder().descriptor_count(hiz_levels).ty(vk::DescriptorType::STORAGE_IMAGE).build(),
+                        vk::DescriptorPoolSize::builder().descriptor_count(6).ty(vk::DescriptorType::STORAGE_BUFFER).build(),
+                    ])
+                    .max_sets(hiz_levels + 1),
+                None,
+            )?
+        };
+
+        // The pyramid is only ever fetched from, so no samplers
+        let downsample_layout = create_set_layout(vk, &[
+            vk::DescriptorType::SAMPLED_IMAGE,
+            vk::DescriptorType::STORAGE_IMAGE,
+        ])?;
+        let cull_layout = create_set_layout(vk, &[
+            vk::DescriptorType::STORAGE_BUFFER,
+            vk::DescriptorType::STORAGE_BUFFER,
+            vk::DescriptorType::STORAGE_BUFFER,
+            vk::DescriptorType::STORAGE_BUFFER,
+            vk::DescriptorType::SAMPLED_IMAGE,
+            vk::DescriptorType::STORAGE_BUFFER,
+            vk::DescriptorType::STORAGE_BUFFER,
+        ])?;
+
+        let downsample_sets = unsafe {
+            vk.device.allocate_descriptor_sets(
+                &vk::DescriptorSetAllocateInfo::builder()
+                    .descriptor_pool(pool)
+                    .set_layouts(&vec![downsample_layout; hiz_levels as usize]),
+            )?
+        };
+        let cull_set = unsafe {
+            vk.device.allocate_descriptor_sets(
+                &vk::DescriptorSetAllocateInfo::builder()
+                    .descriptor_pool(pool)
+                    .set_layouts(&[cull_layout]),
+            )?[0]
+        };
+
+        for (level, &set) in downsample_sets.iter().enumerate() {
+            let (src_view, src_layout) = match level {
+                0 => (state.depth_image.view, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL),
+                _ => (hiz_level_views[level - 1], vk::ImageLayout::GENERAL),
+            };
+            unsafe {
+                vk.device.update_descriptor_sets(
+                    &[
+                        vk::WriteDescriptorSet::builder()
+                            .dst_set(set)
+                            .dst_binding(0)
+                            .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
+                            .image_info(&[vk::DescriptorImageInfo::builder()
+                                .image_view(src_view)
+                                .image_layout(src_layout)
+                                .build()])
+                            .build(),
+                        vk::WriteDescriptorSet::builder()
+                            .dst_set(set)
+                            .dst_binding(1)
+                            .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+                            .image_info(&[vk::DescriptorImageInfo::builder()
+                                .image_view(hiz_level_views[level])
+                                .image_layout(vk::ImageLayout::GENERAL)
+                                .build()])
+                            .build(),
+                    ],
+                    &[],
+                );
+            }
+        }
+
+        unsafe {
+            vk.device.update_descriptor_sets(
+                &[
+                    buffer_write(cull_set, 2, &whole_buffer(&visible_draws)),
+                    buffer_write(cull_set, 3, &whole_buffer(&draw_counts)),
+                    vk::WriteDescriptorSet::builder()
+                        .dst_set(cull_set)
+                        .dst_binding(4)
+                        .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
+                        .image_info(&[vk::DescriptorImageInfo::builder()
+                            .image_view(hiz.view)
+                            .image_layout(vk::ImageLayout::GENERAL)
+                            .build()])
+                        .build(),
+                    buffer_write(cull_set, 5, &whole_buffer(&visibility)),
+                    buffer_write(cull_set, 6, &whole_buffer(&group_counts)),
+                ],
+                &[],
+            );
+        }
+
+        let (downsample_pipeline, cull_pipeline, compact_pipeline) =
+            create_pipelines(vk, assets, downsample_layout, cull_layout)?;
+
+        Ok(Self {
+            hiz,
+            hiz_level_views,
+            depth_size,
+            downsample_pipeline,
+            downsample_sets: downsample_sets
+                .into_iter()
+                .map(|handle| DescriptorSet { handle, layout: downsample_layout })
+                .collect(),
+            cull_pipeline,
+            compact_pipeline,
+            cull_set: DescriptorSet { handle: cull_set, layout: cull_layout },
+            visible_draws,
+            draw_counts,
+            hiz_proj_view: None,
+            hiz_initialized: false,
+            candidate_counts: [0; FRAME_OVERLAP],
+            stats: DrawStats::default(),
+        })
+    }
+
+    /// Recreates the pipelines with the shaders as they are now. The old ones are kept
+    /// if that fails.
+    pub fn reload_shaders(&mut self, vk: &Vk, assets: &Assets) -> anyhow::Result<()> {
+        let (downsample, cull, compact) = create_pipelines(vk, assets, self.downsample_sets[0].layout, self.cull_set.layout)?;
+        unsafe {
+            vk.device.device_wait_idle()?;
+            for old in [&self.downsample_pipeline, &self.cull_pipeline, &self.compact_pipeline] {
+                vk.device.destroy_pipeline(old.handle, None);
+                vk.device.destroy_pipeline_layout(old.layout, None);
+            }
+        }
+        self.downsample_pipeline = downsample;
+        self.cull_pipeline = cull;
+        self.compact_pipeline = compact;
+        Ok(())
+    }
+
+    /// The candidate draws and their chunk origins, see `RenderWorld::draw_buffers()`.
+    pub fn set_draw_source(&mut self, vk: &Vk, (draws, origins): (&GpuBuffer, &GpuBuffer)) {
+        unsafe {
+            vk.device.update_descriptor_sets(
+                &[
+                    buffer_write(self.cull_set.handle, 0, &whole_buffer(draws)),
+                    buffer_write(self.cull_set.handle, 1, &whole_buffer(origins)),
+                ],
+                &[],
+            );
+        }
+    }
+
+    /// As of the last frame that used the same per-frame resources as the current one,
+    /// i.e. a couple of frames behind.
+    pub fn stats(&self) -> DrawStats {
+        self.stats
+    }
+
+    /// Records the culling of the `draw_count` candidates of this frame. Has to be
+    /// outside of a render pass.
+    pub fn cull(&mut self, cmd: vk::CommandBuffer, vk: &Vk, frame: usize, draw_count: u32) {
+        // The GPU is done with the last frame that used this slot, so its count is final
+        let counts = self.draw_counts.allocation.mapped_slice().unwrap();
+        let counts: &[u32] = bytemuck::cast_slice(&counts[..FRAME_OVERLAP * 4]);
+        self.stats = DrawStats { candidates: self.candidate_counts[frame], visible: counts[frame] };
+        self.candidate_counts[frame] = draw_count;
+
+        let params = CullParams {
+            proj_view: self.hiz_proj_view.unwrap_or(Mat4::IDENTITY).to_cols_array(),
+            depth_size: [self.depth_size.width as i32, self.depth_size.height as i32],
+            first_draw: (frame * MAX_DRAWS) as u32,
+            draw_count,
+            hiz_levels: if self.hiz_proj_view.is_some() { self.hiz_level_views.len() as u32 } else { 0 },
+            frame: frame as u32,
+            first_group: (frame * MAX_CULL_GROUPS) as u32,
+        };
+
+        unsafe {
+            if !self.hiz_initialized {
+                self.hiz_initialized = true;
+                vk.device.cmd_pipeline_barrier(
+                    cmd,
+                    vk::PipelineStageFlags::TOP_OF_PIPE,
+                    vk::PipelineStageFlags::COMPUTE_SHADER,
+                    vk::DependencyFlags::empty(),
+                    &[],
+                    &[],
+                    &[vk::ImageMemoryBarrier::builder()
+                        .image(self.hiz.handle)
+                        .old_layout(vk::ImageLayout::UNDEFINED)
+                        .new_layout(vk::ImageLayout::GENERAL)
+                        .src_access_mask(vk::AccessFlags::empty())
+                        .dst_access_mask(vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE)
+                        .subresource_range(vk::ImageSubresourceRange {
+                            aspect_mask: vk::ImageAspectFlags::COLOR,
+                            base_mip_level: 0,
+                            level_count: self.hiz.mip_levels,
+                            base_array_layer: 0,
+                            layer_count: 1,
+                        })
+                        .build()],
+                );
+            }
+
+            vk.device.cmd_fill_buffer(cmd, self.draw_counts.handle, (frame * 4) as u64, 4, 0);
+            // The reset count, and the pyramid built at the end of the last frame
+            global_barrier(
+                vk,
+                cmd,
+                (vk::PipelineStageFlags::TRANSFER | vk::PipelineStageFlags::COMPUTE_SHADER, vk::AccessFlags::TRANSFER_WRITE | vk::AccessFlags::SHADER_WRITE),
+                (vk::PipelineStageFlags::COMPUTE_SHADER, vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE),
+            );
+
+            if draw_count > 0 {
+                let groups = draw_count.div_ceil(CULL_GROUP_SIZE);
+                for (pass, pipeline) in [&self.cull_pipeline, &self.compact_pipeline].into_iter().enumerate() {
+                    if pass > 0 {
+                        // The visibility and the group counts
+                        global_barrier(
+                            vk,
+                            cmd,
+                            (vk::PipelineStageFlags::COMPUTE_SHADER, vk::AccessFlags::SHADER_WRITE),
+                            (vk::PipelineStageFlags::COMPUTE_SHADER, vk::AccessFlags::SHADER_READ),
+                        );
+                    }
+                    vk.device.cmd_bind_pipeline(cmd, vk::PipelineBindPoint::COMPUTE, pipeline.handle);
+                    vk.device.cmd_bind_descriptor_sets(
+                        cmd,
+                        vk::PipelineBindPoint::COMPUTE,
+                        pipeline.layout,
+                        0,
+                        &[self.cull_set.handle],
+                        &[],
+                    );
+                    vk.device.cmd_push_constants(
+                        cmd,
+                        pipeline.layout,
+                        vk::ShaderStageFlags::COMPUTE,
+                        0,
+                        bytemuck::bytes_of(&params),
+                    );
+                    vk.device.cmd_dispatch(cmd, groups, 1, 1);
+                }
+            }
+
+            global_barrier(
+                vk,
+                cmd,
+                (vk::PipelineStageFlags::TRANSFER | vk::PipelineStageFlags::COMPUTE_SHADER, vk::AccessFlags::TRANSFER_WRITE | vk::AccessFlags::SHADER_WRITE),
+                (vk::PipelineStageFlags::DRAW_INDIRECT | vk::PipelineStageFlags::HOST, vk::AccessFlags::INDIRECT_COMMAND_READ | vk::AccessFlags::HOST_READ),
+            );
+        }
+    }
+
+    /// What `cull()` left over, for `RenderWorld::render()`.
+    pub fn visible_draws(&self, frame: usize) -> IndirectDraws {
+        IndirectDraws {
+            buffer: self.visible_draws.handle,
+            offset: (frame * MAX_DRAWS * std::mem::size_of::<vk::DrawIndexedIndirectCommand>()) as u64,
+            count_buffer: self.draw_counts.handle,
+            count_offset: (frame * 4) as u64,
+        }
+    }
+
+    /// Records the building of the pyramid from the depth buffer. Has to be after the main
+    /// pass, outside of a render pass. `proj_view`: what the depth buffer was drawn with.
+    pub fn build_hiz(&mut self, cmd: vk::CommandBuffer, vk: &Vk, proj_view: Mat4) {
+        unsafe {
+            vk.device.cmd_bind_pipeline(cmd, vk::PipelineBindPoint::COMPUTE, self.downsample_pipeline.handle);
+            for (level, set) in self.downsample_sets.iter().enumerate() {
+                let width = (self.hiz.extent.width >> level).max(1);
+                let height = (self.hiz.extent.height >> level).max(1);
+
+                vk.device.cmd_bind_descriptor_sets(
+                    cmd,
+                    vk::PipelineBindPoint::COMPUTE,
+                    self.downsample_pipeline.layout,
+                    0,
+                    &[set.handle],
+                    &[],
+                );
+                vk.device.cmd_dispatch(
+                    cmd,
+                    width.div_ceil(DOWNSAMPLE_GROUP_SIZE),
+                    height.div_ceil(DOWNSAMPLE_GROUP_SIZE),
+                    1,
+                );
+                // The next level reads this one
+                global_barrier(
+                    vk,
+                    cmd,
+                    (vk::PipelineStageFlags::COMPUTE_SHADER, vk::AccessFlags::SHADER_WRITE),
+                    (vk::PipelineStageFlags::COMPUTE_SHADER, vk::AccessFlags::SHADER_READ),
+                );
+            }
+        }
+        self.hiz_proj_view = Some(proj_view);
+    }
+}
+
+fn create_set_layout(vk: &Vk, bindings: &[vk::DescriptorType]) -> anyhow::Result<vk::DescriptorSetLayout> {
+    let bindings: Vec<_> = bindings
+        .iter()
+        .enumerate()
+        .map(|(i, &ty)| {
+            vk::DescriptorSetLayoutBinding::builder()
+                .binding(i as u32)
+                .stage_flags(vk::ShaderStageFlags::COMPUTE)
+                .descriptor_count(1)
+                .descriptor_type(ty)
+                .build()
+        })
+        .collect();
+    let layout = unsafe {
+        vk.device.create_descriptor_set_layout(&vk::DescriptorSetLayoutCreateInfo::builder().bindings(&bindings), None)?
+    };
+    Ok(layout)
+}
+
+// The downsampling pipeline, then the two culling passes
+fn create_pipelines(
+    vk: &Vk,
+    assets: &Assets,
+    downsample_layout: vk::DescriptorSetLayout,
+    cull_layout: vk::DescriptorSetLayout,
+) -> anyhow::Result<(Pipeline, Pipeline, Pipeline)> {
+    let downsample = create_compute_pipeline(vk, &assets.shader(shaders::HIZ_DOWNSAMPLE_COMP)?, downsample_layout, 0)?;
+    let cull = create_compute_pipeline(
+        vk,
+        &assets.shader(shaders::OCCLUSION_CULL_COMP)?,
+        cull_layout,
+        std::mem::size_of::<CullParams>() as u32,
+    )?;
+    let compact = create_compute_pipeline(
+        vk,
+        &assets.shader(shaders::OCCLUSION_COMPACT_COMP)?,
+        cull_layout,
+        std::mem::size_of::<CullParams>() as u32,
+    )?;
+    Ok((downsample, cull, compact))
+}
+
+fn create_compute_pipeline(
+    vk: &Vk,
+    code: &[u8],
+    set_layout: vk::DescriptorSetLayout,
+    push_constant_size: u32,
+) -> anyhow::Result<Pipeline> {
+    unsafe {
+        let push_constant_ranges = [vk::PushConstantRange::builder()
+            .stage_flags(vk::ShaderStageFlags::COMPUTE)
+            .offset(0)
+            .size(push_constant_size)
+            .build()];
+        let layout = vk.device.create_pipeline_layout(
+            &vk::PipelineLayoutCreateInfo::builder()
+                .set_layouts(&[set_layout])
+                .push_constant_ranges(if push_constant_size > 0 { &push_constant_ranges } else { &[] }),
+            None,
+        )?;
+
+        let shader = make_shader_module(code, vk)?;
+        let handle = vk
+            .device
+            .create_compute_pipelines(
+                vk::PipelineCache::null(),
+                &[vk::ComputePipelineCreateInfo::builder()
+                    .stage(make_shader_stage_create_info(shader, vk::ShaderStageFlags::COMPUTE))
+                    .layout(layout)
+                    .build()],
+                None,
+            )
+            .map_err(|(_, e)| e)?[0];
+        vk.device.destroy_shader_module(shader, None);
+
+        Ok(Pipeline { handle, layout })
+    }
+}
+
+// The write points to `info`, so that has to outlive it
+fn buffer_write(set: vk::DescriptorSet, binding: u32, info: &[vk::DescriptorBufferInfo; 1]) -> vk::WriteDescriptorSet {
+    vk::WriteDescriptorSet::builder()
+        .dst_set(set)
+        .dst_binding(binding)
+        .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+        .buffer_info(info)
+        .build()
+}
+
+fn whole_buffer(buffer: &GpuBuffer) -> [vk::DescriptorBufferInfo; 1] {
+    [vk::DescriptorBufferInfo::builder()
+        .buffer(buffer.handle)
+        .offset(0)
+        .range(buffer.size as u64)
+        .build()]
+}
+
+unsafe fn global_barrier(
+    vk: &Vk,
+    cmd: vk::CommandBuffer,
+    (src_stage, src_access): (vk::PipelineStageFlags, vk::AccessFlags),
+    (dst_stage, dst_access): (vk::PipelineStageFlags, vk::AccessFlags),
+) {
+    vk.device.cmd_pipeline_barrier(
+        cmd,
+        src_stage,
+        dst_stage,
+        vk::DependencyFlags::empty(),
+        &[vk::MemoryBarrier::builder().src_access_mask(src_access).dst_access_mask(dst_access).build()],
+        &[],
+        &[],
+    );
+}
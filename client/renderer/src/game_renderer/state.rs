@@ -1,9 +1,9 @@
 use ash::vk;
 
 use anyhow::Result;
-use common::assets;
+use common::assets::{shaders, Assets};
 use log::debug;
-use crate::{vulkan::{Vk, util::{make_shader_module, make_shader_stage_create_info, render_pass}}};
+use crate::{vulkan::{Vk, util::{self, GpuImage, make_shader_module, make_shader_stage_create_info, render_pass}}};
 
 pub struct Pipeline {
     pub handle: vk::Pipeline,
@@ -25,9 +25,12 @@ pub struct State {
     // Render passes
     pub main_render_pass: vk::RenderPass,
     pub main_pass_framebuffers: Vec<vk::Framebuffer>,
+    // Reverse-Z, so cleared to 0. Kept around after the pass for occlusion culling
+    pub depth_image: GpuImage,
 
     // Pipelines
     pub full_block_pipeline: Pipeline,
+    pub fluid_pipeline: Pipeline,
 
     // Descriptor sets
     pub descriptors: DescriptorSets,
@@ -37,16 +40,47 @@ impl State {
     pub fn handle_window_resize(&mut self, vk: &Vk) {
         // TODO
     }
+
+    /// Recreates the pipelines with the shaders as they are now. The old ones are kept
+    /// if that fails.
+    pub fn reload_shaders(&mut self, vk: &Vk, assets: &Assets) -> Result<()> {
+        let layout = self.full_block_pipeline.layout;
+        let (full_block, fluid) = unsafe { create_block_pipelines(vk, self.main_render_pass, layout, assets)? };
+        unsafe {
+            vk.device.device_wait_idle()?;
+            vk.device.destroy_pipeline(self.full_block_pipeline.handle, None);
+            vk.device.destroy_pipeline(self.fluid_pipeline.handle, None);
+        }
+        self.full_block_pipeline = Pipeline { handle: full_block, layout };
+        self.fluid_pipeline = Pipeline { handle: fluid, layout };
+        Ok(())
+    }
 }
 
-pub fn init(vk: &mut Vk) -> anyhow::Result<State> {
+pub fn init(vk: &mut Vk, assets: &Assets) -> anyhow::Result<State> {
     let wnd_extent = vk.swapchain.surface.extent;
 
+    let depth_image = util::allocate_image_and_bind(
+        "Depth buffer",
+        &vk.device,
+        &mut vk.allocator,
+        vk::Format::D32_SFLOAT,
+        wnd_extent,
+        1,
+        vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
+    )?;
+
     let (main_render_pass, main_pass_framebuffers) = unsafe {
         let pass = render_pass! {
             device: &vk.device,
             bind_point: vk::PipelineBindPoint::GRAPHICS,
             attachments: [
+                depth {
+                    initial_layout: vk::ImageLayout::UNDEFINED,
+                    // To be read by the Hi-Z pass
+                    final_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                    load_op: vk::AttachmentLoadOp::CLEAR,
+                },
                 color {
                     format: vk.swapchain.surface.format.format,
 
@@ -65,13 +99,29 @@ pub fn init(vk: &mut Vk) -> anyhow::Result<State> {
                     .dst_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
                     .src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
                     .dst_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+                // The previous frame's Hi-Z pass has to be done reading the depth
+                vk::SubpassDependency::builder()
+                    .src_subpass(vk::SUBPASS_EXTERNAL)
+                    .dst_subpass(0)
+                    .src_access_mask(vk::AccessFlags::empty())
+                    .dst_access_mask(vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE)
+                    .src_stage_mask(vk::PipelineStageFlags::COMPUTE_SHADER)
+                    .dst_stage_mask(vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS)
+                // And this frame's can't start reading before it's written
+                vk::SubpassDependency::builder()
+                    .src_subpass(0)
+                    .dst_subpass(vk::SUBPASS_EXTERNAL)
+                    .src_access_mask(vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE)
+                    .dst_access_mask(vk::AccessFlags::SHADER_READ)
+                    .src_stage_mask(vk::PipelineStageFlags::LATE_FRAGMENT_TESTS)
+                    .dst_stage_mask(vk::PipelineStageFlags::COMPUTE_SHADER)
             ]
         }?;
 
         let framebuffers = vk.swapchain.image_views.iter().map(|view| {
             vk.device.create_framebuffer(&vk::FramebufferCreateInfo::builder()
                 .render_pass(pass)
-                .attachments(&[*view])
+                .attachments(&[depth_image.view, *view])
                 .width(vk.swapchain.surface.extent.width)
                 .height(vk.swapchain.surface.extent.height)
                 .layers(1)
@@ -82,10 +132,8 @@ pub fn init(vk: &mut Vk) -> anyhow::Result<State> {
 
     let dsets = create_descriptor_sets(vk)?;
 
-    let full_block_pipeline = unsafe {
-        let vert_shader = make_shader_module(assets::shaders::TEXTURED_FULL_CUBE_VERT, vk)?;
-        let frag_shader = make_shader_module(assets::shaders::TEXTURED_LIT_FRAG, vk)?;
-
+    let (full_block_pipeline, fluid_pipeline) = unsafe {
+        // Fluids use the same faces and the same descriptors, so one layout does for both
         let layout = vk.device.create_pipeline_layout(&vk::PipelineLayoutCreateInfo::builder()
             .push_constant_ranges(&[vk::PushConstantRange::builder()
                 .stage_flags(vk::ShaderStageFlags::VERTEX)
@@ -97,87 +145,8 @@ pub fn init(vk: &mut Vk) -> anyhow::Result<State> {
             .flags(vk::PipelineLayoutCreateFlags::empty())
         , None)?;
 
-        let handle = vk.device.create_graphics_pipelines(vk::PipelineCache::null(), &[
-            vk::GraphicsPipelineCreateInfo::builder()
-            .render_pass(main_render_pass)
-            .layout(layout)
-            .stages(&[
-                make_shader_stage_create_info(vert_shader, vk::ShaderStageFlags::VERTEX),
-                make_shader_stage_create_info(frag_shader, vk::ShaderStageFlags::FRAGMENT)
-            ])
-            .depth_stencil_state(&vk::PipelineDepthStencilStateCreateInfo::builder()
-                .depth_test_enable(false)
-                .stencil_test_enable(false)
-            )
-            .input_assembly_state(&vk::PipelineInputAssemblyStateCreateInfo::builder()
-                .primitive_restart_enable(false)
-                .topology(vk::PrimitiveTopology::TRIANGLE_LIST)
-            )
-            .dynamic_state(&vk::PipelineDynamicStateCreateInfo::builder()
-                .dynamic_states(&[])
-            )
-            .rasterization_state(&vk::PipelineRasterizationStateCreateInfo::builder()
-                .cull_mode(vk::CullModeFlags::BACK)
-                .depth_bias_enable(false)
-                .depth_clamp_enable(false)
-                .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
-                .rasterizer_discard_enable(false)
-                .polygon_mode(vk::PolygonMode::FILL)
-                .line_width(1.0)
-            )
-            .viewport_state(&vk::PipelineViewportStateCreateInfo::builder()
-                .scissors(&[vk::Rect2D::builder()
-                    .offset(vk::Offset2D::builder().build())
-                    .extent(wnd_extent)
-                    .build()
-                ])
-                .viewport_count(1)
-                .viewports(&[vk::Viewport::builder()
-                    .x(0.0)
-                    .y(wnd_extent.height as f32)
-                    .width(wnd_extent.width as f32)
-                    .height(-(wnd_extent.height as f32))
-                    .min_depth(0.0)
-                    .max_depth(1.0)
-                    .build(),
-                ])
-            )
-            .vertex_input_state(&vk::PipelineVertexInputStateCreateInfo::builder()
-                .vertex_attribute_descriptions(&[])
-                .vertex_binding_descriptions(&[])
-                .flags(vk::PipelineVertexInputStateCreateFlags::empty())
-                .build()
-            )
-            .color_blend_state(&vk::PipelineColorBlendStateCreateInfo::builder()
-                .attachments(&[vk::PipelineColorBlendAttachmentState {
-                    blend_enable: 0,
-                    src_color_blend_factor: vk::BlendFactor::SRC_COLOR,
-                    dst_color_blend_factor: vk::BlendFactor::ONE_MINUS_DST_COLOR,
-                    color_blend_op: vk::BlendOp::ADD,
-                    src_alpha_blend_factor: vk::BlendFactor::ZERO,
-                    dst_alpha_blend_factor: vk::BlendFactor::ZERO,
-                    alpha_blend_op: vk::BlendOp::ADD,
-                    color_write_mask: vk::ColorComponentFlags::RGBA,
-                }])
-                .logic_op_enable(false)
-            )
-            .multisample_state(&vk::PipelineMultisampleStateCreateInfo::builder()
-                .sample_shading_enable(false)
-                .rasterization_samples(vk::SampleCountFlags::TYPE_1)
-            )
-            .base_pipeline_handle(vk::Pipeline::null())
-            .subpass(0)
-            .flags(vk::PipelineCreateFlags::empty())
-            .build()
-        ], None).unwrap()[0];
-
-        vk.device.destroy_shader_module(vert_shader, None);
-        vk.device.destroy_shader_module(frag_shader, None);
-
-        Pipeline {
-            handle,
-            layout,
-        }
+        let (full_block, fluid) = create_block_pipelines(vk, main_render_pass, layout, assets)?;
+        (Pipeline { handle: full_block, layout }, Pipeline { handle: fluid, layout })
     };
 
     debug!("State created!");
@@ -185,19 +154,148 @@ pub fn init(vk: &mut Vk) -> anyhow::Result<State> {
     Ok(State {
         main_render_pass,
         main_pass_framebuffers,
+        depth_image,
         
         full_block_pipeline,
+        fluid_pipeline,
         
         descriptors: dsets,
     })
 }
 
+// The solid and the fluid pipeline
+unsafe fn create_block_pipelines(
+    vk: &Vk,
+    render_pass: vk::RenderPass,
+    layout: vk::PipelineLayout,
+    assets: &Assets,
+) -> Result<(vk::Pipeline, vk::Pipeline)> {
+    let vert_shader = make_shader_module(&assets.shader(shaders::TEXTURED_FULL_CUBE_VERT)?, vk)?;
+    let frag_shader = make_shader_module(&assets.shader(shaders::TEXTURED_LIT_FRAG)?, vk)?;
+    let fluid_frag_shader = make_shader_module(&assets.shader(shaders::FLUID_FRAG)?, vk)?;
+
+    let full_block = create_block_pipeline(vk, render_pass, layout, vert_shader, frag_shader, false);
+    let fluid = create_block_pipeline(vk, render_pass, layout, vert_shader, fluid_frag_shader, true);
+
+    vk.device.destroy_shader_module(vert_shader, None);
+    vk.device.destroy_shader_module(frag_shader, None);
+    vk.device.destroy_shader_module(fluid_frag_shader, None);
+
+    Ok((full_block?, fluid?))
+}
+
+// Everything that draws chunk faces. Translucent pipelines blend with what's already
+// there and don't write depth, so that everything behind them still gets drawn, and
+// show their back faces too, for when the camera is inside (under water).
+unsafe fn create_block_pipeline(
+    vk: &Vk,
+    render_pass: vk::RenderPass,
+    layout: vk::PipelineLayout,
+    vert_shader: vk::ShaderModule,
+    frag_shader: vk::ShaderModule,
+    translucent: bool,
+) -> Result<vk::Pipeline> {
+    let wnd_extent = vk.swapchain.surface.extent;
+    let blend = if translucent {
+        vk::PipelineColorBlendAttachmentState {
+            blend_enable: 1,
+            src_color_blend_factor: vk::BlendFactor::SRC_ALPHA,
+            dst_color_blend_factor: vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+            color_blend_op: vk::BlendOp::ADD,
+            src_alpha_blend_factor: vk::BlendFactor::ONE,
+            dst_alpha_blend_factor: vk::BlendFactor::ZERO,
+            alpha_blend_op: vk::BlendOp::ADD,
+            color_write_mask: vk::ColorComponentFlags::RGBA,
+        }
+    } else {
+        vk::PipelineColorBlendAttachmentState {
+            blend_enable: 0,
+            src_color_blend_factor: vk::BlendFactor::SRC_COLOR,
+            dst_color_blend_factor: vk::BlendFactor::ONE_MINUS_DST_COLOR,
+            color_blend_op: vk::BlendOp::ADD,
+            src_alpha_blend_factor: vk::BlendFactor::ZERO,
+            dst_alpha_blend_factor: vk::BlendFactor::ZERO,
+            alpha_blend_op: vk::BlendOp::ADD,
+            color_write_mask: vk::ColorComponentFlags::RGBA,
+        }
+    };
+
+    Ok(vk.device.create_graphics_pipelines(vk::PipelineCache::null(), &[
+        vk::GraphicsPipelineCreateInfo::builder()
+        .render_pass(render_pass)
+        .layout(layout)
+        .stages(&[
+            make_shader_stage_create_info(vert_shader, vk::ShaderStageFlags::VERTEX),
+            make_shader_stage_create_info(frag_shader, vk::ShaderStageFlags::FRAGMENT)
+        ])
+        .depth_stencil_state(&vk::PipelineDepthStencilStateCreateInfo::builder()
+            .depth_test_enable(true)
+            .depth_write_enable(!translucent)
+            .depth_compare_op(vk::CompareOp::GREATER_OR_EQUAL)
+            .stencil_test_enable(false)
+        )
+        .input_assembly_state(&vk::PipelineInputAssemblyStateCreateInfo::builder()
+            .primitive_restart_enable(false)
+            .topology(vk::PrimitiveTopology::TRIANGLE_LIST)
+        )
+        .dynamic_state(&vk::PipelineDynamicStateCreateInfo::builder()
+            .dynamic_states(&[])
+        )
+        .rasterization_state(&vk::PipelineRasterizationStateCreateInfo::builder()
+            .cull_mode(if translucent { vk::CullModeFlags::NONE } else { vk::CullModeFlags::BACK })
+            .depth_bias_enable(false)
+            .depth_clamp_enable(false)
+            .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
+            .rasterizer_discard_enable(false)
+            .polygon_mode(vk::PolygonMode::FILL)
+            .line_width(1.0)
+        )
+        .viewport_state(&vk::PipelineViewportStateCreateInfo::builder()
+            .scissors(&[vk::Rect2D::builder()
+                .offset(vk::Offset2D::builder().build())
+                .extent(wnd_extent)
+                .build()
+            ])
+            .viewport_count(1)
+            .viewports(&[vk::Viewport::builder()
+                .x(0.0)
+                .y(wnd_extent.height as f32)
+                .width(wnd_extent.width as f32)
+                .height(-(wnd_extent.height as f32))
+                .min_depth(0.0)
+                .max_depth(1.0)
+                .build(),
+            ])
+        )
+        .vertex_input_state(&vk::PipelineVertexInputStateCreateInfo::builder()
+            .vertex_attribute_descriptions(&[])
+            .vertex_binding_descriptions(&[])
+            .flags(vk::PipelineVertexInputStateCreateFlags::empty())
+            .build()
+        )
+        .color_blend_state(&vk::PipelineColorBlendStateCreateInfo::builder()
+            .attachments(&[blend])
+            .logic_op_enable(false)
+        )
+        .multisample_state(&vk::PipelineMultisampleStateCreateInfo::builder()
+            .sample_shading_enable(false)
+            .rasterization_samples(vk::SampleCountFlags::TYPE_1)
+        )
+        .base_pipeline_handle(vk::Pipeline::null())
+        .subpass(0)
+        .flags(vk::PipelineCreateFlags::empty())
+        .build()
+    ], None).map_err(|(_, e)| e)?[0])
+}
+
 fn create_descriptor_sets(vk: &mut Vk) -> Result<DescriptorSets> {
     let pool = unsafe {vk.device.create_descriptor_pool(
         &vk::DescriptorPoolCreateInfo::builder()
             .pool_sizes(&[
-                vk::DescriptorPoolSize::builder().descriptor_count(1).ty(vk::DescriptorType::STORAGE_BUFFER).build(),
+                vk::DescriptorPoolSize::builder().descriptor_count(2).ty(vk::DescriptorType::STORAGE_BUFFER).build(),
                 vk::DescriptorPoolSize::builder().descriptor_count(1).ty(vk::DescriptorType::UNIFORM_BUFFER).build(),
+                vk::DescriptorPoolSize::builder().descriptor_count(1).ty(vk::DescriptorType::SAMPLED_IMAGE).build(),
+                vk::DescriptorPoolSize::builder().descriptor_count(1).ty(vk::DescriptorType::SAMPLER).build(),
             ])
             .max_sets(2)
         , None)?
@@ -210,7 +308,27 @@ fn create_descriptor_sets(vk: &mut Vk) -> Result<DescriptorSets> {
                     .stage_flags(vk::ShaderStageFlags::VERTEX)
                     .descriptor_count(1)
                     .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
-                    .build()
+                    .build(),
+                // Chunk origins, indexed by the draw
+                vk::DescriptorSetLayoutBinding::builder()
+                    .binding(1)
+                    .stage_flags(vk::ShaderStageFlags::VERTEX)
+                    .descriptor_count(1)
+                    .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                    .build(),
+                // Block textures, and their sampler
+                vk::DescriptorSetLayoutBinding::builder()
+                    .binding(2)
+                    .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+                    .descriptor_count(1)
+                    .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
+                    .build(),
+                vk::DescriptorSetLayoutBinding::builder()
+                    .binding(3)
+                    .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+                    .descriptor_count(1)
+                    .descriptor_type(vk::DescriptorType::SAMPLER)
+                    .build(),
             ])
         , None)? 
     };
@@ -0,0 +1,240 @@
+#![cfg(test)]
+
+use std::f32::consts::PI;
+
+use glam::{ivec2, ivec3, vec2, vec3, IVec2, IVec3, Mat4, Vec2, Vec3, Vec4Swizzles};
+
+use crate::camera::Camera;
+
+use super::culling::{chunk_in_frustum, visible_axis_groups};
+
+#[test]
+fn axis_groups_facing_away_are_culled() {
+    // Camera is on the +X, -Y, +Z side of the chunk at the origin
+    let visible = visible_axis_groups(IVec3::ZERO, vec3(40.0, -3.0, 20.0));
+    assert_eq!(visible, [true, false, false, true, true, false]);
+
+    // Inside the bounds on one axis: both of that axis' groups may face the camera
+    let visible = visible_axis_groups(IVec3::ZERO, vec3(8.0, 30.0, -5.0));
+    assert_eq!(visible, [true, true, true, false, false, true]);
+
+    // Inside the chunk: everything
+    assert_eq!(visible_axis_groups(ivec3(2, 3, 4), vec3(40.0, 60.0, 70.0)), [true; 6]);
+}
+
+#[test]
+fn about_half_of_the_faces_are_culled() {
+    // Chunks all around the camera, with the same number of faces in every group,
+    // as is roughly the case for terrain
+    let camera_pos = vec3(3.5, 70.2, -12.9);
+    let mut total = 0;
+    let mut drawn = 0;
+    for x in -12..=12 {
+        for y in 0..16 {
+            for z in -12..=12 {
+                let visible = visible_axis_groups(ivec3(x, y, z), camera_pos);
+                total += 6;
+                drawn += visible.iter().filter(|&&v| v).count();
+            }
+        }
+    }
+
+    let culled = 1.0 - drawn as f32 / total as f32;
+    assert!((0.4..=0.5).contains(&culled), "culled {culled}");
+}
+
+#[test]
+fn culling_never_drops_visible_faces() {
+    // Brute force: a face is visible if the camera is on its front side
+    let camera_pos = vec3(-21.3, 40.7, 5.1);
+    let normals = [Vec3::X, Vec3::NEG_X, Vec3::Y, Vec3::NEG_Y, Vec3::Z, Vec3::NEG_Z];
+    for chunk_pos in [ivec3(-2, 2, 0), ivec3(-1, 2, 0), ivec3(0, 3, 1), ivec3(-2, 1, -1)] {
+        let visible = visible_axis_groups(chunk_pos, camera_pos);
+        for (group, normal) in normals.into_iter().enumerate() {
+            for i in 0..16 * 16 * 16 {
+                let block = chunk_pos * 16 + ivec3(i / 256, (i / 16) % 16, i % 16);
+                let face_center = block.as_vec3() + 0.5 + normal * 0.5;
+                if (camera_pos - face_center).dot(normal) > 0.0 {
+                    assert!(visible[group], "{chunk_pos} group {group} culled, but block {block} faces the camera");
+                }
+            }
+        }
+    }
+}
+
+fn test_camera(pos: Vec3, yaw_rad: f32, pitch_rad: f32) -> Camera {
+    let mut camera = Camera::new(pos, (0.0, 0.0), 90f32.to_radians(), vec2(1280.0, 720.0));
+    camera.set_rotation(yaw_rad, pitch_rad);
+    camera.update();
+    camera
+}
+
+#[test]
+fn frustum_rejects_chunks_out_of_view() {
+    // Looking along +X from the middle of chunk (0, 4, 0)
+    let frustum = test_camera(vec3(8.0, 72.0, 8.0), 0.0, 0.0).frustum();
+
+    // Around the camera, and straight ahead, near and far
+    assert!(chunk_in_frustum(ivec3(0, 4, 0), &frustum));
+    assert!(chunk_in_frustum(ivec3(1, 4, 0), &frustum));
+    assert!(chunk_in_frustum(ivec3(30, 4, 0), &frustum));
+    assert!(chunk_in_frustum(ivec3(1000, 4, 0), &frustum));
+
+    // Behind
+    assert!(!chunk_in_frustum(ivec3(-2, 4, 0), &frustum));
+    assert!(!chunk_in_frustum(ivec3(-10, 4, 3), &frustum));
+    // Way off to the side (the horizontal FOV is about 120°), and way above and below
+    assert!(!chunk_in_frustum(ivec3(2, 4, 10), &frustum));
+    assert!(!chunk_in_frustum(ivec3(2, 4, -10), &frustum));
+    assert!(!chunk_in_frustum(ivec3(2, 12, 0), &frustum));
+    assert!(!chunk_in_frustum(ivec3(2, -4, 0), &frustum));
+
+    // Partially in view at the edge of the FOV
+    assert!(chunk_in_frustum(ivec3(4, 4, 6), &frustum));
+}
+
+#[test]
+fn frustum_follows_the_camera() {
+    // Looking straight down at the terrain below
+    let frustum = test_camera(vec3(-100.0, 120.0, 50.0), 1.0, -PI / 2.0).frustum();
+    let camera_chunk = ivec3(-7, 7, 3);
+
+    assert!(chunk_in_frustum(camera_chunk - IVec3::Y, &frustum));
+    assert!(chunk_in_frustum(camera_chunk - IVec3::Y * 4, &frustum));
+    assert!(!chunk_in_frustum(camera_chunk + IVec3::Y * 2, &frustum));
+    assert!(!chunk_in_frustum(camera_chunk + ivec3(8, 0, 0), &frustum));
+
+    // Of everything in the render distance, most is out of view
+    let in_view = (-12..=12)
+        .flat_map(|x| (0..16).flat_map(move |y| (-12..=12).map(move |z| ivec3(x, y, z))))
+        .filter(|&offset| chunk_in_frustum(ivec3(camera_chunk.x, 0, camera_chunk.z) + offset, &frustum))
+        .count();
+    assert!(in_view < 25 * 25 * 16 / 4, "{in_view} chunks in view");
+}
+
+// A copy of the pyramid hiz_downsample.comp builds from a depth buffer (row by row),
+// level sizes as in OcclusionCuller::new()
+fn build_hiz(depth: &[f32], depth_size: IVec2) -> Vec<(IVec2, Vec<f32>)> {
+    let mut size = (depth_size / 2).max(IVec2::ONE);
+    let level_count = 32 - size.x.max(size.y).leading_zeros();
+    let (mut src, mut src_size) = (depth.to_vec(), depth_size);
+    let mut levels = Vec::new();
+    for _ in 0..level_count {
+        let mut texels = Vec::new();
+        for y in 0..size.y {
+            for x in 0..size.x {
+                let from = ivec2(x, y) * 2;
+                let mut to = (from + 1).min(src_size - 1);
+                if x == size.x - 1 {
+                    to.x = src_size.x - 1;
+                }
+                if y == size.y - 1 {
+                    to.y = src_size.y - 1;
+                }
+                let mut depth = 1.0f32;
+                for sy in from.y..=to.y {
+                    for sx in from.x..=to.x {
+                        depth = depth.min(src[(sy * src_size.x + sx) as usize]);
+                    }
+                }
+                texels.push(depth);
+            }
+        }
+        levels.push((size, texels.clone()));
+        (src, src_size) = (texels, size);
+        size = (size / 2).max(IVec2::ONE);
+    }
+    levels
+}
+
+// A copy of the level selection in isOccluded(), occlusion_cull.comp
+fn hiz_level(p_min: IVec2, p_max: IVec2, level_count: i32) -> i32 {
+    let mut level = 0;
+    while level + 1 < level_count && ((p_max >> (level + 1)) - (p_min >> (level + 1))).cmpgt(IVec2::ONE).any() {
+        level += 1;
+    }
+    level
+}
+
+// A copy of isOccluded() in occlusion_cull.comp
+fn is_occluded(box_min: Vec3, box_max: Vec3, proj_view: Mat4, depth_size: IVec2, hiz: &[(IVec2, Vec<f32>)]) -> bool {
+    let mut uv_min = Vec2::ONE;
+    let mut uv_max = Vec2::ZERO;
+    let mut nearest = 0.0f32;
+    for i in 0..8 {
+        let corner = box_min + (box_max - box_min) * vec3((i & 1) as f32, ((i >> 1) & 1) as f32, ((i >> 2) & 1) as f32);
+        let clip = proj_view * corner.extend(1.0);
+        if clip.w < 0.1 {
+            return false;
+        }
+        let ndc = clip.xyz() / clip.w;
+        let uv = vec2(ndc.x, -ndc.y) * 0.5 + 0.5;
+        uv_min = uv_min.min(uv);
+        uv_max = uv_max.max(uv);
+        nearest = nearest.max(ndc.z);
+    }
+
+    if uv_min.cmplt(Vec2::ZERO).any() || uv_max.cmpgt(Vec2::ONE).any() {
+        return false;
+    }
+
+    let p_min = (uv_min * depth_size.as_vec2()).as_ivec2().min(depth_size - 1);
+    let p_max = (uv_max * depth_size.as_vec2()).as_ivec2().min(depth_size - 1);
+    let level = hiz_level(p_min, p_max, hiz.len() as i32);
+
+    let (size, texels) = &hiz[level as usize];
+    let t_min = (p_min >> (level + 1)).min(*size - 1);
+    let t_max = (p_max >> (level + 1)).min(*size - 1);
+    let fetch = |t: IVec2| texels[(t.y * size.x + t.x) as usize];
+    let furthest = fetch(t_min).min(fetch(ivec2(t_max.x, t_min.y))).min(fetch(ivec2(t_min.x, t_max.y))).min(fetch(t_max));
+    nearest < furthest
+}
+
+#[test]
+fn hiz_level_fits_the_box_in_2x2_texels() {
+    let level_count = 6;
+    for (p_min, p_max) in [(ivec2(0, 0), ivec2(1, 1)), (ivec2(5, 9), ivec2(6, 12)), (ivec2(3, 3), ivec2(17, 4)), (ivec2(0, 0), ivec2(63, 35))] {
+        let level = hiz_level(p_min, p_max, level_count);
+        let span = |level: i32| (p_max >> (level + 1)) - (p_min >> (level + 1));
+        // The smallest level that does, or the last one
+        assert!(span(level).cmple(IVec2::ONE).all() || level == level_count - 1, "{p_min} {p_max}: level {level}");
+        assert!(level == 0 || span(level - 1).cmpgt(IVec2::ONE).any(), "{p_min} {p_max}: level {level}");
+    }
+
+    assert_eq!(hiz_level(ivec2(10, 10), ivec2(11, 11), level_count), 0);
+    // 4 pixels wide, which could straddle 3 texels of level 0
+    assert_eq!(hiz_level(ivec2(1, 10), ivec2(4, 10), level_count), 1);
+    // The whole screen, 2x1 texels of level 4
+    assert_eq!(hiz_level(ivec2(0, 0), ivec2(63, 35), level_count), 4);
+}
+
+#[test]
+fn boxes_behind_the_depth_buffer_are_occluded() {
+    // Looking along +X, with a wall 20 blocks away covering the left 40 of 64 columns,
+    // and nothing on the right. Odd level sizes on the way down: 32x18, 16x9, 8x4, ...
+    let camera = test_camera(vec3(8.0, 72.0, 8.0), 0.0, 0.0);
+    let proj_view = camera.proj_view_matrix();
+    let depth_size = ivec2(64, 36);
+    let wall = proj_view.project_point3(vec3(28.0, 72.0, 8.0)).z;
+    let depth: Vec<f32> = (0..depth_size.x * depth_size.y)
+        .map(|i| if i % depth_size.x < 40 { wall } else { 0.0 })
+        .collect();
+    let hiz = build_hiz(&depth, depth_size);
+    assert_eq!(hiz.iter().map(|(size, _)| *size).collect::<Vec<_>>(), [
+        ivec2(32, 18), ivec2(16, 9), ivec2(8, 4), ivec2(4, 2), ivec2(2, 1), ivec2(1, 1),
+    ]);
+    assert_eq!(hiz[5].1, [0.0]);
+
+    // Same size and distance, one on each side of the screen
+    let (left, right) = if proj_view.project_point3(vec3(40.0, 72.0, -16.0)).x < 0.0 { (-1.0, 1.0) } else { (1.0, -1.0) };
+    let behind_the_wall = vec3(40.0, 68.0, 8.0 + left * 20.0);
+    assert!(is_occluded(behind_the_wall, behind_the_wall + 8.0, proj_view, depth_size, &hiz));
+    let out_in_the_open = vec3(40.0, 68.0, 8.0 + right * 28.0);
+    assert!(!is_occluded(out_in_the_open, out_in_the_open + 8.0, proj_view, depth_size, &hiz));
+
+    // In front of the wall
+    let in_front = vec3(18.0, 70.0, 8.0 + left * 6.0);
+    assert!(!is_occluded(in_front, in_front + 2.0, proj_view, depth_size, &hiz));
+    // Around the camera, reaching past the near plane
+    assert!(!is_occluded(vec3(0.0, 64.0, 0.0), vec3(16.0, 80.0, 16.0), proj_view, depth_size, &hiz));
+}
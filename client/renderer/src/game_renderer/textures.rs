@@ -0,0 +1,97 @@
+use anyhow::{ensure, Result};
+use ash::vk;
+
+use crate::vulkan::{self, util::GpuImage, Vk};
+
+use super::state::State;
+
+/// Faces can only refer to this many layers, see the texture bits in `FaceData`.
+pub const MAX_BLOCK_TEXTURES: u32 = 1 << 10;
+
+// All block textures as the layers of a single array texture, sampled by the chunk
+// pipelines. That way a face only needs a layer index, and a greedy meshed face
+// can repeat its texture over every block it covers.
+pub struct BlockTextures {
+    image: GpuImage,
+    sampler: vk::Sampler,
+}
+
+impl BlockTextures {
+    /// `pixels` is every layer, one after the other, as `size` x `size` RGBA8 (sRGB).
+    /// The size has to be a power of two, for the mip chain to go all the way down.
+    pub(crate) fn new(vk: &mut Vk, state: &State, size: u32, pixels: &[u8]) -> Result<Self> {
+        let layer_bytes = (size * size * 4) as usize;
+        ensure!(size.is_power_of_two(), "Block textures are {size}x{size}, which isn't a power of two");
+        ensure!(!pixels.is_empty() && pixels.len() % layer_bytes == 0, "Block textures are {} bytes, which isn't a whole number of {size}x{size} layers", pixels.len());
+        let layers = (pixels.len() / layer_bytes) as u32;
+        ensure!(layers <= MAX_BLOCK_TEXTURES, "{layers} block textures, at most {MAX_BLOCK_TEXTURES} are supported");
+
+        let image = vulkan::util::allocate_image_array_and_bind(
+            "Block textures",
+            &vk.device,
+            &mut vk.allocator,
+            vk::Format::R8G8B8A8_SRGB,
+            vk::Extent2D { width: size, height: size },
+            size.ilog2() + 1,
+            layers,
+            vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_SRC | vk::ImageUsageFlags::TRANSFER_DST,
+        )?;
+        vk.uploader.upload_to_image(pixels, &image, vk::PipelineStageFlags::FRAGMENT_SHADER)?;
+
+        // Crisp up close, mipmapped in the distance. Repeats, for greedy meshed faces
+        let sampler = unsafe {
+            vk.device.create_sampler(
+                &vk::SamplerCreateInfo::builder()
+                    .mag_filter(vk::Filter::NEAREST)
+                    .min_filter(vk::Filter::NEAREST)
+                    .mipmap_mode(vk::SamplerMipmapMode::LINEAR)
+                    .address_mode_u(vk::SamplerAddressMode::REPEAT)
+                    .address_mode_v(vk::SamplerAddressMode::REPEAT)
+                    .address_mode_w(vk::SamplerAddressMode::REPEAT)
+                    .max_lod(vk::LOD_CLAMP_NONE),
+                None,
+            )?
+        };
+
+        unsafe {
+            vk.device.update_descriptor_sets(
+                &[
+                    vk::WriteDescriptorSet::builder()
+                        .dst_set(state.descriptors.full_block.handle)
+                        .dst_binding(2)
+                        .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
+                        .image_info(&[vk::DescriptorImageInfo::builder()
+                            .image_view(image.view)
+                            .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+                            .build()])
+                        .build(),
+                    vk::WriteDescriptorSet::builder()
+                        .dst_set(state.descriptors.full_block.handle)
+                        .dst_binding(3)
+                        .descriptor_type(vk::DescriptorType::SAMPLER)
+                        .image_info(&[vk::DescriptorImageInfo::builder().sampler(sampler).build()])
+                        .build(),
+                ],
+                &[],
+            );
+        }
+
+        Ok(Self { image, sampler })
+    }
+
+    /// The GPU can't be using them anymore.
+    pub(crate) fn destroy(self, vk: &mut Vk) -> Result<()> {
+        unsafe {
+            vk.device.destroy_sampler(self.sampler, None);
+            vk.device.destroy_image_view(self.image.view, None);
+            vk.device.destroy_image(self.image.handle, None);
+        }
+        vk.allocator.free(self.image.allocation)?;
+        Ok(())
+    }
+
+    /// How many textures there are.
+    pub fn layer_count(&self) -> u32 {
+        self.image.layers
+    }
+}
@@ -1,47 +1,24 @@
-use std::num::NonZeroU32;
+use std::{borrow::Cow, num::NonZeroU32};
 
 use ash::vk::{self, BufferUsageFlags, MemoryHeapFlags};
 use bytemuck::{Pod, Zeroable};
-use glam::{uvec3, IVec3, UVec3};
+use glam::{IVec3, IVec4, UVec3, Vec3Swizzles};
 use gpu_allocator::MemoryLocation;
-use log::debug;
-use xalloc::SysTlsf;
+use log::{debug, warn};
+use xalloc::{SysTlsf, SysTlsfRegion};
 
 use crate::{
+    camera::Camera,
     vulkan::{self, util::GpuBuffer, Vk},
-    RendererBase,
+    RendererBase, FRAME_OVERLAP,
 };
 
-use super::state::State;
-
-pub fn build_test_chunk() -> Vec<FaceData> {
-    let mut res = Vec::new();
-
-    let faces = [
-        Facing::Nx,
-        Facing::Ny,
-        Facing::Nz,
-        Facing::Px,
-        Facing::Py,
-        Facing::Pz,
-    ];
-
-    for x in (0..32).step_by(2) {
-        for y in (0..32).step_by(2) {
-            for z in (0..32).step_by(2) {
-                for face in faces {
-                    res.push(FaceData::new(uvec3(x, y, z), face, 0));
-                }
-            }
-        }
-    }
-
-    res
-}
+use super::{culling, state::State};
 
+// Which two coordinates the vertex shader varies, see offsetCw() in there
 const XY: u32 = 0b11 << 14;
-const XZ: u32 = 0b10 << 14;
-const YZ: u32 = 0b01 << 14;
+const YZ: u32 = 0b10 << 14;
+const XZ: u32 = 0b01 << 14;
 const FLIP: u32 = 0b100 << 14;
 
 #[repr(u32)]
@@ -62,25 +39,73 @@ pub enum Facing {
 // where
 //   X/Y/Z: position, duh
 //   F: "flip" (true/false), i.e, whether to push the face vertices along the negative normal by one unit
-//   NN: plane: 11 <=> 110 <=> XY, 10 <=> 101 <=> XZ; 01 <=> 011 <=> YZ
-//   I: texture id <=> block id
+//   NN: plane: 11 <=> XY, 10 <=> YZ, 01 <=> XZ
+//   I: texture, the layer of the block texture array (see textures.rs)
 //   ?: unused for now
-#[repr(transparent)]
+// followed by the light at each vertex:
+// [SSSS BBBB] x 4, vertex 0 in the lowest byte
+// where
+//   S: sky light, B: block light, 0-15
+// and then the rest of the shading, plus the size:
+// [???? ????][???? ???H][HHHW WWWQ][AAAA AAAA]
+// where
+//   A: ambient occlusion, 2 bits per vertex (vertex 0 lowest), 0 = darkest, 3 = not occluded
+//   Q: quad flip, split the quad along the other diagonal
+//   W/H: size of the face minus one, along the first and the second of the two coordinates
+//        of its plane (in xyz order), for greedy meshing. 0 for single block faces
+#[repr(C)]
 #[derive(Clone, Copy, Pod, Zeroable)]
-pub struct FaceData(u32);
+pub struct FaceData {
+    face: u32,
+    light: u32,
+    shading: u32,
+}
 
 impl FaceData {
     /// xyz: each component should be in range 0..31
-    /// block_id: 10 bits max
+    /// texture: 10 bits max
     #[inline]
-    pub const fn new(xyz: UVec3, facing: Facing, block_id: u16) -> Self {
+    pub const fn new(xyz: UVec3, facing: Facing, texture: u16) -> Self {
         let mut res = 0;
-        res |= block_id as u32;
+        res |= texture as u32 & 0x3FF;
         res |= facing as u32;
         res |= xyz.z << 17;
         res |= xyz.y << 22;
         res |= xyz.x << 27;
-        Self(res)
+        Self { face: res, light: 0, shading: 0xFF }
+    }
+
+    /// Per vertex, in the order the vertex shader emits them in:
+    /// light: sky light << 4 | block light
+    /// ao: 0-3, 3 being not occluded at all
+    #[inline]
+    pub const fn with_shading(self, light: [u8; 4], ao: [u8; 4]) -> Self {
+        let mut shading = 0;
+        let mut i = 0;
+        while i < 4 {
+            shading |= (ao[i] as u32 & 3) << (i * 2);
+            i += 1;
+        }
+        // The quad is normally split along the 1-2 diagonal. Interpolating across the
+        // darker diagonal makes the shading lopsided, so split along the brighter one
+        if ao[0] as u32 + ao[3] as u32 > ao[1] as u32 + ao[2] as u32 {
+            shading |= 1 << 8;
+        }
+        Self {
+            light: u32::from_le_bytes(light),
+            shading: shading | (self.shading & !0x1FF),
+            ..self
+        }
+    }
+
+    /// width, height: 1-16, see the format above for which is which
+    #[inline]
+    pub const fn with_size(self, width: u32, height: u32) -> Self {
+        let size = (((width - 1) & 0xF) << 9) | (((height - 1) & 0xF) << 13);
+        Self {
+            shading: (self.shading & !(0xFF << 9)) | size,
+            ..self
+        }
     }
 }
 
@@ -92,25 +117,78 @@ pub struct ChunkMeshView<'a> {
     /// Indices to the start of each group of faces in `faces`.
     /// First group (+X) starts at 0, so not included.
     pub axis_offsets: [u32; 5],
+    /// Translucent faces (water), drawn after everything else. In no particular order.
+    pub fluid_faces: &'a [FaceData],
 }
 
 pub struct RenderChunk {
-    /// Number of faces in the chunk.
+    pos: IVec3,
+    /// Number of faces in the chunk, fluid faces included.
     num_faces: NonZeroU32,
     /// Offset to the buffer, in faces.
     offset: u32,
+    axis_offsets: [u32; 5],
+    /// The fluid faces come after the rest, starting from here.
+    fluid_start: u32,
+    region: SysTlsfRegion,
+}
+
+struct PendingFree {
+    /// Value of `RenderWorld::frame_index` at the time
+    freed_on: u64,
+    region: SysTlsfRegion,
+    /// In bytes
+    size: u32,
 }
 
+// Same layout as the client's `Chunks`: a ring buffer in x and z, so nothing
+// needs to be moved around as the player moves.
+const GRID_XZ: usize = 64;
+const GRID_Y: usize = 16;
+
 pub struct RenderWorld {
     chunks: Box<[Option<RenderChunk>]>,
     offset: IVec3,
-
-    test_chunk_faces: u32,
+    player_chunk_pos: IVec3,
+    // Chunks left without a mesh for lack of memory since the last take_evicted()
+    evicted: Vec<(IVec3, i32)>,
 
     gpu_buffer: GpuBuffer,
     chunk_mesh_allocator: SysTlsf<u32>,
+    // Regions can't be reused until the GPU has finished all frames that might
+    // still be reading them, so freeing is delayed
+    pending_frees: Vec<PendingFree>,
+    // Number of frames rendered so far
+    frame_index: u64,
 
     index_buffer: GpuBuffer,
+
+    // One indirect draw per (non-empty, visible) axis group of every chunk in view.
+    // Both buffers have room for MAX_DRAWS entries per frame in flight, and the origin
+    // of a draw is at the same index as its command. The shader finds it through
+    // gl_InstanceIndex (= firstInstance), as push constants can't change between the
+    // draws. These are only the candidates: occlusion culling picks the final draws.
+    draw_command_buffer: GpuBuffer,
+    chunk_origin_buffer: GpuBuffer,
+    draw_commands: Vec<vk::DrawIndexedIndirectCommand>,
+    chunk_origins: Vec<IVec4>,
+    draw_limit_hit: bool,
+    // MAX_DRAWS, unless the device can't do that many
+    max_draws: u32,
+
+    // Fluids are drawn after everything else, back to front, one draw per chunk. They skip
+    // occlusion culling, so these are the final draws, and go straight to the GPU. Their
+    // chunk origins come right after the ones of the opaque draws.
+    fluid_draw_buffer: GpuBuffer,
+    fluid_draws: Vec<vk::DrawIndexedIndirectCommand>,
+}
+
+/// Draw commands on the GPU, along with how many of them there are.
+pub struct IndirectDraws {
+    pub buffer: vk::Buffer,
+    pub offset: u64,
+    pub count_buffer: vk::Buffer,
+    pub count_offset: u64,
 }
 
 // Each face needs 6 indices, and there are 32³/2*6 = 98304 faces, so
@@ -118,6 +196,11 @@ pub struct RenderWorld {
 // Those shall be placed at the end of the buffer...
 const INDEX_BUFFER_SIZE: u32 = 589824;
 
+// Plenty for the default render distance, where most chunks don't have all 6 groups
+pub(crate) const MAX_DRAWS: usize = 1 << 17;
+// One per chunk at most
+const MAX_FLUID_DRAWS: usize = GRID_XZ * GRID_Y * GRID_XZ;
+
 impl RenderWorld {
     /// pub(crate) because this should definitely be ran only after all other resources
     /// (framebuffers, textures and such) have been allocated, because this allocates
@@ -131,8 +214,6 @@ impl RenderWorld {
 
         let vk = &mut renderer.vk;
 
-        let test_chunk = build_test_chunk();
-
         let index_buffer = vulkan::util::allocate_buffer_and_bind(
             "Index Buffer",
             &vk.device,
@@ -142,44 +223,137 @@ impl RenderWorld {
             MemoryLocation::GpuOnly,
         )?;
 
+        // Rewritten every frame, so these live in host-visible memory and skip the uploader.
+        // Only read by the culling shader, which writes the actual indirect draws
+        let draw_command_buffer = vulkan::util::allocate_buffer_and_bind(
+            "Draw command buffer",
+            &vk.device,
+            &mut vk.allocator,
+            (FRAME_OVERLAP * MAX_DRAWS * std::mem::size_of::<vk::DrawIndexedIndirectCommand>()) as u32,
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+            MemoryLocation::CpuToGpu,
+        )?;
+        let chunk_origin_buffer = vulkan::util::allocate_buffer_and_bind(
+            "Chunk origin buffer",
+            &vk.device,
+            &mut vk.allocator,
+            (FRAME_OVERLAP * MAX_DRAWS * std::mem::size_of::<IVec4>()) as u32,
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+            MemoryLocation::CpuToGpu,
+        )?;
+
+        let fluid_draw_buffer = vulkan::util::allocate_buffer_and_bind(
+            "Fluid draw buffer",
+            &vk.device,
+            &mut vk.allocator,
+            (FRAME_OVERLAP * MAX_FLUID_DRAWS * std::mem::size_of::<vk::DrawIndexedIndirectCommand>()) as u32,
+            vk::BufferUsageFlags::INDIRECT_BUFFER,
+            MemoryLocation::CpuToGpu,
+        )?;
+
         let indices = generate_indices();
         vk.uploader
             .upload_to_buffer(&indices, index_buffer.handle, 0)?;
-        vk.uploader
-            .upload_to_buffer(&test_chunk, buffer.handle, 0)?;
-
-        let suballocator = SysTlsf::new(buffer.size);
 
         unsafe {
             vk.device.update_descriptor_sets(
-                &[vk::WriteDescriptorSet::builder()
-                    .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
-                    .dst_array_element(0)
-                    .dst_binding(0)
-                    .dst_set(state.descriptors.full_block.handle)
-                    .buffer_info(&[vk::DescriptorBufferInfo::builder()
-                        .buffer(buffer.handle)
-                        .offset(0)
-                        .range(buffer.size as u64)
-                        .build()])
-                    .build()],
+                &[
+                    vk::WriteDescriptorSet::builder()
+                        .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                        .dst_array_element(0)
+                        .dst_binding(0)
+                        .dst_set(state.descriptors.full_block.handle)
+                        .buffer_info(&[vk::DescriptorBufferInfo::builder()
+                            .buffer(buffer.handle)
+                            .offset(0)
+                            .range(buffer.size as u64)
+                            .build()])
+                        .build(),
+                    vk::WriteDescriptorSet::builder()
+                        .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                        .dst_array_element(0)
+                        .dst_binding(1)
+                        .dst_set(state.descriptors.full_block.handle)
+                        .buffer_info(&[vk::DescriptorBufferInfo::builder()
+                            .buffer(chunk_origin_buffer.handle)
+                            .offset(0)
+                            .range(chunk_origin_buffer.size as u64)
+                            .build()])
+                        .build(),
+                ],
                 &[],
             );
         }
 
         Ok(Self {
-            chunks: vec![].into_boxed_slice(),
+            chunks: std::iter::repeat_with(|| None)
+                .take(GRID_XZ * GRID_Y * GRID_XZ)
+                .collect(),
             offset: player_chunk_pos,
+            player_chunk_pos,
+            evicted: Vec::new(),
 
-            test_chunk_faces: test_chunk.len() as u32,
-
+            chunk_mesh_allocator: SysTlsf::new(buffer.size),
             gpu_buffer: buffer,
-            chunk_mesh_allocator: suballocator,
+            pending_frees: Vec::new(),
+            frame_index: 0,
             index_buffer,
+
+            draw_command_buffer,
+            chunk_origin_buffer,
+            draw_commands: Vec::new(),
+            chunk_origins: Vec::new(),
+            draw_limit_hit: false,
+            max_draws: (MAX_DRAWS as u32).min(vk.device.limits.max_draw_indirect_count),
+
+            fluid_draw_buffer,
+            fluid_draws: Vec::new(),
         })
     }
 
-    pub fn render(&mut self, cmd: vk::CommandBuffer, vk: &Vk, state: &State) -> anyhow::Result<()> {
+    /// Writes out the draws that pass the CPU-side culling. Returns how many there are,
+    /// not counting the fluid draws, which don't go through occlusion culling.
+    /// `frame`: which of the frames in flight this is, see `RendererBase::frame_in_flight`
+    pub(crate) fn prepare_draws(&mut self, camera: &Camera, frame: usize) -> u32 {
+        self.release_pending_frees();
+        self.frame_index += 1;
+
+        self.build_draw_commands(camera, frame);
+        self.build_fluid_draws(camera, frame);
+        let draw_count = self.draw_commands.len();
+
+        let command_offset = frame * MAX_DRAWS * std::mem::size_of::<vk::DrawIndexedIndirectCommand>();
+        let origin_offset = frame * MAX_DRAWS * std::mem::size_of::<IVec4>();
+        // safe: the buffers have room for MAX_DRAWS entries at these offsets, and the
+        // GPU is done with the previous frame that used them
+        unsafe {
+            let commands_ptr = self.draw_command_buffer.allocation.mapped_ptr().unwrap().as_ptr();
+            std::ptr::copy_nonoverlapping(
+                self.draw_commands.as_ptr(),
+                commands_ptr.cast::<u8>().add(command_offset).cast(),
+                draw_count,
+            );
+            let origins_ptr = self.chunk_origin_buffer.allocation.mapped_ptr().unwrap().as_ptr();
+            std::ptr::copy_nonoverlapping(
+                self.chunk_origins.as_ptr(),
+                origins_ptr.cast::<u8>().add(origin_offset).cast(),
+                self.chunk_origins.len(),
+            );
+
+            let fluid_offset = frame * MAX_FLUID_DRAWS * std::mem::size_of::<vk::DrawIndexedIndirectCommand>();
+            let fluid_ptr = self.fluid_draw_buffer.allocation.mapped_ptr().unwrap().as_ptr();
+            std::ptr::copy_nonoverlapping(
+                self.fluid_draws.as_ptr(),
+                fluid_ptr.cast::<u8>().add(fluid_offset).cast(),
+                self.fluid_draws.len(),
+            );
+        }
+
+        draw_count as u32
+    }
+
+    /// Draws what's left of the draws after occlusion culling.
+    pub fn render(&self, cmd: vk::CommandBuffer, vk: &Vk, state: &State, draws: &IndirectDraws) -> anyhow::Result<()> {
         unsafe {
             vk.device.cmd_bind_descriptor_sets(
                 cmd,
@@ -200,14 +374,274 @@ impl RenderWorld {
                 0,
                 vk::IndexType::UINT32,
             );
-            vk.device
-                .cmd_draw_indexed(cmd, self.test_chunk_faces * 6, 1, 0, 0, 0);
+            vk.device.cmd_draw_indexed_indirect_count(
+                cmd,
+                draws.buffer,
+                draws.offset,
+                draws.count_buffer,
+                draws.count_offset,
+                self.max_draws,
+                std::mem::size_of::<vk::DrawIndexedIndirectCommand>() as u32,
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Draws the fluids from the last `prepare_draws`, over what's already been drawn.
+    pub fn render_fluids(&self, cmd: vk::CommandBuffer, vk: &Vk, state: &State, frame: usize) {
+        if self.fluid_draws.is_empty() {
+            return;
+        }
+
+        unsafe {
+            // Same layout as the opaque pipeline, so the descriptors and the push
+            // constants stay bound
+            vk.device.cmd_bind_pipeline(
+                cmd,
+                vk::PipelineBindPoint::GRAPHICS,
+                state.fluid_pipeline.handle,
+            );
+            vk.device.cmd_bind_index_buffer(
+                cmd,
+                self.index_buffer.handle,
+                0,
+                vk::IndexType::UINT32,
+            );
+            vk.device.cmd_draw_indexed_indirect(
+                cmd,
+                self.fluid_draw_buffer.handle,
+                (frame * MAX_FLUID_DRAWS * std::mem::size_of::<vk::DrawIndexedIndirectCommand>()) as u64,
+                self.fluid_draws.len() as u32,
+                std::mem::size_of::<vk::DrawIndexedIndirectCommand>() as u32,
+            );
         }
+    }
+
+    /// Where the draws from `prepare_draws` are: `FRAME_OVERLAP` blocks of `MAX_DRAWS` draw
+    /// commands, and as many chunk origins.
+    pub(crate) fn draw_buffers(&self) -> (&GpuBuffer, &GpuBuffer) {
+        (&self.draw_command_buffer, &self.chunk_origin_buffer)
+    }
+
+    fn build_draw_commands(&mut self, camera: &Camera, frame: usize) {
+        self.draw_commands.clear();
+        self.chunk_origins.clear();
+
+        let frustum = camera.frustum();
+        let mut limit_hit = false;
+        'chunks: for chunk in self.chunks.iter().flatten() {
+            if !culling::chunk_in_frustum(chunk.pos, &frustum) {
+                continue;
+            }
+
+            let visible = culling::visible_axis_groups(chunk.pos, camera.pos());
+            let mut group_start = 0;
+            for (group, group_end) in chunk.axis_offsets.into_iter().chain([chunk.fluid_start]).enumerate() {
+                let num_faces = group_end - group_start;
+                if num_faces > 0 && visible[group] {
+                    if self.draw_commands.len() == self.max_draws as usize {
+                        limit_hit = true;
+                        break 'chunks;
+                    }
+                    self.draw_commands.push(vk::DrawIndexedIndirectCommand {
+                        index_count: num_faces * 6,
+                        instance_count: 1,
+                        first_index: 0,
+                        // 4 vertices per face, see generate_indices()
+                        vertex_offset: ((chunk.offset + group_start) * 4) as i32,
+                        first_instance: (frame * MAX_DRAWS + self.draw_commands.len()) as u32,
+                    });
+                    self.chunk_origins.push(chunk.pos.extend(0));
+                }
+                group_start = group_end;
+            }
+        }
+
+        if limit_hit && !self.draw_limit_hit {
+            warn!("Over {} chunk draws, some chunks won't be rendered", self.max_draws);
+        }
+        self.draw_limit_hit = limit_hit;
+    }
+
+    // Has to run after build_draw_commands(), the origins go after the ones of those
+    fn build_fluid_draws(&mut self, camera: &Camera, frame: usize) {
+        self.fluid_draws.clear();
+
+        let frustum = camera.frustum();
+        let mut chunks: Vec<&RenderChunk> = self.chunks
+            .iter()
+            .flatten()
+            .filter(|chunk| chunk.fluid_start < chunk.num_faces.get())
+            .filter(|chunk| culling::chunk_in_frustum(chunk.pos, &frustum))
+            .collect();
+
+        // Back to front, so that the blending comes out right (between chunks, at least)
+        let distance = |chunk: &RenderChunk| {
+            let center = chunk.pos.as_vec3() * 16.0 + 8.0;
+            center.distance_squared(camera.pos())
+        };
+        chunks.sort_by(|a, b| distance(b).total_cmp(&distance(a)));
+
+        for chunk in chunks {
+            // Shares the origins with the opaque draws
+            if self.chunk_origins.len() == self.max_draws as usize {
+                break;
+            }
+            self.fluid_draws.push(vk::DrawIndexedIndirectCommand {
+                index_count: (chunk.num_faces.get() - chunk.fluid_start) * 6,
+                instance_count: 1,
+                first_index: 0,
+                vertex_offset: ((chunk.offset + chunk.fluid_start) * 4) as i32,
+                first_instance: (frame * MAX_DRAWS + self.chunk_origins.len()) as u32,
+            });
+            self.chunk_origins.push(chunk.pos.extend(0));
+        }
+    }
 
+    /// Used to decide what to evict when running out of memory.
+    pub(crate) fn set_player_chunk_pos(&mut self, chunk_pos: IVec3) {
+        self.player_chunk_pos = chunk_pos;
+    }
+
+    /// Chunks whose meshes were thrown out (or never kept) because the memory was needed
+    /// for ones closer to the player, since the last call. Along with each comes how close
+    /// it has to get to the player (in chunks, along X or Z) for a new mesh to be kept.
+    pub fn take_evicted(&mut self) -> Vec<(IVec3, i32)> {
+        std::mem::take(&mut self.evicted)
+    }
+
+    /// Replaces the mesh of the chunk. If there isn't enough GPU memory left, the meshes of
+    /// the chunks furthest away are thrown out to make room, unless the chunk itself is
+    /// the furthest away, in which case it is left without a mesh. Either way, those
+    /// chunks come up in `take_evicted()`.
+    ///
+    /// Fails if the mesh can't be uploaded right now (the staging buffer is full, or the
+    /// memory of evicted chunks is still in use by the GPU), in which case the chunk keeps
+    /// its old mesh and the update should be retried on a later frame.
+    pub fn update_chunk_mesh(&mut self, chunk_pos: IVec3, mesh: ChunkMeshView, renderer: &mut RendererBase) -> anyhow::Result<()> {
+        // Uploaded in one piece, so that a failed upload can't leave half a mesh behind
+        let faces = if mesh.fluid_faces.is_empty() {
+            Cow::Borrowed(mesh.faces)
+        } else {
+            Cow::Owned([mesh.faces, mesh.fluid_faces].concat())
+        };
+
+        let Some(num_faces) = NonZeroU32::new(faces.len() as u32) else {
+            self.remove_chunk_mesh(chunk_pos);
+            return Ok(());
+        };
+
+        let face_size = std::mem::size_of::<FaceData>() as u32;
+        let Some((region, byte_offset)) = self.allocate(num_faces.get() * face_size, chunk_pos)? else {
+            warn!("Out of memory for chunk meshes, {chunk_pos} won't be rendered");
+            self.remove_chunk_mesh(chunk_pos);
+            // Everything that's kept is at least as close
+            self.evicted.push((chunk_pos, self.distance_to_player(chunk_pos) - 1));
+            return Ok(());
+        };
+
+        if let Err(e) = renderer.vk.uploader.upload_to_buffer(&faces, self.gpu_buffer.handle, byte_offset) {
+            // Never seen by the GPU, so this one can be freed right away
+            unsafe { self.chunk_mesh_allocator.dealloc_unchecked(region) };
+            return Err(e);
+        }
+
+        // Whatever had the slot goes, even if it's a chunk that has since wrapped around
+        let idx = self.pos_to_idx(chunk_pos);
+        if let Some(old) = self.chunks[idx].take() {
+            self.free_later(old);
+        }
+        self.chunks[idx] = Some(RenderChunk {
+            pos: chunk_pos,
+            num_faces,
+            offset: byte_offset / face_size,
+            axis_offsets: mesh.axis_offsets,
+            fluid_start: mesh.faces.len() as u32,
+            region,
+        });
         Ok(())
     }
 
-    pub fn update_chunk_mesh(&mut self, chunk_pos: IVec3, mesh: ChunkMeshView) {}
+    pub fn remove_chunk_mesh(&mut self, chunk_pos: IVec3) {
+        let idx = self.pos_to_idx(chunk_pos);
+        if !self.chunks[idx].as_ref().map_or(false, |chunk| chunk.pos == chunk_pos) {
+            return;
+        }
+
+        let chunk = self.chunks[idx].take().unwrap();
+        self.free_later(chunk);
+    }
+
+    // Once the GPU is done with the frames that might still draw it
+    fn free_later(&mut self, chunk: RenderChunk) {
+        let size = chunk.num_faces.get() * std::mem::size_of::<FaceData>() as u32;
+        self.pending_frees.push(PendingFree { freed_on: self.frame_index, region: chunk.region, size });
+    }
+
+    pub fn has_mesh(&self, chunk_pos: IVec3) -> bool {
+        self.chunks[self.pos_to_idx(chunk_pos)]
+            .as_ref()
+            .map_or(false, |chunk| chunk.pos == chunk_pos)
+    }
+
+    // Ok(None) if there is no room, and nothing further away than `for_chunk` to evict.
+    // Err if chunks had to be evicted, but their memory can't be reused just yet.
+    fn allocate(&mut self, size: u32, for_chunk: IVec3) -> anyhow::Result<Option<(SysTlsfRegion, u32)>> {
+        if let Some(allocation) = self.chunk_mesh_allocator.alloc(size) {
+            return Ok(Some(allocation));
+        }
+
+        let distance = |pos: IVec3| self.distance_to_player(pos);
+        let mut candidates: Vec<IVec3> = self.chunks
+            .iter()
+            .flatten()
+            .map(|chunk| chunk.pos)
+            .filter(|&pos| distance(pos) > distance(for_chunk))
+            .collect();
+        candidates.sort_unstable_by_key(|&pos| std::cmp::Reverse(distance(pos)));
+        let keep_within = distance(for_chunk);
+
+        // Evict until enough is on its way to being freed. Fragmentation can still get in
+        // the way, but then the retry will just evict some more
+        let mut to_be_freed: u32 = self.pending_frees.iter().map(|free| free.size).sum();
+        for chunk_pos in candidates {
+            if to_be_freed >= size {
+                break;
+            }
+            debug!("Out of mesh memory, evicting chunk {chunk_pos}");
+            self.remove_chunk_mesh(chunk_pos);
+            self.evicted.push((chunk_pos, keep_within));
+            to_be_freed += self.pending_frees.last().unwrap().size;
+        }
+
+        if to_be_freed == 0 {
+            return Ok(None);
+        }
+        anyhow::bail!("Out of mesh memory, waiting for {to_be_freed} bytes to be freed")
+    }
+
+    fn distance_to_player(&self, chunk_pos: IVec3) -> i32 {
+        (chunk_pos - self.player_chunk_pos).xz().abs().max_element()
+    }
+
+    fn release_pending_frees(&mut self) {
+        let mut i = 0;
+        while i < self.pending_frees.len() {
+            if self.pending_frees[i].freed_on + FRAME_OVERLAP as u64 <= self.frame_index {
+                let free = self.pending_frees.swap_remove(i);
+                // safe: the region came from this allocator, and is only ever freed once
+                unsafe { self.chunk_mesh_allocator.dealloc_unchecked(free.region) };
+            } else {
+                i += 1;
+            }
+        }
+    }
+
+    fn pos_to_idx(&self, chunk_pos: IVec3) -> usize {
+        let grid_xz = (chunk_pos.xz() + self.offset.xz()).as_uvec2() & (GRID_XZ as u32 - 1);
+        ((grid_xz.x as usize * GRID_XZ + grid_xz.y as usize) * GRID_Y) | (chunk_pos.y as usize & (GRID_Y - 1))
+    }
 }
 
 fn allocate_mesh_buffer(vk: &mut Vk) -> GpuBuffer {
@@ -226,6 +660,9 @@ fn allocate_mesh_buffer(vk: &mut Vk) -> GpuBuffer {
     for percentage in [70, 55, 45, 30, 20, 15] {
         let mut size = total_memory * percentage / 100;
         size = size.min(vk.device.limits.max_storage_buffer_range as usize);
+        // Chunks are drawn with vertexOffset = 4 * (offset in faces) < offset in bytes,
+        // and that is an i32
+        size = size.min(i32::MAX as usize);
 
         if let Ok(buffer) = vulkan::util::allocate_buffer_and_bind(
             "Mesh buffer",
@@ -126,6 +126,12 @@ impl RendererBase {
 
         Ok(())
     }
+
+    /// Which of the `FRAME_OVERLAP` sets of per-frame resources the frame being
+    /// recorded uses. Whatever the GPU read from that set last time is done by then.
+    pub(crate) fn frame_in_flight(&self) -> usize {
+        self.frame_count % FRAME_OVERLAP
+    }
 }
 
 impl RendererBase {
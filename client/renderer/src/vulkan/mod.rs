@@ -150,6 +150,18 @@ impl Vk {
 
 fn get_device_features() -> vk::PhysicalDeviceFeatures {
     vk::PhysicalDeviceFeatures {
+        // All chunks are drawn with one cmd_draw_indexed_indirect, and find their
+        // origin through gl_InstanceIndex
+        multi_draw_indirect: vk::TRUE,
+        draw_indirect_first_instance: vk::TRUE,
+        ..Default::default()
+    }
+}
+
+fn get_device_features_12() -> vk::PhysicalDeviceVulkan12Features {
+    vk::PhysicalDeviceVulkan12Features {
+        // Occlusion culling compacts the draws on the GPU, so only it knows how many there are
+        draw_indirect_count: vk::TRUE,
         ..Default::default()
     }
 }
@@ -245,10 +257,12 @@ unsafe fn create_device(instance: &Instance, surface: &Surface) -> Result<Device
         .queue_priorities(&priorities);
 
     let enabled_features = get_device_features();
+    let mut enabled_features_12 = get_device_features_12();
     let device_create_info = vk::DeviceCreateInfo::builder()
         .queue_create_infos(std::slice::from_ref(&queue_info))
         .enabled_extension_names(&extensions)
-        .enabled_features(&enabled_features);
+        .enabled_features(&enabled_features)
+        .push_next(&mut enabled_features_12);
 
     let handle = instance.create_device(physical_device, &device_create_info, None)?;
 
@@ -458,6 +472,21 @@ mod phys_device_selection {
             return None;
         }
 
+        // 3. It has to support the features that get enabled
+        if properties.api_version < vk::make_api_version(0, 1, 2, 0) {
+            return None;
+        }
+        let mut features_12 = vk::PhysicalDeviceVulkan12Features::default();
+        let mut features = vk::PhysicalDeviceFeatures2::builder().push_next(&mut features_12);
+        unsafe { instance.get_physical_device_features2(phys_device, &mut features) };
+        let features = features.features;
+        if features.multi_draw_indirect == vk::FALSE
+            || features.draw_indirect_first_instance == vk::FALSE
+            || features_12.draw_indirect_count == vk::FALSE
+        {
+            return None;
+        }
+
         Some(GraphicsDeviceDetails {
             queue_idx,
             physical_device: phys_device,
@@ -9,7 +9,7 @@ use log::debug;
 
 use crate::vulkan;
 
-use super::{Device, GpuAllocator, util::GpuBuffer};
+use super::{Device, GpuAllocator, util::{GpuBuffer, GpuImage}};
 
 const STAGING_BUFFER_SIZE: u64 = 1 << 24; // 16 MiB (same as Sodium)
 
@@ -122,7 +122,64 @@ impl Uploader {
             return Ok(());
         }
 
-        if self.staging_buffer_head + data.len() as u32 >= self.buffer.allocation.size() as u32 {
+        let src_offset = self.stage(data)?;
+        debug!(
+            "Queued buffer copy of {} bytes with dst offset {offset}",
+            data.len()
+        );
+        self.pending_copy_ops.push(MemCopyOp::Buf2Buffer {
+            dst,
+            src_offset,
+            dst_offset: offset,
+            size: data.len() as _,
+        });
+
+        Ok(())
+    }
+
+    /// Uploads the first mip level of every layer of the `dst` image, one after the other
+    /// in `data`, and generates the rest of its mip levels from it. The image needs
+    /// TRANSFER_SRC and TRANSFER_DST usage for that, and ends up SHADER_READ_ONLY_OPTIMAL
+    /// for the `shader_stages`.
+    pub fn upload_to_image(
+        &mut self,
+        data: &[u8],
+        dst: &GpuImage,
+        shader_stages: vk::PipelineStageFlags,
+    ) -> Result<()> {
+        let range = vk::ImageSubresourceRange {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            base_mip_level: 0,
+            level_count: 1,
+            base_array_layer: 0,
+            layer_count: dst.layers,
+        };
+
+        let src_offset = self.stage(data)?;
+        debug!("Queued image copy of {} bytes, {} layers", data.len(), dst.layers);
+        self.pending_copy_ops.push(MemCopyOp::Buf2Image {
+            dst: dst.handle,
+            extent: dst.extent,
+            range,
+            shader_stages,
+            src_offset,
+        });
+
+        if dst.mip_levels > 1 {
+            self.pending_mip_gens.push(MipGenData {
+                image: dst.handle,
+                size: dst.extent,
+                range: vk::ImageSubresourceRange { level_count: dst.mip_levels, ..range },
+            });
+        }
+        Ok(())
+    }
+
+    // Copies the data to the staging buffer, returns where it went
+    fn stage(&mut self, data: &[u8]) -> Result<u32> {
+        // Copies to images need 4 byte aligned offsets, and that never hurts buffers
+        let head = (self.staging_buffer_head + 3) & !3;
+        if head + data.len() as u32 >= self.buffer.allocation.size() as u32 {
             // TODO: What should actually be done is allocating another buffer,
             // but so far this has never happened, and that adds a non-trivial
             // amount of complexity
@@ -136,23 +193,12 @@ impl Uploader {
 
         unsafe {
             // unwrap(): Some is always returned when memory is host-visible, which is the whole point here
-            let mapped_ptr = self.buffer.allocation.mapped_ptr().unwrap().as_ptr().offset(self.staging_buffer_head as isize);
+            let mapped_ptr = self.buffer.allocation.mapped_ptr().unwrap().as_ptr().offset(head as isize);
             std::ptr::copy_nonoverlapping(data.as_ptr(), mapped_ptr.cast(), data.len());
         }
+        self.staging_buffer_head = head + data.len() as u32;
 
-        debug!(
-            "Queued buffer copy of {} bytes with dst offset {offset}",
-            data.len()
-        );
-        self.pending_copy_ops.push(MemCopyOp::Buf2Buffer {
-            dst,
-            src_offset: self.staging_buffer_head,
-            dst_offset: offset,
-            size: data.len() as _,
-        });
-        self.staging_buffer_head += data.len() as u32;
-
-        Ok(())
+        Ok(head)
     }
 
     pub fn flush_staged(&mut self, device: &Device) -> Result<()> {
@@ -295,6 +341,14 @@ impl Uploader {
         }?;
 
         for mip_gen_ops in &self.pending_mip_gens {
+            // The first level was just uploaded and has to be kept, the rest can be
+            // thrown away
+            let first_level = vk::ImageSubresourceRange { level_count: 1, ..mip_gen_ops.range };
+            let other_levels = vk::ImageSubresourceRange {
+                base_mip_level: 1,
+                level_count: mip_gen_ops.range.level_count - 1,
+                ..mip_gen_ops.range
+            };
             unsafe {
                 device.handle.cmd_pipeline_barrier(
                     self.commands,
@@ -303,14 +357,24 @@ impl Uploader {
                     vk::DependencyFlags::empty(),
                     &[],
                     &[],
-                    &[vk::ImageMemoryBarrier::builder()
-                        .old_layout(vk::ImageLayout::UNDEFINED)
-                        .new_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
-                        .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
-                        .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
-                        .image(mip_gen_ops.image)
-                        .subresource_range(mip_gen_ops.range)
-                        .build()],
+                    &[
+                        vk::ImageMemoryBarrier::builder()
+                            .old_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+                            .new_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+                            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                            .image(mip_gen_ops.image)
+                            .subresource_range(first_level)
+                            .build(),
+                        vk::ImageMemoryBarrier::builder()
+                            .old_layout(vk::ImageLayout::UNDEFINED)
+                            .new_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+                            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                            .image(mip_gen_ops.image)
+                            .subresource_range(other_levels)
+                            .build(),
+                    ],
                 );
             }
 
@@ -73,6 +73,143 @@ pub fn allocate_buffer_without_binding(
     })
 }
 
+pub struct GpuImage {
+    pub allocation: Allocation,
+    pub handle: vk::Image,
+    /// Of all mip levels
+    pub view: vk::ImageView,
+    pub extent: vk::Extent2D,
+    pub mip_levels: u32,
+    pub layers: u32,
+}
+
+/// A 2D image with a view of all of its mip levels.
+pub fn allocate_image_and_bind(
+    allocation_name: &'static str,
+    device: &Device,
+    allocator: &mut GpuAllocator,
+    format: vk::Format,
+    extent: vk::Extent2D,
+    mip_levels: u32,
+    usage: vk::ImageUsageFlags,
+) -> anyhow::Result<GpuImage> {
+    allocate_layered_image(allocation_name, device, allocator, format, extent, mip_levels, 1, vk::ImageViewType::TYPE_2D, usage)
+}
+
+/// A 2D array image with a view of all of its layers and mip levels.
+#[allow(clippy::too_many_arguments)]
+pub fn allocate_image_array_and_bind(
+    allocation_name: &'static str,
+    device: &Device,
+    allocator: &mut GpuAllocator,
+    format: vk::Format,
+    extent: vk::Extent2D,
+    mip_levels: u32,
+    layers: u32,
+    usage: vk::ImageUsageFlags,
+) -> anyhow::Result<GpuImage> {
+    allocate_layered_image(allocation_name, device, allocator, format, extent, mip_levels, layers, vk::ImageViewType::TYPE_2D_ARRAY, usage)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn allocate_layered_image(
+    allocation_name: &'static str,
+    device: &Device,
+    allocator: &mut GpuAllocator,
+    format: vk::Format,
+    extent: vk::Extent2D,
+    mip_levels: u32,
+    layers: u32,
+    view_type: vk::ImageViewType,
+    usage: vk::ImageUsageFlags,
+) -> anyhow::Result<GpuImage> {
+    let image = unsafe {
+        device.create_image(
+            &vk::ImageCreateInfo::builder()
+                .image_type(vk::ImageType::TYPE_2D)
+                .format(format)
+                .extent(vk::Extent3D { width: extent.width, height: extent.height, depth: 1 })
+                .mip_levels(mip_levels)
+                .array_layers(layers)
+                .samples(vk::SampleCountFlags::TYPE_1)
+                .tiling(vk::ImageTiling::OPTIMAL)
+                .usage(usage)
+                .sharing_mode(SharingMode::EXCLUSIVE)
+                .initial_layout(vk::ImageLayout::UNDEFINED),
+            None,
+        )?
+    };
+
+    let allocation = allocator.allocate(&AllocationCreateDesc {
+        name: allocation_name,
+        requirements: unsafe { device.get_image_memory_requirements(image) },
+        location: MemoryLocation::GpuOnly,
+        linear: false,
+    })?;
+
+    if let Err(e) = unsafe { device.bind_image_memory(image, allocation.memory(), allocation.offset()) } {
+        allocator.free(allocation)?;
+        return Err(e.into());
+    }
+
+    let aspect_mask = match format {
+        vk::Format::D32_SFLOAT | vk::Format::D16_UNORM => vk::ImageAspectFlags::DEPTH,
+        _ => vk::ImageAspectFlags::COLOR,
+    };
+    let view = unsafe {
+        device.create_image_view(
+            &vk::ImageViewCreateInfo::builder()
+                .image(image)
+                .view_type(view_type)
+                .format(format)
+                .subresource_range(vk::ImageSubresourceRange {
+                    aspect_mask,
+                    base_mip_level: 0,
+                    level_count: mip_levels,
+                    base_array_layer: 0,
+                    layer_count: layers,
+                }),
+            None,
+        )?
+    };
+
+    Ok(GpuImage {
+        allocation,
+        handle: image,
+        view,
+        extent,
+        mip_levels,
+        layers,
+    })
+}
+
+pub fn make_image_view(
+    device: &Device,
+    image: vk::Image,
+    format: vk::Format,
+    aspect_mask: vk::ImageAspectFlags,
+    base_mip_level: u32,
+    level_count: u32,
+) -> anyhow::Result<vk::ImageView> {
+    let view = unsafe {
+        device.create_image_view(
+            &vk::ImageViewCreateInfo::builder()
+                .image(image)
+                .view_type(vk::ImageViewType::TYPE_2D)
+                .format(format)
+                .subresource_range(vk::ImageSubresourceRange {
+                    aspect_mask,
+                    base_mip_level,
+                    level_count,
+                    base_array_layer: 0,
+                    layer_count: 1,
+                }),
+            None,
+        )?
+    };
+    Ok(view)
+}
+
 pub fn make_shader_module(code: &[u8], vk: &Vk) -> Result<vk::ShaderModule> {
     let spir_v = ash::util::read_spv(&mut std::io::Cursor::new(code))?;
 
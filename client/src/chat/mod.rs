@@ -0,0 +1,119 @@
+// The chat as far as the client is concerned: what's been said so far, and the line
+// being typed. Press T to start typing (or / for a command), Enter to send, Escape
+// to give up on it, Tab to have the server complete the last word. There's no text
+// rendering yet, so everything ends up in the log.
+
+use std::collections::VecDeque;
+
+use log::info;
+use shared::net::{ChatMessage, MAX_CHAT_MESSAGE_LEN};
+
+mod tests;
+
+/// How many messages are kept around.
+pub const SCROLLBACK_LEN: usize = 100;
+
+/// What to do with the line being typed, see `Chat::on_character()`.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ChatAction {
+    Send(String),
+    /// Ask the server for completions, which go to `Chat::on_completions()`
+    Complete(String),
+}
+
+pub struct Chat {
+    history: VecDeque<ChatMessage>,
+    // What's being typed, None when not typing
+    input: Option<String>,
+}
+
+impl Default for Chat {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Chat {
+    pub fn new() -> Self {
+        Self {
+            history: VecDeque::with_capacity(SCROLLBACK_LEN),
+            input: None,
+        }
+    }
+
+    /// Forgets the oldest message when there are too many.
+    pub fn receive(&mut self, message: ChatMessage) {
+        info!("[Chat] {message}");
+        if self.history.len() == SCROLLBACK_LEN {
+            self.history.pop_front();
+        }
+        self.history.push_back(message);
+    }
+
+    /// Oldest first.
+    pub fn history(&self) -> impl DoubleEndedIterator<Item = &ChatMessage> {
+        self.history.iter()
+    }
+
+    pub fn typing(&self) -> bool {
+        self.input.is_some()
+    }
+
+    pub fn cancel_typing(&mut self) {
+        self.input = None;
+    }
+
+    /// From `WindowEvent::ReceivedCharacter`. Returns the line to send once Enter is
+    /// pressed, or to complete on Tab. Anything past `MAX_CHAT_MESSAGE_LEN` is dropped.
+    pub fn on_character(&mut self, c: char) -> Option<ChatAction> {
+        let Some(input) = &mut self.input else {
+            match c {
+                't' | 'T' => self.input = Some(String::new()),
+                '/' => self.input = Some("/".to_owned()),
+                _ => {}
+            }
+            return None;
+        };
+
+        match c {
+            '\r' | '\n' => {
+                let line = self.input.take().unwrap();
+                return (!line.trim().is_empty()).then_some(ChatAction::Send(line));
+            }
+            '\t' => return Some(ChatAction::Complete(input.clone())),
+            '\u{8}' => {
+                input.pop();
+            }
+            c if !c.is_control() && input.len() + c.len_utf8() <= MAX_CHAT_MESSAGE_LEN => input.push(c),
+            _ => {}
+        }
+        None
+    }
+
+    /// The server's answer to `ChatAction::Complete`. The last word gets as far as all
+    /// the suggestions agree, and if there's more than one they're listed.
+    pub fn on_completions(&mut self, for_input: &str, suggestions: &[Box<str>]) {
+        // Too late, something's been typed since
+        let Some(input) = self.input.as_mut().filter(|input| input.as_str() == for_input) else {
+            return;
+        };
+        let Some((first, rest)) = suggestions.split_first() else {
+            return;
+        };
+
+        let common = rest.iter().fold(first.len(), |common, suggestion| {
+            common.min(first.bytes().zip(suggestion.bytes()).take_while(|(a, b)| a == b).count())
+        });
+        // Without cutting a character in half
+        let common = (0..=common).rev().find(|&i| first.is_char_boundary(i)).unwrap();
+        let word_start = input.rfind(' ').map_or(0, |i| i + 1);
+        if common > input.len() - word_start {
+            input.truncate(word_start);
+            input.push_str(&first[..common]);
+        }
+
+        if !rest.is_empty() {
+            info!("[Chat] {}", suggestions.join(" "));
+        }
+    }
+}
@@ -0,0 +1,44 @@
+#![cfg(test)]
+
+use shared::net::ChatMessage;
+
+use super::{Chat, ChatAction, SCROLLBACK_LEN};
+
+#[test]
+fn typing_and_scrollback() {
+    let mut chat = Chat::new();
+    assert_eq!(chat.on_character('x'), None);
+    assert!(!chat.typing());
+
+    let typed: Vec<_> = "thi!\u{8}\u{8}ey\r".chars().filter_map(|c| chat.on_character(c)).collect();
+    assert_eq!(typed, [ChatAction::Send("hey".to_owned())]);
+    assert!(!chat.typing());
+
+    // Nothing to send
+    chat.on_character('t');
+    assert_eq!(chat.on_character('\r'), None);
+
+    for i in 0..SCROLLBACK_LEN + 5 {
+        chat.receive(ChatMessage::from_server(i.to_string()));
+    }
+    assert_eq!(chat.history().count(), SCROLLBACK_LEN);
+    assert_eq!(&*chat.history().next().unwrap().text, "5");
+    assert_eq!(&*chat.history().next_back().unwrap().text, (SCROLLBACK_LEN + 4).to_string());
+}
+
+#[test]
+fn completions_fill_in_the_last_word() {
+    let mut chat = Chat::new();
+    for c in "/kick al".chars() {
+        chat.on_character(c);
+    }
+    assert_eq!(chat.on_character('\t'), Some(ChatAction::Complete("/kick al".to_owned())));
+
+    // Only as far as they agree
+    chat.on_completions("/kick al", &["alice".into(), "alicia".into()]);
+    assert_eq!(chat.on_character('\t'), Some(ChatAction::Complete("/kick alic".to_owned())));
+    chat.on_completions("/kick alic", &["alice".into()]);
+    // Not for this line anymore
+    chat.on_completions("/kick alic", &["alicia".into()]);
+    assert_eq!(chat.on_character('\r'), Some(ChatAction::Send("/kick alice".to_owned())));
+}
@@ -1,12 +1,13 @@
 pub mod state;
 
 use glam::{Vec3, Vec2, vec2};
-use log::debug;
-use netcode::login::LoginResponse;
+use log::{debug, info, warn};
+use netcode::{login::LoginResponse, ServerConnection};
+use shared::net::{ChatEvent, GameEvent};
 use renderer::game_renderer::GameRenderer;
 use winit::{event::{Event, WindowEvent, ElementState, MouseButton, DeviceEvent}, dpi::LogicalPosition};
 
-use crate::{views::{StateChange, exit}, resources::Resources, world::chunk::WorldBlockPosExt, util::input::Key};
+use crate::{views::{StateChange, exit}, resources::Resources, textures::TexturePack, world::chunk::WorldBlockPosExt, util::input::Key, chat::ChatAction};
 
 use self::state::GameState;
 
@@ -15,16 +16,20 @@ pub struct GameView {
     renderer: GameRenderer,
     focused: bool,
     mouse_motion_accumulator: Vec2,
+    // Position and head rotation the server last heard about
+    sent_movement: Option<(Vec3, Vec2)>,
 }
 
 impl GameView {
-    pub fn new(login_response: LoginResponse, res: &mut Resources) -> anyhow::Result<Self> {
+    pub fn new(login_response: LoginResponse, connection: ServerConnection, res: &mut Resources) -> anyhow::Result<Self> {
         let chunk_pos = login_response.position.as_ivec3().to_chunk_pos();
+        let textures = TexturePack::load(&res.assets, &res.face_textures)?;
         Ok(Self {
-            state: GameState::new(login_response, res),
-            renderer: GameRenderer::new(chunk_pos, &mut res.renderer)?,
+            state: GameState::new(login_response, connection, res),
+            renderer: GameRenderer::new(chunk_pos, &res.assets, textures.size, &textures.pixels, &mut res.renderer)?,
             focused: false,
-            mouse_motion_accumulator: Vec2::ZERO
+            mouse_motion_accumulator: Vec2::ZERO,
+            sent_movement: None,
         })
     }
 }
@@ -37,22 +42,46 @@ impl GameView {
 
     pub fn on_exit_view(&mut self, _res: &mut Resources) -> anyhow::Result<()> {
         debug!("Leaving game view");
+        self.state.connection.stop();
         Ok(())
     }
 
     pub fn on_update(&mut self, res: &mut Resources) -> Option<Box<StateChange>> {
         if res.input.keyboard.just_pressed(Key::Escape) {
-            if !self.focused {
+            if self.state.chat.typing() {
+                self.state.chat.cancel_typing();
+            } else if !self.focused {
                 return exit();
+            } else {
+                self.focused = false;
+                res.window_handle.set_cursor_visible(true);
             }
-            self.focused = false;
-            res.window_handle.set_cursor_visible(true);
         }
 
-        if self.focused {
+        if self.focused && !self.state.chat.typing() {
             self.do_player_movement(res);
         }
 
+        if !self.state.connection.open() {
+            warn!("Lost connection to the server");
+            return exit();
+        }
+
+        while let Some(event) = self.state.connection.poll_chat() {
+            match event {
+                ChatEvent::Message(message) => self.state.chat.receive(message),
+                ChatEvent::Completions { input, suggestions } => self.state.chat.on_completions(&input, &suggestions),
+            }
+        }
+        while let Some(event) = self.state.connection.poll_game_event() {
+            match event {
+                GameEvent::Teleport(position) => self.state.camera.move_to(position),
+            }
+        }
+        self.send_movement();
+        self.update_chunks(res);
+        self.reload_changed_assets(res);
+
         self.state.camera.update();
         self.renderer.render(&self.state.camera, &mut res.renderer).unwrap();
         None
@@ -70,6 +99,15 @@ impl GameView {
                     _ = res.window_handle.set_cursor_position(LogicalPosition::<f32>::from((res.window_size.w_h_f32 * 0.5).to_array()));
                 }
             }
+            else if let WindowEvent::ReceivedCharacter(c) = event {
+                match self.state.chat.on_character(c) {
+                    Some(ChatAction::Send(line)) if !self.state.connection.send_chat(&line) => {
+                        warn!("Couldn't send the chat message");
+                    },
+                    Some(ChatAction::Complete(input)) => { self.state.connection.request_completions(&input); },
+                    _ => {}
+                }
+            }
             else if let WindowEvent::CursorMoved { .. } = event {
                 if self.focused {
                     _ = res.window_handle.set_cursor_position(LogicalPosition::<f32>::from((res.window_size.w_h_f32 * 0.5).to_array()));
@@ -83,6 +121,73 @@ impl GameView {
         None
     }
 
+    fn update_chunks(&mut self, res: &mut Resources) {
+        let state = &mut self.state;
+        let chunks = &mut state.dimension.chunks;
+
+        for chunk_pos in state.chunk_loader.update_center(state.camera.pos(), chunks, &mut state.connection) {
+            state.mesher.forget(chunk_pos);
+            self.renderer.world.remove_chunk_mesh(chunk_pos);
+        }
+        let received = state.chunk_loader.receive(chunks, &mut state.connection);
+        for (chunk_pos, replaced) in &received {
+            state.light.on_chunk_loaded(chunks, *chunk_pos, replaced.as_deref(), &res.blocks);
+        }
+        // Only once all the light has spread, which can be into chunks that were meshed already
+        state.mesher.on_chunks_loaded(
+            received.into_iter().map(|(chunk_pos, _)| chunk_pos),
+            state.light.take_changed_chunks(),
+            chunks,
+            &res.thread_pool,
+        );
+
+        let mut meshes = state.mesher.finished().collect::<Vec<_>>().into_iter();
+        while let Some((chunk_pos, mesh)) = meshes.next() {
+            if let Err(e) = self.renderer.world.update_chunk_mesh(chunk_pos, mesh.view(), &mut res.renderer) {
+                // Try again next frame
+                debug!("Postponing mesh uploads: {e}");
+                state.mesher.requeue(chunk_pos, mesh);
+                meshes.for_each(|(chunk_pos, mesh)| state.mesher.requeue(chunk_pos, mesh));
+                break;
+            }
+        }
+
+        for (chunk_pos, within) in self.renderer.world.take_evicted() {
+            state.mesher.on_evicted(chunk_pos, within);
+        }
+        let player_chunk_pos = state.camera.pos().floor().as_ivec3().to_chunk_pos();
+        state.mesher.remesh_evicted(player_chunk_pos, chunks, &res.thread_pool);
+    }
+
+    // Hot reloading, only does anything in debug builds (see Assets::poll_changes())
+    fn reload_changed_assets(&mut self, res: &mut Resources) {
+        let changed = res.assets.poll_changes();
+        if changed.iter().any(|path| path.starts_with("shaders/")) {
+            info!("Reloading shaders");
+            if let Err(e) = self.renderer.reload_shaders(&res.renderer, &res.assets) {
+                warn!("Couldn't reload the shaders: {e:#}");
+            }
+        }
+        if changed.iter().any(|path| path.starts_with("textures/")) {
+            info!("Reloading textures");
+            let reloaded = TexturePack::load(&res.assets, &res.face_textures)
+                .and_then(|textures| self.renderer.reload_textures(&mut res.renderer, textures.size, &textures.pixels));
+            if let Err(e) = reloaded {
+                warn!("Couldn't reload the textures: {e:#}");
+            }
+        }
+    }
+
+    // The server streams chunks around wherever it was told the player is
+    fn send_movement(&mut self) {
+        let camera = &self.state.camera;
+        let movement = (camera.pos(), vec2(camera.yaw(), camera.pitch()));
+        // Otherwise it's tried again next frame
+        if self.sent_movement != Some(movement) && self.state.connection.send_movement(movement.0, movement.1) {
+            self.sent_movement = Some(movement);
+        }
+    }
+
     fn do_player_movement(&mut self, res: &mut Resources) {
         let keyboard = &mut res.input.keyboard;
         
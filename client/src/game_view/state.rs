@@ -1,17 +1,39 @@
-use netcode::login::LoginResponse;
+use glam::Vec3Swizzles;
+use netcode::{login::LoginResponse, ServerConnection};
 use renderer::camera::Camera;
+use shared::world::light::LightEngine;
 
-use crate::resources::Resources;
+use crate::{
+    chat::Chat,
+    resources::Resources,
+    world::{chunk::WorldBlockPosExt, chunk_loader::ChunkLoader, chunk_map::Chunks, dimension::Dimension, ecs::ECS, mesher::{Mesher, MeshingMode}},
+};
 
 
 pub struct GameState {
-    pub camera: Camera
+    pub camera: Camera,
+    pub connection: ServerConnection,
+    pub dimension: Dimension,
+    pub chunk_loader: ChunkLoader,
+    pub mesher: Mesher,
+    pub light: LightEngine,
+    pub chat: Chat,
 }
 
 impl GameState {
-    pub fn new(login_response: LoginResponse, res: &mut Resources) -> Self {
+    pub fn new(login_response: LoginResponse, connection: ServerConnection, res: &mut Resources) -> Self {
+        let chunk_pos = login_response.position.floor().as_ivec3().to_chunk_pos();
         Self {
-            camera: Camera::new(login_response.position, (0.0, 0.0), f32::to_radians(80.0), res.window_size.w_h_f32)
+            camera: Camera::new(login_response.position, (0.0, 0.0), f32::to_radians(80.0), res.window_size.w_h_f32),
+            connection,
+            dimension: Dimension {
+                chunks: Chunks::new(chunk_pos.xz()),
+                entities: ECS::new(),
+            },
+            chunk_loader: ChunkLoader::new(),
+            mesher: Mesher::new(res.blocks.clone(), res.face_textures.clone(), MeshingMode::default()),
+            light: LightEngine::new(),
+            chat: Chat::new(),
         }
     }
-}
\ No newline at end of file
+}
@@ -5,9 +5,11 @@ use winit::{
     event_loop::{ControlFlow, EventLoop}, event::{Event, WindowEvent},
 };
 
+pub mod chat;
 pub mod game_view;
 pub mod main_menu_view;
 pub mod resources;
+pub mod textures;
 pub mod util;
 pub mod views;
 pub mod world;
@@ -18,7 +20,7 @@ fn main() {
     let event_loop = EventLoop::new();
 
     let mut resources = resources::init_resources("Game", &event_loop);
-    let mut view = View::main_menu();
+    let mut view = View::main_menu(&resources);
     view.on_enter(&mut resources).unwrap();
 
     event_loop.run(move |event, _, flow| {
@@ -4,19 +4,21 @@ use winit::event::Event;
 
 use crate::{views::{StateChange, switch_to, View}, resources::Resources};
 
-fn start_connecting() -> Connecting {
-    netcode::try_connect("127.0.0.1:29477".parse().unwrap(), "Player1".into())
+fn start_connecting(block_checksum: u64) -> Connecting {
+    netcode::try_connect("127.0.0.1:29477".parse().unwrap(), "Player1".into(), block_checksum)
 }
 
 pub struct MainMenuView {
-    connecting: Connecting
+    connecting: Connecting,
+    block_checksum: u64,
 }
 
 impl MainMenuView {
-    pub fn new() -> Self {
+    pub fn new(block_checksum: u64) -> Self {
         Self {
             // Todo obviously only start connecting once username and address have been entered
-            connecting: start_connecting(),
+            connecting: start_connecting(block_checksum),
+            block_checksum,
         }
     }
 }
@@ -35,13 +37,13 @@ impl MainMenuView {
     pub fn on_update(&mut self, res: &mut Resources) -> Option<Box<StateChange>> {
         match self.connecting.tick() {
             Ok(None) => {},
-            Ok(Some((response, _connection))) => {
+            Ok(Some((response, connection))) => {
                 info!("Connected! {response:?}");
-                return switch_to(View::game(response, res).unwrap());
+                return switch_to(View::game(response, connection, res).unwrap());
             }
             Err(e) => {
                 warn!("Error: {e}, retrying...");
-                self.connecting = start_connecting();
+                self.connecting = start_connecting(self.block_checksum);
             }
         }
         None
@@ -1,11 +1,13 @@
-use std::time::Instant;
+use std::{sync::Arc, time::Instant};
 
+use common::assets::Assets;
 use glam::{ivec2, vec2};
 use rayon::{ThreadPool, ThreadPoolBuilder};
 use renderer::RendererBase;
+use shared::world::registry::BlockRegistry;
 use winit::{event_loop::EventLoop, dpi::LogicalPosition, window::{WindowBuilder, Window}, event::{Event, WindowEvent}};
 
-use crate::util::{self, input::{Keyboard, Mouse}};
+use crate::{textures::FaceTextures, util::{self, input::{Keyboard, Mouse}}};
 
 
 /// Resources that persist during the entire runtime of the
@@ -20,6 +22,9 @@ pub struct Resources {
     pub input: input::Resources,
     pub thread_pool: ThreadPool,
     pub metrics: metrics::Resources,
+    pub assets: Assets,
+    pub blocks: Arc<BlockRegistry>,
+    pub face_textures: Arc<FaceTextures>,
 }
 
 pub mod core {
@@ -92,6 +97,9 @@ pub fn init_resources(title: &'static str, event_loop: &EventLoop<()>) -> Resour
 
     let renderer = RendererBase::new(&window);
 
+    let blocks = BlockRegistry::builtin();
+    let face_textures = FaceTextures::new(&blocks);
+
     Resources {
         time: core::Time {
             at_launch: now,
@@ -122,6 +130,9 @@ pub fn init_resources(title: &'static str, event_loop: &EventLoop<()>) -> Resour
                 last_updated: now,
             },
         },
+        assets: Assets::find().unwrap(),
+        blocks: Arc::new(blocks),
+        face_textures: Arc::new(face_textures),
     }
 }
 
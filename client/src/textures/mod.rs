@@ -0,0 +1,146 @@
+// Block textures. Every texture named in the block registry becomes a layer of the
+// renderer's texture array, and faces refer to their texture by that layer. Which
+// layer is which only depends on the registry, the pixels come from the texture pack
+// in the assets: a <name>.png in TEXTURE_PACK_DIR for every texture.
+
+use std::{collections::HashMap, io::{ErrorKind, Read}};
+
+use anyhow::{bail, ensure, Context};
+use common::assets::Assets;
+use log::warn;
+use shared::world::{block::BlockId, registry::BlockRegistry};
+
+mod tests;
+
+/// In the assets.
+pub const TEXTURE_PACK_DIR: &str = "textures/blocks";
+
+// For the placeholders, when there's nothing to go by
+const DEFAULT_TEXTURE_SIZE: u32 = 16;
+
+/// The texture layer of every face of every block.
+pub struct FaceTextures {
+    names: Vec<Box<str>>,
+    // Indexed by the raw block id, faces in the order of `BlockDef::textures`
+    layers: Box<[[u16; 6]]>,
+}
+
+impl FaceTextures {
+    /// Layers are numbered in the order the textures first show up in the registry. Air
+    /// is never drawn, so its textures don't get one.
+    pub fn new(registry: &BlockRegistry) -> Self {
+        let mut names = Vec::new();
+        let mut by_name = HashMap::new();
+        let layers = registry
+            .iter()
+            .map(|(id, def)| {
+                if id == BlockId::AIR {
+                    return [0; 6];
+                }
+                def.textures.clone().map(|name| {
+                    *by_name.entry(name.clone()).or_insert_with(|| {
+                        names.push(name);
+                        names.len() as u16 - 1
+                    })
+                })
+            })
+            .collect();
+
+        Self { names, layers }
+    }
+
+    /// `face` in the order of `BlockDef::textures`. Unknown blocks get the first texture.
+    #[inline]
+    pub fn layer(&self, id: BlockId, face: usize) -> u16 {
+        self.layers.get(id.raw() as usize).map_or(0, |faces| faces[face])
+    }
+
+    /// In the order of the layers.
+    pub fn names(&self) -> &[Box<str>] {
+        &self.names
+    }
+}
+
+/// The pixels of every texture of `FaceTextures`, ready for the renderer.
+pub struct TexturePack {
+    /// Width and height of every texture
+    pub size: u32,
+    /// RGBA8, the layers one after the other
+    pub pixels: Vec<u8>,
+}
+
+impl TexturePack {
+    /// All textures have to be the same size. Missing ones are replaced by a placeholder,
+    /// broken ones are an error.
+    pub fn load(assets: &Assets, textures: &FaceTextures) -> anyhow::Result<Self> {
+        let mut images = Vec::with_capacity(textures.names().len());
+        for name in textures.names() {
+            let path = format!("{TEXTURE_PACK_DIR}/{name}.png");
+            let image = match assets.read(&path) {
+                Ok(bytes) => Some(decode_png(&*bytes).with_context(|| format!("Couldn't load {path}"))?),
+                Err(e) if e.kind() == ErrorKind::NotFound => {
+                    warn!("Texture {path} is missing");
+                    None
+                }
+                Err(e) => return Err(e).with_context(|| format!("Couldn't read {path}")),
+            };
+            images.push(image);
+        }
+
+        let size = images.iter().flatten().next().map_or(DEFAULT_TEXTURE_SIZE, |(size, _)| *size);
+        let mut pixels = Vec::with_capacity(images.len() * (size * size * 4) as usize);
+        for (name, image) in textures.names().iter().zip(images) {
+            match image {
+                Some((image_size, image)) => {
+                    ensure!(image_size == size, "Texture {name} is {image_size}x{image_size}, the others are {size}x{size}");
+                    pixels.extend(image);
+                }
+                None => pixels.extend(placeholder(size)),
+            }
+        }
+
+        Ok(Self { size, pixels })
+    }
+}
+
+// Decodes to RGBA8, and returns the size with the pixels. Only square power of two
+// sizes, for the mip chain to work out.
+fn decode_png(reader: impl Read) -> anyhow::Result<(u32, Vec<u8>)> {
+    let mut decoder = png::Decoder::new(reader);
+    // No palettes and 8 bits per channel, so that only the number of channels varies
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info()?;
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buf)?;
+    ensure!(
+        info.width == info.height && info.width.is_power_of_two(),
+        "{}x{} isn't a square power of two size",
+        info.width,
+        info.height
+    );
+
+    let pixels = &buf[..info.buffer_size()];
+    let rgba = match info.color_type {
+        png::ColorType::Rgba => pixels.to_vec(),
+        png::ColorType::Rgb => pixels.chunks_exact(3).flat_map(|p| [p[0], p[1], p[2], 255]).collect(),
+        png::ColorType::GrayscaleAlpha => pixels.chunks_exact(2).flat_map(|p| [p[0], p[0], p[0], p[1]]).collect(),
+        png::ColorType::Grayscale => pixels.iter().flat_map(|&p| [p, p, p, 255]).collect(),
+        png::ColorType::Indexed => bail!("Palette wasn't expanded"),
+    };
+    Ok((info.width, rgba))
+}
+
+// The classic magenta and black checkerboard, hard to miss
+fn placeholder(size: u32) -> Vec<u8> {
+    let half = (size / 2).max(1);
+    (0..size * size)
+        .flat_map(|i| {
+            let (x, y) = (i % size, i / size);
+            if (x / half + y / half) & 1 == 0 {
+                [255, 0, 255, 255]
+            } else {
+                [0, 0, 0, 255]
+            }
+        })
+        .collect()
+}
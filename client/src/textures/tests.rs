@@ -0,0 +1,53 @@
+#![cfg(test)]
+
+use std::fs::File;
+
+use common::assets::Assets;
+use shared::world::registry::BlockRegistry;
+
+use super::{FaceTextures, TexturePack, TEXTURE_PACK_DIR};
+
+#[test]
+fn faces_map_to_shared_layers() {
+    let registry = BlockRegistry::builtin();
+    let textures = FaceTextures::new(&registry);
+    let layer_of = |name: &str| textures.names().iter().position(|n| &**n == name).unwrap() as u16;
+
+    let grass = registry.id_of("grass").unwrap();
+    assert_eq!(textures.layer(grass, 0), layer_of("grass_side"));
+    assert_eq!(textures.layer(grass, 2), layer_of("grass_top"));
+    assert_eq!(textures.layer(grass, 3), layer_of("dirt"));
+    assert_eq!(textures.layer(registry.id_of("dirt").unwrap(), 3), layer_of("dirt"));
+
+    let slab = registry.id_of("stone_slab").unwrap();
+    assert_eq!(textures.layer(slab, 4), layer_of("stone"));
+    assert!(!textures.names().iter().any(|name| &**name == "air"));
+
+    // What the game ships with is complete
+    let assets = Assets::from_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/assets")).unwrap();
+    let pack = TexturePack::load(&assets, &textures).unwrap();
+    assert_eq!(pack.pixels.len(), textures.names().len() * (pack.size * pack.size * 4) as usize);
+}
+
+#[test]
+fn missing_textures_get_placeholders() {
+    let registry = BlockRegistry::builtin();
+    let textures = FaceTextures::new(&registry);
+
+    let dir = std::env::temp_dir().join(format!("texture_pack_test_{}", std::process::id()));
+    std::fs::create_dir_all(dir.join(TEXTURE_PACK_DIR)).unwrap();
+    // A 4x4 RGB one, the rest are missing
+    let mut encoder = png::Encoder::new(File::create(dir.join(TEXTURE_PACK_DIR).join("stone.png")).unwrap(), 4, 4);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.write_header().unwrap().write_image_data(&[100; 4 * 4 * 3]).unwrap();
+
+    let pack = TexturePack::load(&Assets::from_dir(&dir).unwrap(), &textures);
+    std::fs::remove_dir_all(&dir).unwrap();
+    let pack = pack.unwrap();
+
+    assert_eq!(pack.size, 4);
+    let layer = |i: usize| &pack.pixels[i * 64..(i + 1) * 64];
+    let stone = textures.names().iter().position(|name| &**name == "stone").unwrap();
+    assert!(layer(stone).chunks(4).all(|pixel| pixel == [100, 100, 100, 255]));
+    assert_eq!(layer(stone + 1)[..8], [255, 0, 255, 255, 255, 0, 255, 255]);
+}
@@ -1,4 +1,4 @@
-use netcode::login::LoginResponse;
+use netcode::{login::LoginResponse, ServerConnection};
 use winit::event::Event;
 
 use crate::{main_menu_view::MainMenuView, game_view::GameView, resources::Resources};
@@ -22,12 +22,12 @@ pub enum View {
 }
 
 impl View {
-    pub fn main_menu() -> Box<View> {
-        Box::new(View::MainMenu(MainMenuView::new()))
+    pub fn main_menu(res: &Resources) -> Box<View> {
+        Box::new(View::MainMenu(MainMenuView::new(res.blocks.checksum())))
     }
 
-    pub fn game(login_response: LoginResponse, res: &mut Resources) -> anyhow::Result<Box<View>> {
-        Ok(Box::new(View::Game(GameView::new(login_response, res)?)))
+    pub fn game(login_response: LoginResponse, connection: ServerConnection, res: &mut Resources) -> anyhow::Result<Box<View>> {
+        Ok(Box::new(View::Game(GameView::new(login_response, connection, res)?)))
     }
 }
 
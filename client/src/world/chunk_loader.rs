@@ -0,0 +1,112 @@
+use std::collections::HashSet;
+
+use glam::{IVec3, Vec3};
+use log::warn;
+use netcode::ServerConnection;
+use shared::{
+    net::{read_ivec3, ChunkRequest},
+    serialization::ByteReader,
+};
+
+use super::{
+    chunk::{Chunk, WorldBlockPosExt},
+    chunk_map::{Chunks, WORLD_HEIGHT_CHUNKS},
+};
+
+/// Asks the server for the chunks around the player, and puts them into `Chunks` as
+/// they arrive. Whenever the player crosses a chunk border, chunks that went out of
+/// range are cancelled (and unloaded) and the newly visible ones requested.
+pub struct ChunkLoader {
+    center: Option<IVec3>,
+    // Every chunk requested and not cancelled since, whether it has arrived yet or not
+    requested: HashSet<IVec3>,
+}
+
+impl ChunkLoader {
+    /// Horizontal radius, in chunks. Must stay below 32, `Chunks` can't hold more.
+    pub const LOAD_DISTANCE: i32 = 12;
+
+    pub fn new() -> Self {
+        Self {
+            center: None,
+            requested: HashSet::new(),
+        }
+    }
+
+    /// Returns the positions of the chunks that went out of range (and were unloaded, if
+    /// they had arrived).
+    pub fn update_center(&mut self, player_pos: Vec3, chunks: &mut Chunks, connection: &mut ServerConnection) -> Vec<IVec3> {
+        let center = player_pos.floor().as_ivec3().to_chunk_pos();
+        if self.center == Some(center) {
+            return Vec::new();
+        }
+        self.center = Some(center);
+
+        let wanted: HashSet<IVec3> = chunks_in_range(center).collect();
+
+        let unloaded: Vec<IVec3> = self.requested.difference(&wanted).copied().collect();
+        for &chunk_pos in &unloaded {
+            self.requested.remove(&chunk_pos);
+            *chunks.get_at_mut(chunk_pos) = None;
+        }
+
+        let mut requested: Vec<IVec3> = wanted.difference(&self.requested).copied().collect();
+        requested.sort_unstable_by_key(|&pos| (pos - center).dot(pos - center));
+        self.requested.extend(requested.iter().copied());
+
+        // Split into as many messages as it takes
+        let (mut requested, mut cancelled) = (&requested[..], &unloaded[..]);
+        while !requested.is_empty() || !cancelled.is_empty() {
+            let request_count = requested.len().min(ChunkRequest::MAX_POSITIONS);
+            let cancel_count = cancelled.len().min(ChunkRequest::MAX_POSITIONS - request_count);
+
+            connection.request_chunks(&ChunkRequest {
+                center,
+                requested: requested[..request_count].to_vec(),
+                cancelled: cancelled[..cancel_count].to_vec(),
+            });
+
+            requested = &requested[request_count..];
+            cancelled = &cancelled[cancel_count..];
+        }
+
+        unloaded
+    }
+
+    /// Inserts all chunks received since the last call into `chunks`, and returns their
+    /// positions, along with the chunks they replaced (when the server sent one again).
+    pub fn receive(&mut self, chunks: &mut Chunks, connection: &mut ServerConnection) -> Vec<(IVec3, Option<Box<Chunk>>)> {
+        let mut received = Vec::new();
+        while let Some(bytes) = connection.poll_chunk() {
+            let mut reader = ByteReader::new(&bytes);
+            if !reader.has_n_more(12) {
+                warn!("Received truncated chunk ({} bytes)", bytes.len());
+                continue;
+            }
+            let chunk_pos = read_ivec3(&mut reader);
+
+            let Some(chunk) = Chunk::decode(&mut reader) else {
+                warn!("Received malformed chunk at {chunk_pos}");
+                continue;
+            };
+
+            // Might have been cancelled while it was on its way
+            if self.requested.contains(&chunk_pos) {
+                let replaced = chunks.get_at_mut(chunk_pos).replace(chunk);
+                received.push((chunk_pos, replaced));
+            }
+        }
+        received
+    }
+}
+
+fn chunks_in_range(center: IVec3) -> impl Iterator<Item = IVec3> {
+    const R: i32 = ChunkLoader::LOAD_DISTANCE;
+
+    (-R..=R)
+        .flat_map(|dx| (-R..=R).map(move |dz| (dx, dz)))
+        .filter(|&(dx, dz)| dx * dx + dz * dz <= R * R)
+        .flat_map(move |(dx, dz)| {
+            (0..WORLD_HEIGHT_CHUNKS as i32).map(move |y| IVec3::new(center.x + dx, y, center.z + dz))
+        })
+}
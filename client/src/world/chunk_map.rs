@@ -1,4 +1,5 @@
 use glam::{IVec2, IVec3, Vec3Swizzles};
+use shared::world::light::LightWorld;
 
 use super::{chunk::{CHUNK_SIZE, Chunk}};
 
@@ -38,3 +39,23 @@ impl Chunks {
         ((grid_xz.x * 64 * 16) | (grid_xz.y * 16) | (chunk_pos.y as u32 & 15)) as usize
     }
 }
+
+impl LightWorld for Chunks {
+    const HEIGHT_CHUNKS: i32 = WORLD_HEIGHT_CHUNKS as i32;
+
+    // get_at() wraps around vertically, so the ones above and below the world need
+    // to be ruled out here
+    fn chunk(&self, chunk_pos: IVec3) -> Option<&Chunk> {
+        if !(0..Self::HEIGHT_CHUNKS).contains(&chunk_pos.y) {
+            return None;
+        }
+        self.get_at(chunk_pos)
+    }
+
+    fn chunk_mut(&mut self, chunk_pos: IVec3) -> Option<&mut Chunk> {
+        if !(0..Self::HEIGHT_CHUNKS).contains(&chunk_pos.y) {
+            return None;
+        }
+        self.get_at_mut(chunk_pos).as_deref_mut()
+    }
+}
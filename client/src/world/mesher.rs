@@ -0,0 +1,501 @@
+// Turns chunks into meshes for the renderer.
+//
+// Whether a face on the chunk border is visible depends on the neighboring chunk,
+// so a chunk only gets meshed once all of its neighbors have arrived too. The blocks
+// and their light (plus the layer of neighbor blocks around them) are copied out on the main thread,
+// because the chunks themselves can't be shared with the worker threads, and the
+// actual meshing happens on the thread pool.
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{
+        mpsc::{channel, Receiver, Sender},
+        Arc,
+    },
+};
+
+use glam::{IVec3, Vec3Swizzles};
+use rayon::ThreadPool;
+use renderer::game_renderer::world::{ChunkMeshView, FaceData, Facing};
+use shared::world::{block::BlockId, fluid::FluidState, light::Light, registry::BlockRegistry};
+
+use crate::textures::FaceTextures;
+
+use super::{
+    block::Block,
+    chunk::{CHUNK_SIZE, CHUNK_SIZE_LOG2},
+    chunk_map::{Chunks, WORLD_HEIGHT_CHUNKS},
+};
+
+const PADDED_SIZE: usize = CHUNK_SIZE + 2;
+const PADDED_VOLUME: usize = PADDED_SIZE * PADDED_SIZE * PADDED_SIZE;
+
+// In the order the renderer wants the faces grouped in
+pub(super) const DIRECTIONS: [(IVec3, Facing); 6] = [
+    (IVec3::X, Facing::Px),
+    (IVec3::NEG_X, Facing::Nx),
+    (IVec3::Y, Facing::Py),
+    (IVec3::NEG_Y, Facing::Ny),
+    (IVec3::Z, Facing::Pz),
+    (IVec3::NEG_Z, Facing::Nz),
+];
+
+// The corner of the block (0 or 1 on every axis) each vertex of a face is at, in the
+// order the vertex shader emits them in (see offsetCw() and offsetCcw() in there).
+// Same order of faces as DIRECTIONS.
+pub(super) const FACE_CORNERS: [[IVec3; 4]; 6] = [
+    [IVec3::new(1, 1, 1), IVec3::new(1, 0, 1), IVec3::new(1, 1, 0), IVec3::new(1, 0, 0)],
+    [IVec3::new(0, 0, 0), IVec3::new(0, 0, 1), IVec3::new(0, 1, 0), IVec3::new(0, 1, 1)],
+    [IVec3::new(1, 1, 1), IVec3::new(1, 1, 0), IVec3::new(0, 1, 1), IVec3::new(0, 1, 0)],
+    [IVec3::new(0, 0, 0), IVec3::new(1, 0, 0), IVec3::new(0, 0, 1), IVec3::new(1, 0, 1)],
+    [IVec3::new(1, 1, 1), IVec3::new(0, 1, 1), IVec3::new(1, 0, 1), IVec3::new(0, 0, 1)],
+    [IVec3::new(0, 0, 0), IVec3::new(0, 1, 0), IVec3::new(1, 0, 0), IVec3::new(1, 1, 0)],
+];
+
+/// The blocks of a chunk and their light, surrounded by a one block thick layer of
+/// the blocks in the neighboring chunks, edges and corners included (ambient occlusion
+/// needs those). Coordinates range from -1 to 16 (inclusive).
+pub struct PaddedChunk {
+    blocks: Box<[Block]>,
+    light: Box<[Light]>,
+}
+
+impl PaddedChunk {
+    /// Returns None if the chunk or any of its 26 neighbors isn't loaded. Below the world
+    /// counts as solid and dark and above it as open sky, so those don't need to be loaded.
+    pub fn gather(chunk_pos: IVec3, chunks: &Chunks) -> Option<Self> {
+        // The chunk and its neighbors, indexed by offset + 1
+        let mut neighbors = [[[Err((Block::AIR, Light::SKY)); 3]; 3]; 3];
+        for offset in neighbor_offsets().chain([IVec3::ZERO]) {
+            let neighbor_pos = chunk_pos + offset;
+            let idx = (offset + 1).as_uvec3();
+            neighbors[idx.x as usize][idx.y as usize][idx.z as usize] = if neighbor_pos.y < 0 {
+                Err((Block::STONE, Light::DARK))
+            } else if neighbor_pos.y >= WORLD_HEIGHT_CHUNKS as i32 {
+                Err((Block::AIR, Light::SKY))
+            } else {
+                Ok(chunks.get_at(neighbor_pos)?)
+            };
+        }
+
+        let mut padded = Self {
+            blocks: vec![Block::AIR; PADDED_VOLUME].into_boxed_slice(),
+            light: vec![Light::DARK; PADDED_VOLUME].into_boxed_slice(),
+        };
+
+        let size = CHUNK_SIZE as i32;
+        for x in -1..=size {
+            for y in -1..=size {
+                for z in -1..=size {
+                    let pos = IVec3::new(x, y, z);
+                    let idx = ((pos >> CHUNK_SIZE_LOG2 as i32) + 1).as_uvec3();
+                    let (block, light) = match neighbors[idx.x as usize][idx.y as usize][idx.z as usize] {
+                        Ok(chunk) => {
+                            let local = (pos & (size - 1)).as_uvec3();
+                            (chunk.get_at(local), chunk.light_at(local))
+                        }
+                        Err(filler) => filler,
+                    };
+                    padded.set(pos, block, light);
+                }
+            }
+        }
+
+        Some(padded)
+    }
+
+    #[inline(always)]
+    pub fn get(&self, pos: IVec3) -> Block {
+        self.blocks[Self::index(pos)]
+    }
+
+    #[inline(always)]
+    pub fn light(&self, pos: IVec3) -> Light {
+        self.light[Self::index(pos)]
+    }
+
+    #[inline(always)]
+    fn set(&mut self, pos: IVec3, block: Block, light: Light) {
+        self.blocks[Self::index(pos)] = block;
+        self.light[Self::index(pos)] = light;
+    }
+
+    #[inline(always)]
+    fn index(pos: IVec3) -> usize {
+        let p = (pos + 1).as_uvec3();
+        (p.x as usize * PADDED_SIZE + p.z as usize) * PADDED_SIZE + p.y as usize
+    }
+}
+
+/// Owned counterpart of `ChunkMeshView`.
+#[derive(Default)]
+pub struct ChunkMesh {
+    pub faces: Vec<FaceData>,
+    pub axis_offsets: [u32; 5],
+    pub fluid_faces: Vec<FaceData>,
+}
+
+impl ChunkMesh {
+    pub fn view(&self) -> ChunkMeshView<'_> {
+        ChunkMeshView {
+            faces: &self.faces,
+            axis_offsets: self.axis_offsets,
+            fluid_faces: &self.fluid_faces,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MeshingMode {
+    /// One face per visible block side
+    Naive,
+    /// Merges neighboring faces that look exactly the same into rectangles. Far fewer
+    /// faces for flat terrain, for a bit more work up front.
+    #[default]
+    Greedy,
+}
+
+// A face before it's packed into FaceData
+#[derive(Clone, Copy)]
+struct Quad {
+    pos: IVec3,
+    texture: u16,
+    light: [Light; 4],
+    ao: [u8; 4],
+}
+
+impl Quad {
+    // Faces with the same light and occlusion at all four corners can be merged,
+    // shading that varies across the face would get stretched
+    fn is_uniform(&self) -> bool {
+        self.light.iter().all(|&light| light == self.light[0]) && self.ao.iter().all(|&ao| ao == self.ao[0])
+    }
+
+    fn looks_like(&self, other: &Quad) -> bool {
+        self.texture == other.texture && self.light == other.light && self.ao == other.ao
+    }
+
+    fn pack(&self, facing: Facing) -> FaceData {
+        FaceData::new(self.pos.as_uvec3(), facing, self.texture).with_shading(self.light.map(Light::raw), self.ao)
+    }
+}
+
+/// Emits a face for every side of a non-air block that isn't covered by an opaque block,
+/// with its texture, and smooth lighting and ambient occlusion at its vertices. Fluids (including the water
+/// in waterlogged blocks) go in a separate list, for the translucent pass, and only have
+/// faces where they don't touch more of the fluid. Only the solid faces get merged in
+/// greedy mode, there aren't that many fluid faces anyway.
+pub fn build_mesh(blocks: &PaddedChunk, registry: &BlockRegistry, textures: &FaceTextures, mode: MeshingMode) -> ChunkMesh {
+    let mut groups: [Vec<Quad>; 6] = Default::default();
+    let mut fluid_faces = Vec::new();
+
+    let size = CHUNK_SIZE as i32;
+    for x in 0..size {
+        for y in 0..size {
+            for z in 0..size {
+                let pos = IVec3::new(x, y, z);
+                let block = blocks.get(pos);
+                if block == Block::AIR {
+                    continue;
+                }
+
+                if FluidState::of(block).is_some() {
+                    for (face, (dir, facing)) in DIRECTIONS.into_iter().enumerate() {
+                        let neighbor = blocks.get(pos + dir);
+                        if FluidState::of(neighbor).is_none() && !registry.is_opaque(neighbor.id()) {
+                            let (light, ao) = face_shading(blocks, registry, pos, face);
+                            let texture = textures.layer(BlockId::WATER, face);
+                            fluid_faces.push(Quad { pos, texture, light, ao }.pack(facing));
+                        }
+                    }
+                }
+                // Nothing solid in there
+                if block.id() == BlockId::WATER {
+                    continue;
+                }
+
+                for (face, (group, (dir, _))) in groups.iter_mut().zip(DIRECTIONS).enumerate() {
+                    if !registry.is_opaque(blocks.get(pos + dir).id()) {
+                        let (light, ao) = face_shading(blocks, registry, pos, face);
+                        group.push(Quad { pos, texture: textures.layer(block.id(), face), light, ao });
+                    }
+                }
+            }
+        }
+    }
+
+    let groups: Vec<Vec<FaceData>> = (groups.iter().enumerate())
+        .map(|(face, quads)| match mode {
+            MeshingMode::Naive => quads.iter().map(|quad| quad.pack(DIRECTIONS[face].1)).collect(),
+            MeshingMode::Greedy => merge_quads(face, quads),
+        })
+        .collect();
+
+    let mut axis_offsets = [0; 5];
+    let mut total = 0;
+    for (offset, group) in axis_offsets.iter_mut().zip(&groups) {
+        total += group.len() as u32;
+        *offset = total;
+    }
+
+    ChunkMesh {
+        faces: groups.concat(),
+        axis_offsets,
+        fluid_faces,
+    }
+}
+
+// Greedy meshing of the quads of the `face`-th group (in the order of DIRECTIONS): for
+// every layer of the chunk, going row by row, each face that's left is grown as far as
+// it goes along the row, and then row by row as long as the whole width matches.
+fn merge_quads(face: usize, quads: &[Quad]) -> Vec<FaceData> {
+    let (_, facing) = DIRECTIONS[face];
+    let size = CHUNK_SIZE;
+    // The normal, then the two coordinates of the plane in xyz order (what W and H of
+    // FaceData go along)
+    let (normal, u, v) = match face / 2 {
+        0 => (0, 1, 2),
+        1 => (1, 0, 2),
+        _ => (2, 0, 1),
+    };
+
+    let mut by_layer: [Vec<Quad>; CHUNK_SIZE] = Default::default();
+    for quad in quads {
+        by_layer[quad.pos[normal] as usize].push(*quad);
+    }
+
+    let mut faces = Vec::new();
+    let mut layer = [None; CHUNK_SIZE * CHUNK_SIZE];
+    for quads in by_layer.iter().filter(|quads| !quads.is_empty()) {
+        for quad in quads {
+            layer[quad.pos[v] as usize * size + quad.pos[u] as usize] = Some(*quad);
+        }
+
+        for start_v in 0..size {
+            for start_u in 0..size {
+                let Some(quad) = layer[start_v * size + start_u].take() else {
+                    continue;
+                };
+                if !quad.is_uniform() {
+                    faces.push(quad.pack(facing));
+                    continue;
+                }
+
+                let matches = |cell: &Option<Quad>| cell.as_ref().is_some_and(|other| other.looks_like(&quad));
+                let mut width = 1;
+                while start_u + width < size && matches(&layer[start_v * size + start_u + width]) {
+                    width += 1;
+                }
+                let mut height = 1;
+                while start_v + height < size {
+                    let row = (start_v + height) * size + start_u;
+                    if !layer[row..row + width].iter().all(matches) {
+                        break;
+                    }
+                    height += 1;
+                }
+
+                for row in start_v..start_v + height {
+                    let row = row * size + start_u;
+                    layer[row..row + width].fill(None);
+                }
+                faces.push(quad.pack(facing).with_size(width as u32, height as u32));
+            }
+        }
+    }
+    faces
+}
+
+/// The light and ambient occlusion at every vertex of the `face`-th face (in the order of
+/// DIRECTIONS) of the block at `pos`. Each vertex touches four blocks in front of the face:
+/// the one right in front, two along the edges and one diagonally. The classic voxel AO
+/// counts how many of the last three are opaque (with both edges opaque, the corner is
+/// hidden no matter what), and the light is the average of the ones that aren't.
+pub(super) fn face_shading(blocks: &PaddedChunk, registry: &BlockRegistry, pos: IVec3, face: usize) -> ([Light; 4], [u8; 4]) {
+    let (dir, _) = DIRECTIONS[face];
+    let front = pos + dir;
+    let mut light = [Light::DARK; 4];
+    let mut ao = [0; 4];
+
+    // DIRECTIONS goes X, X, Y, Y, Z, Z
+    let (u, v) = ((face / 2 + 1) % 3, (face / 2 + 2) % 3);
+    for (i, corner) in FACE_CORNERS[face].into_iter().enumerate() {
+        // Towards the corner along the two axes of the face
+        let (mut edge_a, mut edge_b) = (IVec3::ZERO, IVec3::ZERO);
+        edge_a[u] = corner[u] * 2 - 1;
+        edge_b[v] = corner[v] * 2 - 1;
+
+        let opaque = |pos: IVec3| registry.is_opaque(blocks.get(pos).id());
+        let side_a = opaque(front + edge_a);
+        let side_b = opaque(front + edge_b);
+        let diagonal = (side_a && side_b) || opaque(front + edge_a + edge_b);
+        ao[i] = 3 - side_a as u8 - side_b as u8 - diagonal as u8;
+
+        let (mut sky, mut block, mut count) = (0, 0, 0);
+        let cells = [(front, false), (front + edge_a, side_a), (front + edge_b, side_b), (front + edge_a + edge_b, diagonal)];
+        for (pos, blocked) in cells {
+            if !blocked {
+                let cell = blocks.light(pos);
+                sky += cell.sky() as u32;
+                block += cell.block() as u32;
+                count += 1;
+            }
+        }
+        // Rounded to the nearest level
+        light[i] = Light::new(((sky + count / 2) / count) as u8, ((block + count / 2) / count) as u8);
+    }
+    (light, ao)
+}
+
+fn neighbor_offsets() -> impl Iterator<Item = IVec3> {
+    (-1..=1)
+        .flat_map(|x| (-1..=1).flat_map(move |y| (-1..=1).map(move |z| IVec3::new(x, y, z))))
+        .filter(|&offset| offset != IVec3::ZERO)
+}
+
+struct MeshResult {
+    chunk_pos: IVec3,
+    job_id: u32,
+    mesh: ChunkMesh,
+}
+
+/// Schedules meshing jobs on the thread pool and collects the results.
+pub struct Mesher {
+    registry: Arc<BlockRegistry>,
+    textures: Arc<FaceTextures>,
+    mode: MeshingMode,
+    results_send: Sender<MeshResult>,
+    results_recv: Receiver<MeshResult>,
+    // Latest job for every chunk being meshed. When a chunk gets remeshed before the
+    // previous job finishes, the older result is stale and gets thrown away.
+    in_flight: HashMap<IVec3, u32>,
+    next_job_id: u32,
+    // Chunks the renderer had no room for, with how close they have to get to the player
+    // to be meshed again, see `on_evicted()`
+    evicted: HashMap<IVec3, i32>,
+}
+
+impl Mesher {
+    pub fn new(registry: Arc<BlockRegistry>, textures: Arc<FaceTextures>, mode: MeshingMode) -> Self {
+        let (results_send, results_recv) = channel();
+        Self {
+            registry,
+            textures,
+            mode,
+            results_send,
+            results_recv,
+            in_flight: HashMap::new(),
+            next_job_id: 0,
+            evicted: HashMap::new(),
+        }
+    }
+
+    /// Meshes the chunks, and remeshes all 26 of their neighbors, whose border faces may now
+    /// be culled or shaded differently (or which were waiting for them to arrive). The
+    /// `relit` ones only need to be remeshed themselves. Every chunk gets meshed once,
+    /// however many times it comes up.
+    pub fn on_chunks_loaded(
+        &mut self,
+        loaded: impl IntoIterator<Item = IVec3>,
+        relit: impl IntoIterator<Item = IVec3>,
+        chunks: &Chunks,
+        pool: &ThreadPool,
+    ) {
+        let mut dirty: HashSet<IVec3> = relit.into_iter().collect();
+        for chunk_pos in loaded {
+            dirty.insert(chunk_pos);
+            dirty.extend(neighbor_offsets().map(|offset| chunk_pos + offset));
+        }
+
+        for chunk_pos in dirty {
+            if (0..WORLD_HEIGHT_CHUNKS as i32).contains(&chunk_pos.y) {
+                self.remesh(chunk_pos, chunks, pool);
+            }
+        }
+    }
+
+    /// Queues the chunk for meshing. Returns false (and does nothing) if it
+    /// or any of its 26 neighbors isn't loaded.
+    pub fn remesh(&mut self, chunk_pos: IVec3, chunks: &Chunks, pool: &ThreadPool) -> bool {
+        // If the new mesh doesn't fit either, the renderer says so again
+        self.evicted.remove(&chunk_pos);
+
+        let Some(chunk) = chunks.get_at(chunk_pos) else {
+            return false;
+        };
+
+        let job_id = self.next_job_id;
+        self.next_job_id = self.next_job_id.wrapping_add(1);
+
+        // Nothing to mesh, no matter what the neighbors look like
+        if chunk.uniform_block() == Some(Block::AIR) {
+            self.in_flight.insert(chunk_pos, job_id);
+            _ = self.results_send.send(MeshResult { chunk_pos, job_id, mesh: ChunkMesh::default() });
+            return true;
+        }
+
+        let Some(blocks) = PaddedChunk::gather(chunk_pos, chunks) else {
+            return false;
+        };
+
+        self.in_flight.insert(chunk_pos, job_id);
+        let results = self.results_send.clone();
+        let registry = self.registry.clone();
+        let textures = self.textures.clone();
+        let mode = self.mode;
+        pool.spawn(move || {
+            let mesh = build_mesh(&blocks, &registry, &textures, mode);
+            _ = results.send(MeshResult { chunk_pos, job_id, mesh });
+        });
+        true
+    }
+
+    /// Call when the chunk is unloaded, so that a mesh still in progress won't be returned.
+    pub fn forget(&mut self, chunk_pos: IVec3) {
+        self.in_flight.remove(&chunk_pos);
+        self.evicted.remove(&chunk_pos);
+    }
+
+    /// For the chunks from `RenderWorld::take_evicted()`, which are left without a mesh.
+    /// They're meshed again by `remesh_evicted()` once they're within `within` chunks of
+    /// the player (along X or Z), where the renderer would keep them.
+    pub fn on_evicted(&mut self, chunk_pos: IVec3, within: i32) {
+        self.evicted.insert(chunk_pos, within);
+    }
+
+    pub fn remesh_evicted(&mut self, player_chunk_pos: IVec3, chunks: &Chunks, pool: &ThreadPool) {
+        let back_in_range: Vec<IVec3> = self.evicted
+            .iter()
+            .filter(|&(&chunk_pos, &within)| (chunk_pos - player_chunk_pos).xz().abs().max_element() <= within)
+            .map(|(&chunk_pos, _)| chunk_pos)
+            .collect();
+        for chunk_pos in back_in_range {
+            self.remesh(chunk_pos, chunks, pool);
+        }
+    }
+
+    /// Hands a finished mesh back, to be returned again by the next `finished()`. For when
+    /// it couldn't be used yet. Dropped if the chunk has been remeshed or forgotten since.
+    pub fn requeue(&mut self, chunk_pos: IVec3, mesh: ChunkMesh) {
+        if self.in_flight.contains_key(&chunk_pos) {
+            return;
+        }
+
+        let job_id = self.next_job_id;
+        self.next_job_id = self.next_job_id.wrapping_add(1);
+        self.in_flight.insert(chunk_pos, job_id);
+        _ = self.results_send.send(MeshResult { chunk_pos, job_id, mesh });
+    }
+
+    /// Meshes finished since the last call, in no particular order.
+    pub fn finished(&mut self) -> impl Iterator<Item = (IVec3, ChunkMesh)> + '_ {
+        let Self { results_recv, in_flight, .. } = self;
+        results_recv.try_iter().filter_map(|result| {
+            if in_flight.get(&result.chunk_pos) != Some(&result.job_id) {
+                return None;
+            }
+            in_flight.remove(&result.chunk_pos);
+            Some((result.chunk_pos, result.mesh))
+        })
+    }
+}
@@ -1,5 +1,10 @@
 pub mod ecs;
+pub mod chunk_loader;
 pub mod chunk_map;
 pub mod dimension;
-pub mod chunk;
-pub mod block;
+pub mod mesher;
+
+// Blocks and chunks are shared with the server
+pub use shared::world::{block, chunk};
+
+mod tests;
@@ -0,0 +1,287 @@
+#![cfg(test)]
+
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use glam::{IVec2, IVec3, UVec3};
+use rayon::ThreadPoolBuilder;
+use renderer::game_renderer::world::Facing;
+use shared::{
+    world::{
+        light::{Light, LightEngine},
+        registry::BlockRegistry,
+    },
+    worldgen::TerrainGenerator,
+};
+
+use super::{
+    block::Block,
+    chunk::Chunk,
+    chunk_map::{Chunks, WORLD_HEIGHT_CHUNKS},
+    mesher::{build_mesh, face_shading, ChunkMesh, Mesher, MeshingMode, PaddedChunk, DIRECTIONS, FACE_CORNERS},
+};
+use crate::textures::FaceTextures;
+
+// Loads the chunk at the origin (y = 1) and all 26 of its neighbors, filled with air
+fn chunks_around_origin() -> Chunks {
+    let mut chunks = Chunks::new(IVec2::ZERO);
+    for x in -1..=1 {
+        for y in 0..=2 {
+            for z in -1..=1 {
+                *chunks.get_at_mut(IVec3::new(x, y, z)) = Some(Chunk::new());
+            }
+        }
+    }
+    chunks
+}
+
+fn set_block(chunks: &mut Chunks, chunk_pos: IVec3, pos: UVec3, block: Block) {
+    chunks.get_at_mut(chunk_pos).as_mut().unwrap().set_at(pos, block);
+}
+
+fn mesh_of(blocks: &PaddedChunk, registry: &BlockRegistry, mode: MeshingMode) -> ChunkMesh {
+    build_mesh(blocks, registry, &FaceTextures::new(registry), mode)
+}
+
+fn face_count(chunks: &Chunks) -> usize {
+    let mesh = mesh_of(&PaddedChunk::gather(IVec3::Y, chunks).unwrap(), &BlockRegistry::builtin(), MeshingMode::Naive);
+    mesh.faces.len() + mesh.fluid_faces.len()
+}
+
+#[test]
+fn single_block_has_six_faces() {
+    let mut chunks = chunks_around_origin();
+    set_block(&mut chunks, IVec3::Y, UVec3::new(5, 5, 5), Block::STONE);
+
+    let mesh = mesh_of(&PaddedChunk::gather(IVec3::Y, &chunks).unwrap(), &BlockRegistry::builtin(), MeshingMode::Naive);
+    assert_eq!(mesh.faces.len(), 6);
+    assert_eq!(mesh.axis_offsets, [1, 2, 3, 4, 5]);
+}
+
+#[test]
+fn touching_faces_are_culled() {
+    let mut chunks = chunks_around_origin();
+    set_block(&mut chunks, IVec3::Y, UVec3::new(5, 5, 5), Block::STONE);
+    set_block(&mut chunks, IVec3::Y, UVec3::new(6, 5, 5), Block::DIRT);
+    assert_eq!(face_count(&chunks), 10);
+
+    // Not opaque, so doesn't hide the stone's face, but the stone hides its face
+    set_block(&mut chunks, IVec3::Y, UVec3::new(5, 6, 5), Block::WATER);
+    assert_eq!(face_count(&chunks), 10 + 5);
+}
+
+#[test]
+fn fluids_are_meshed_separately() {
+    let mut chunks = chunks_around_origin();
+    set_block(&mut chunks, IVec3::Y, UVec3::new(5, 5, 5), Block::WATER);
+    set_block(&mut chunks, IVec3::Y, UVec3::new(6, 5, 5), Block::WATER.with_data(3));
+
+    let mesh = mesh_of(&PaddedChunk::gather(IVec3::Y, &chunks).unwrap(), &BlockRegistry::builtin(), MeshingMode::Naive);
+    assert_eq!(mesh.faces.len(), 0);
+    assert_eq!(mesh.fluid_faces.len(), 10);
+
+    // The slab's own faces are solid, its water joins up with the water next to it
+    let registry = BlockRegistry::builtin();
+    let slab = Block::new(registry.id_of("stone_slab").unwrap()).with_waterlogged(true);
+    set_block(&mut chunks, IVec3::Y, UVec3::new(7, 5, 5), slab);
+    let mesh = mesh_of(&PaddedChunk::gather(IVec3::Y, &chunks).unwrap(), &registry, MeshingMode::Naive);
+    assert_eq!(mesh.faces.len(), 6);
+    assert_eq!(mesh.fluid_faces.len(), 14);
+}
+
+#[test]
+fn neighbor_chunks_cull_border_faces() {
+    let mut chunks = chunks_around_origin();
+    set_block(&mut chunks, IVec3::Y, UVec3::new(15, 0, 0), Block::STONE);
+    assert_eq!(face_count(&chunks), 6);
+
+    set_block(&mut chunks, IVec3::Y + IVec3::X, UVec3::new(0, 0, 0), Block::STONE);
+    set_block(&mut chunks, IVec3::ZERO, UVec3::new(15, 15, 0), Block::STONE);
+    assert_eq!(face_count(&chunks), 4);
+}
+
+#[test]
+fn meshing_waits_for_neighbors() {
+    let mut chunks = chunks_around_origin();
+    assert!(PaddedChunk::gather(IVec3::Y, &chunks).is_some());
+
+    // Diagonal neighbors too, for the ambient occlusion
+    *chunks.get_at_mut(IVec3::new(1, 2, -1)) = None;
+    assert!(PaddedChunk::gather(IVec3::Y, &chunks).is_none());
+
+    // Below the world doesn't need to be loaded
+    let mut bottom = Chunks::new(IVec2::ZERO);
+    for x in -1..=1 {
+        for z in -1..=1 {
+            *bottom.get_at_mut(IVec3::new(x, 0, z)) = Some(Chunk::new());
+            *bottom.get_at_mut(IVec3::new(x, 1, z)) = Some(Chunk::new());
+        }
+    }
+    assert!(PaddedChunk::gather(IVec3::ZERO, &bottom).is_some());
+}
+
+// Where the vertex shader puts the vertices of a face, relative to its block. A copy of
+// offsetCw() and offsetCcw() in textured_full_cube.vert
+fn shader_corners(facing: Facing) -> [IVec3; 4] {
+    let face = facing as u32;
+    let plane = (face >> 14) & 3;
+    let flip = face & (1 << 16) != 0;
+    let pattern: u32 = if flip { 0xCA0CA0C } else { 0xAC0AC0A };
+    [0, 1, 2, 3].map(|i| {
+        let bit = |shift: u32| ((pattern >> i >> (shift << 2)) & 1) as i32;
+        let offset = IVec3::new(bit(plane - 1), bit(plane), bit(plane + 1));
+        if flip { IVec3::ONE - offset } else { offset }
+    })
+}
+
+#[test]
+fn faces_are_drawn_on_their_side_of_the_block() {
+    for (normal, facing) in DIRECTIONS {
+        let corners = shader_corners(facing);
+        let axis = normal.abs().to_array().iter().position(|&n| n != 0).unwrap();
+        let side = i32::from(normal[axis] > 0);
+
+        // Flat along the normal, and spread out along the other two axes
+        for other in 0..3 {
+            let mut values = corners.map(|corner| corner[other]);
+            values.sort_unstable();
+            if other == axis {
+                assert_eq!(values, [side; 4], "{normal}");
+            } else {
+                assert_eq!(values, [0, 0, 1, 1], "{normal}");
+            }
+        }
+    }
+}
+
+// The AO and smooth lighting are worked out for the corners in FACE_CORNERS, so those
+// had better be the ones the face ends up drawn at
+#[test]
+fn face_corners_match_the_vertex_shader() {
+    for ((normal, facing), corners) in DIRECTIONS.into_iter().zip(FACE_CORNERS) {
+        assert_eq!(shader_corners(facing), corners, "{normal}");
+    }
+}
+
+#[test]
+fn corners_are_occluded_and_smoothly_lit() {
+    let registry = BlockRegistry::builtin();
+    let mut chunks = chunks_around_origin();
+    set_block(&mut chunks, IVec3::Y, UVec3::new(5, 5, 5), Block::STONE);
+    // Only lit right above the block
+    chunks.get_at_mut(IVec3::Y).as_mut().unwrap().set_light_at(UVec3::new(5, 6, 5), Light::new(15, 0));
+    let top_face = 2;
+
+    let shading = |chunks: &Chunks| face_shading(&PaddedChunk::gather(IVec3::Y, chunks).unwrap(), &registry, IVec3::new(5, 5, 5), top_face);
+    let (light, ao) = shading(&chunks);
+    assert_eq!(ao, [3; 4]);
+    // Averaged with the three dark blocks around every vertex
+    assert_eq!(light, [Light::new(4, 0); 4]);
+
+    // Vertices go (1, 1, 1), (1, 1, 0), (0, 1, 1), (0, 1, 0)
+    set_block(&mut chunks, IVec3::Y, UVec3::new(6, 6, 5), Block::STONE);
+    assert_eq!(shading(&chunks).1, [2, 2, 3, 3]);
+
+    set_block(&mut chunks, IVec3::Y, UVec3::new(5, 6, 6), Block::STONE);
+    let (light, ao) = shading(&chunks);
+    assert_eq!(ao, [0, 2, 2, 3]);
+    // Walled in on both sides, only the light in front of the face counts
+    assert_eq!(light[0], Light::new(15, 0));
+    assert_eq!(light[1], Light::new(5, 0));
+}
+
+#[test]
+fn greedy_meshing_merges_flat_areas() {
+    let registry = BlockRegistry::builtin();
+    let mut chunks = chunks_around_origin();
+    for i in 0..16 * 16 {
+        set_block(&mut chunks, IVec3::Y, UVec3::new(i % 16, 5, i / 16), Block::STONE);
+    }
+    set_block(&mut chunks, IVec3::Y, UVec3::new(3, 5, 3), Block::DIRT);
+
+    let blocks = PaddedChunk::gather(IVec3::Y, &chunks).unwrap();
+    let naive = mesh_of(&blocks, &registry, MeshingMode::Naive);
+    let greedy = mesh_of(&blocks, &registry, MeshingMode::Greedy);
+    assert_eq!(naive.faces.len(), 2 * 256 + 4 * 16);
+    // The dirt splits the top and the bottom into 4 rectangles each (and is one itself)
+    assert_eq!(greedy.faces.len(), 2 * 5 + 4);
+    assert_eq!(greedy.axis_offsets, [1, 2, 7, 12, 13]);
+}
+
+#[test]
+fn evicted_chunks_come_back_when_the_player_does() {
+    let registry = Arc::new(BlockRegistry::builtin());
+    let textures = Arc::new(FaceTextures::new(&registry));
+    let mut mesher = Mesher::new(registry, textures, MeshingMode::default());
+    let pool = ThreadPoolBuilder::new().num_threads(1).build().unwrap();
+    let mut chunks = chunks_around_origin();
+    set_block(&mut chunks, IVec3::Y, UVec3::new(5, 5, 5), Block::STONE);
+
+    // Thrown out to make room for a chunk 3 away from the player
+    mesher.on_evicted(IVec3::Y, 3);
+    mesher.remesh_evicted(IVec3::new(-5, 1, 0), &chunks, &pool);
+    assert_eq!(mesher.finished().count(), 0);
+
+    mesher.remesh_evicted(IVec3::new(-3, 1, 2), &chunks, &pool);
+    let start = Instant::now();
+    let (chunk_pos, mesh) = loop {
+        if let Some(result) = mesher.finished().next() {
+            break result;
+        }
+        assert!(start.elapsed() < Duration::from_secs(10), "Timed out");
+        std::thread::sleep(Duration::from_millis(1));
+    };
+    assert_eq!(chunk_pos, IVec3::Y);
+    assert_eq!(mesh.faces.len(), 6);
+
+    // Only until the renderer says it's out of room again
+    mesher.remesh_evicted(IVec3::ZERO, &chunks, &pool);
+    std::thread::sleep(Duration::from_millis(50));
+    assert_eq!(mesher.finished().count(), 0);
+}
+
+// Not a real test: compares the two modes on generated terrain. Run with
+// cargo test --release meshing_benchmark -- --ignored --nocapture
+#[test]
+#[ignore]
+fn meshing_benchmark() {
+    const RADIUS: i32 = 3;
+    let registry = BlockRegistry::builtin();
+    let textures = FaceTextures::new(&registry);
+    let generator = TerrainGenerator::new(0x5EED);
+    let mut chunks = Chunks::new(IVec2::ZERO);
+    let mut light = LightEngine::new();
+    for x in -RADIUS - 1..=RADIUS + 1 {
+        for z in -RADIUS - 1..=RADIUS + 1 {
+            for y in (0..WORLD_HEIGHT_CHUNKS as i32).rev() {
+                let chunk_pos = IVec3::new(x, y, z);
+                *chunks.get_at_mut(chunk_pos) = Some(generator.generate_chunk(chunk_pos));
+                light.on_chunk_loaded(&mut chunks, chunk_pos, None, &registry);
+            }
+        }
+    }
+
+    let padded: Vec<_> = (-RADIUS..=RADIUS)
+        .flat_map(|x| (-RADIUS..=RADIUS).flat_map(move |z| (0..WORLD_HEIGHT_CHUNKS as i32).map(move |y| IVec3::new(x, y, z))))
+        .map(|chunk_pos| PaddedChunk::gather(chunk_pos, &chunks).unwrap())
+        .collect();
+
+    for mode in [MeshingMode::Naive, MeshingMode::Greedy] {
+        let mut faces = 0;
+        let mut time = Duration::ZERO;
+        for blocks in &padded {
+            let start = Instant::now();
+            let mesh = build_mesh(blocks, &registry, &textures, mode);
+            time += start.elapsed();
+            faces += mesh.faces.len() + mesh.fluid_faces.len();
+        }
+        println!(
+            "{mode:?}: {faces} faces in {} chunks, {:.2?} total, {:.2?} per chunk",
+            padded.len(),
+            time,
+            time / padded.len() as u32
+        );
+    }
+}
@@ -1,6 +1,5 @@
-use flexstr::SharedStr;
 use quinn::{RecvStream, SendStream};
-use shared::{serialization::ByteWriter, net::NetworkId};
+use shared::net::NetworkId;
 use tokio::sync::mpsc::{UnboundedSender, UnboundedReceiver};
 
 use crate::util::receive_bytes;
@@ -8,35 +7,99 @@ use crate::util::receive_bytes;
 pub(super) mod chat {
     use super::*;
 
+    /// Chat messages are forwarded as-is, with the message type in front, for the main
+    /// thread to check and pass on to everyone.
     pub async fn recv_driver(
         mut incoming: RecvStream,
-        username: SharedStr,
         id: NetworkId,
         to_server: UnboundedSender<(NetworkId, Box<[u8]>)>,
     ) -> anyhow::Result<()> {
         let mut buf = Vec::new();
         loop {
-            let mut stream = receive_bytes(&mut incoming, &mut buf).await?;
-            
-            let message = format!("{username}: {}", stream.read_str());
-            _ = to_server.send((id, message.into_bytes().into_boxed_slice()));
+            let stream = receive_bytes(&mut incoming, &mut buf).await?;
+            if to_server.send((id, stream.bytes().into())).is_err() {
+                return Ok(()); // main thread is gone
+            }
         }
     }
 
+    /// Writes out already-encoded `ChatEvent`s, framed with the message length.
     pub async fn send_driver(
         mut outgoing: SendStream,
         mut messages: UnboundedReceiver<Box<[u8]>>,
     ) -> anyhow::Result<()> {
-        let mut buf = [0u8; 512];
         while let Some(message) = messages.recv().await {
-            debug_assert!(message.len() < buf.len(), "Chat message too long! ({}/{} bytes)", message.len(), buf.len());
+            debug_assert!(message.len() <= u16::MAX as usize, "Chat message too long! ({} bytes)", message.len());
 
-            let mut writer = ByteWriter::new_for_message(&mut buf);
-            writer.write(&message)
-                .write_message_len();
+            outgoing.write_all(&u16::to_le_bytes(message.len() as u16)).await?;
+            outgoing.write_all(&message).await?;
+        }
+        Ok(())
+    }
+}
+
+pub(super) mod chunks {
+    use super::*;
+
+    /// Chunk requests are forwarded as-is, with the message type in front, for the
+    /// main thread to decode.
+    pub async fn recv_driver(
+        mut incoming: RecvStream,
+        id: NetworkId,
+        to_server: UnboundedSender<(NetworkId, Box<[u8]>)>,
+    ) -> anyhow::Result<()> {
+        let mut buf = Vec::new();
+        loop {
+            let stream = receive_bytes(&mut incoming, &mut buf).await?;
+            if to_server.send((id, stream.bytes().into())).is_err() {
+                return Ok(()); // main thread is gone
+            }
+        }
+    }
+
+    /// Writes out already-encoded chunks, framed with the message length.
+    pub async fn send_driver(
+        mut outgoing: SendStream,
+        mut chunks: UnboundedReceiver<Box<[u8]>>,
+    ) -> anyhow::Result<()> {
+        while let Some(payload) = chunks.recv().await {
+            debug_assert!(payload.len() <= u16::MAX as usize, "Chunk message too long! ({} bytes)", payload.len());
 
-            outgoing.write_all(&writer.bytes()).await?;
+            outgoing.write_all(&u16::to_le_bytes(payload.len() as u16)).await?;
+            outgoing.write_all(&payload).await?;
+        }
+        Ok(())
+    }
+}
+
+pub(super) mod state {
+    use super::*;
+
+    /// Movement is forwarded as-is, with the message type in front, for the main thread
+    /// to decode.
+    pub async fn recv_driver(
+        mut incoming: RecvStream,
+        id: NetworkId,
+        to_server: UnboundedSender<(NetworkId, Box<[u8]>)>,
+    ) -> anyhow::Result<()> {
+        let mut buf = Vec::new();
+        loop {
+            let stream = receive_bytes(&mut incoming, &mut buf).await?;
+            if to_server.send((id, stream.bytes().into())).is_err() {
+                return Ok(()); // main thread is gone
+            }
+        }
+    }
+
+    /// Writes out already-encoded `GameEvent`s, framed with the message length.
+    pub async fn send_driver(
+        mut outgoing: SendStream,
+        mut events: UnboundedReceiver<Box<[u8]>>,
+    ) -> anyhow::Result<()> {
+        while let Some(event) = events.recv().await {
+            outgoing.write_all(&u16::to_le_bytes(event.len() as u16)).await?;
+            outgoing.write_all(&event).await?;
         }
         Ok(())
     }
-}
\ No newline at end of file
+}
@@ -28,6 +28,7 @@ pub struct Channels {
 pub struct NetServer {
     handle: JoinHandle<()>,
     channels: Channels,
+    local_addr: SocketAddr,
 }
 
 impl NetServer {
@@ -36,6 +37,11 @@ impl NetServer {
         !self.handle.is_finished()
     }
 
+    /// Where it's actually listening, for when it was started on port 0.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
     pub fn poll(&mut self) -> Option<(NetworkId, Box<[u8]>)> {
         self.channels.incoming.try_recv().ok()
     }
@@ -82,8 +88,8 @@ impl NetServer {
             .spawn(move || net_thread::start(bind_address, net_channels, on_ready_send))
             .unwrap();
 
-        on_ready_recv.blocking_recv()?.map_err(|e| anyhow!(e))?;
+        let local_addr = on_ready_recv.blocking_recv()?.map_err(|e| anyhow!(e))?;
 
-        Ok(NetServer { handle, channels })
+        Ok(NetServer { handle, channels, local_addr })
     }
 }
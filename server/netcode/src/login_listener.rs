@@ -7,11 +7,11 @@
 use flexstr::{ToSharedStr, SharedStr};
 use glam::{Vec3, Vec2};
 use log::{warn, debug, info};
-use quinn::{Endpoint, Connection};
-use shared::{net::{PROTOCOL_MAGIC, PROTOCOL_VERSION, NetworkId}, serialization::ByteWriter};
+use quinn::{Endpoint, Connection, SendStream, RecvStream};
+use shared::{net::{PROTOCOL_MAGIC, PROTOCOL_VERSION, NetworkId, stream_id}, serialization::ByteWriter};
 use tokio::{task, sync::{oneshot, mpsc::{Sender, unbounded_channel, UnboundedSender}}};
 
-use crate::{util::receive_bytes, net_thread::NetChannels, message::{ServerMsg, PlayerJoin}, channels};
+use crate::{util::receive_bytes, net_thread::NetChannels, message::{ServerMsg, PlayerJoin, PlayerChannels}, channels};
 
 pub async fn poll_new_connections(
     incoming: Endpoint,
@@ -85,10 +85,16 @@ async fn login(connection: Connection, channels: PerClientChannels) -> anyhow::R
         anyhow::bail!("Username too short");
     }
 
+    if !reader.has_n_more(8) {
+        connection.close(quinn::VarInt::from_u32(1), b"Invalid login request");
+        anyhow::bail!("Login request is missing the block checksum");
+    }
+    let block_checksum = reader.read_u64();
+
     debug!("Username: {username}. Generating network ID...");
 
     let (id_send, id_recv) = oneshot::channel();
-    _ = channels.server_messages.send(ServerMsg::LoginRequest { username: username.clone(), id_channel: id_send }).await;
+    _ = channels.server_messages.send(ServerMsg::LoginRequest { username: username.clone(), block_checksum, id_channel: id_send }).await;
         
     let login_response = id_recv.await?;
     let nid = match login_response {
@@ -106,7 +112,17 @@ async fn login(connection: Connection, channels: PerClientChannels) -> anyhow::R
                 .write_message_len()
                 .bytes();
 
-            hello_send.write_all(payload).await?;
+            // The main thread has handed out the id, so from here on it has to hear
+            // about this player leaving
+            let hello = async {
+                hello_send.write_all(payload).await?;
+                hello_send.finish().await?;
+                anyhow::Ok(())
+            };
+            if let Err(e) = hello.await {
+                _ = channels.server_messages.send(ServerMsg::PlayerLeft(nid)).await;
+                return Err(e);
+            }
             nid
         },
         LoginResponse::Denied{ reason } => {
@@ -114,7 +130,6 @@ async fn login(connection: Connection, channels: PerClientChannels) -> anyhow::R
             anyhow::bail!("Invalid login request");
         },
     };
-    hello_send.finish().await?;
 
     if let Err(e) = client_connection(connection, username, nid, channels).await {
         warn!("Error in client connection: {e}");
@@ -129,28 +144,54 @@ async fn client_connection(
     network_id: NetworkId,
     channels: PerClientChannels
 ) -> anyhow::Result<()> {
-    /* let (chat_send_main, chat_recv_self) = unbounded_channel(); // c -> s
-    let (entity_state_send, entity_state_recv) = unbounded_channel(); // s -> c
-
-    let (chat_recv_driver, chat_send_driver) = {
-        let (outgoing, mut incoming) = connection.accept_bi().await?;
+    let (chunks_send_main, chunks_recv_self) = unbounded_channel(); // s -> c
+    let (chat_send_main, chat_recv_self) = unbounded_channel(); // s -> c
+    let (state_send_main, state_recv_self) = unbounded_channel(); // s -> c
+    let (kick_send_main, mut kick_recv_self) = oneshot::channel();
 
-        // Read the byte that was used to open the channel
-        incoming.read_exact(&mut [0u8]).await?;
-
-        let chat_recv_driver = task::spawn(channels::chat::recv_driver(
-            incoming,
-            username.clone(),
-            network_id,
-            channels.incoming,
-        ));
-        let chat_send_driver = task::spawn(channels::chat::send_driver(
-            outgoing,
-            chat_recv_self,
-        ));
+    // The login was already accepted, so the main thread has to hear about this player
+    // leaving even if the connection dies right here
+    let streams = async {
+        let chunks = accept_stream(&connection, stream_id::CHUNKS).await?;
+        let chat = accept_stream(&connection, stream_id::CHAT).await?;
+        let state = accept_stream(&connection, stream_id::STATE).await?;
+        anyhow::Ok((chunks, chat, state))
+    };
+    let ((chunks_outgoing, chunks_incoming), (chat_outgoing, chat_incoming), (state_outgoing, state_incoming)) = match streams.await {
+        Ok(streams) => streams,
+        Err(e) => {
+            _ = channels.server_messages.send(ServerMsg::PlayerLeft(network_id)).await;
+            return Err(e);
+        }
+    };
 
-        (chat_recv_driver, chat_send_driver)
-    }; */
+    let mut chunk_recv_driver = task::spawn(channels::chunks::recv_driver(
+        chunks_incoming,
+        network_id,
+        channels.incoming.clone(),
+    ));
+    let mut chunk_send_driver = task::spawn(channels::chunks::send_driver(
+        chunks_outgoing,
+        chunks_recv_self,
+    ));
+    let mut chat_recv_driver = task::spawn(channels::chat::recv_driver(
+        chat_incoming,
+        network_id,
+        channels.incoming.clone(),
+    ));
+    let mut chat_send_driver = task::spawn(channels::chat::send_driver(
+        chat_outgoing,
+        chat_recv_self,
+    ));
+    let mut state_recv_driver = task::spawn(channels::state::recv_driver(
+        state_incoming,
+        network_id,
+        channels.incoming,
+    ));
+    let mut state_send_driver = task::spawn(channels::state::send_driver(
+        state_outgoing,
+        state_recv_self,
+    ));
 
     // Keep at the end so that Disconnect is definitely sent (no more early exits).
     // Disconnect must be sent to avoid leaking network ids
@@ -158,12 +199,36 @@ async fn client_connection(
         .send(ServerMsg::PlayerJoined(PlayerJoin {
             username: username.clone(),
             nid: network_id,
+            channels: PlayerChannels {
+                chunks: chunks_send_main,
+                chat: chat_send_main,
+                state: state_send_main,
+                kick: kick_send_main,
+            },
         }))
         .await;
 
-    /* tokio::select!(
+    tokio::select!(
         biased;
-    ); */
+        e = connection.closed() => debug!("Connection to \"{username}\" closed: {e}"),
+        Ok(reason) = &mut kick_recv_self => {
+            debug!("Kicking \"{username}\": {reason}");
+            connection.close(quinn::VarInt::from_u32(3), reason.as_bytes());
+        },
+        res = &mut chunk_recv_driver => log_driver_exit("Chunk receive", res),
+        res = &mut chunk_send_driver => log_driver_exit("Chunk send", res),
+        res = &mut chat_recv_driver => log_driver_exit("Chat receive", res),
+        res = &mut chat_send_driver => log_driver_exit("Chat send", res),
+        res = &mut state_recv_driver => log_driver_exit("State receive", res),
+        res = &mut state_send_driver => log_driver_exit("State send", res),
+    );
+
+    chunk_recv_driver.abort();
+    chunk_send_driver.abort();
+    chat_recv_driver.abort();
+    chat_send_driver.abort();
+    state_recv_driver.abort();
+    state_send_driver.abort();
 
     _ = channels.server_messages
         .send(ServerMsg::PlayerLeft(network_id))
@@ -172,3 +237,25 @@ async fn client_connection(
     debug!("Client with username \"{username}\" disconnected");
     Ok(())
 }
+
+/// Accepts the next stream opened by the client, and checks that it is the one expected.
+async fn accept_stream(connection: &Connection, expected_id: u8) -> anyhow::Result<(SendStream, RecvStream)> {
+    let (outgoing, mut incoming) = connection.accept_bi().await?;
+
+    // Read the byte that was used to open the channel
+    let mut id = [0u8];
+    incoming.read_exact(&mut id).await?;
+    if id[0] != expected_id {
+        anyhow::bail!("Expected stream {expected_id}, client opened {}", id[0]);
+    }
+
+    Ok((outgoing, incoming))
+}
+
+fn log_driver_exit(name: &str, result: Result<anyhow::Result<()>, task::JoinError>) {
+    match result {
+        Ok(Ok(())) => debug!("{name} driver finished"),
+        Ok(Err(e)) => debug!("{name} driver stopped: {e}"),
+        Err(e) => warn!("{name} driver panicked: {e}"),
+    }
+}
@@ -1,46 +1,100 @@
 use flexstr::SharedStr;
+use glam::{Vec2, Vec3};
 use shared::{
     serialization::{ByteReader, ByteWriter},
-    net::NetworkId,
+    net::{NetworkId, ChunkRequest},
 };
-use tokio::sync::oneshot;
+use tokio::sync::{oneshot, mpsc::UnboundedSender};
 
 use crate::login_listener::LoginResponse;
 
 pub enum InMsg<'a> {
+    /// Commands too, with the slash
     Chat(&'a str),
+    ChunkRequest(ChunkRequest),
+    /// Tab completion for the chat line being typed
+    Complete(&'a str),
+    /// Where the player moved to. Taken at its word, there's no physics on the server yet
+    Move { position: Vec3, head_rotation: Vec2 },
 }
 
 impl InMsg<'_> {
     const CHAT: u8 = 1;
+    const CHUNK_REQUEST: u8 = 2;
+    const COMPLETE: u8 = 3;
+    const MOVE: u8 = 4;
 }
 
 impl<'a> InMsg<'a> {
-    pub fn decode(stream: &'a [u8]) -> Self {
+    /// Returns None if the message is malformed or of an unknown type.
+    pub fn decode(stream: &'a [u8]) -> Option<Self> {
         let mut reader = ByteReader::new(stream);
-        match stream[0] {
-            Self::CHAT => Self::Chat(reader.read_str()),
-            _ => unreachable!(),
+        if !reader.has_n_more(1) {
+            return None;
+        }
+
+        match reader.read_u8() {
+            Self::CHAT => reader.try_read_str().map(Self::Chat),
+            Self::CHUNK_REQUEST => ChunkRequest::decode(&mut reader).map(Self::ChunkRequest),
+            Self::COMPLETE => reader.try_read_str().map(Self::Complete),
+            Self::MOVE if reader.has_n_more(20) => {
+                let position = Vec3::new(reader.read_f32(), reader.read_f32(), reader.read_f32());
+                let head_rotation = Vec2::new(reader.read_f32(), reader.read_f32());
+                // NaN would end up as chunk 0, 0, 0 and infinity at the end of the world
+                (position.is_finite() && head_rotation.is_finite()).then_some(Self::Move { position, head_rotation })
+            },
+            _ => None,
         }
     }
 
     pub fn encode(&self, dst: &mut ByteWriter) {
         match self {
-            InMsg::Chat(msg) => dst.write_u8(Self::CHAT).write_str(msg),
+            InMsg::Chat(msg) => { dst.write_u8(Self::CHAT).write_str(msg); },
+            InMsg::ChunkRequest(request) => {
+                dst.write_u8(Self::CHUNK_REQUEST);
+                request.encode(dst);
+            },
+            InMsg::Complete(input) => { dst.write_u8(Self::COMPLETE).write_str(input); },
+            InMsg::Move { position, head_rotation } => {
+                dst.write_u8(Self::MOVE)
+                    .write_f32(position.x)
+                    .write_f32(position.y)
+                    .write_f32(position.z)
+                    .write_f32(head_rotation.x)
+                    .write_f32(head_rotation.y);
+            },
         };
     }
 }
 
+// Main -> Net channels for a single player. Dropping these is fine: the net thread
+// notices the connection is gone and cleans up on its own.
+pub struct PlayerChannels {
+    /// Encoded chunks: position followed by the chunk data
+    pub chunks: UnboundedSender<Box<[u8]>>,
+    /// Encoded `ChatEvent`s
+    pub chat: UnboundedSender<Box<[u8]>>,
+    /// Encoded `GameEvent`s
+    pub state: UnboundedSender<Box<[u8]>>,
+    /// Closes the connection, telling the client why
+    pub kick: oneshot::Sender<Box<str>>,
+}
+
 pub struct PlayerJoin {
     pub nid: NetworkId,
     pub username: SharedStr,
+    pub channels: PlayerChannels,
 }
 
 pub enum ServerMsg {
     LoginRequest {
         username: SharedStr,
+        /// Of the client's block definitions
+        block_checksum: u64,
         id_channel: oneshot::Sender<LoginResponse>,
     },
     PlayerJoined(PlayerJoin),
+    /// Sent exactly once for every accepted login, even if the player never got as far
+    /// as joining, so that the id can be freed
     PlayerLeft(NetworkId),
 }
@@ -20,7 +20,7 @@ pub struct NetChannels {
 async fn net_main(
     address: SocketAddr,
     channels: NetChannels,
-    on_ready: oneshot::Sender<Result<(), Box<str>>>,
+    on_ready: oneshot::Sender<Result<SocketAddr, Box<str>>>,
 ) {
     let incoming = match setup::make_server_endpoint(address) {
         Ok(incoming) => incoming,
@@ -31,7 +31,14 @@ async fn net_main(
         }
     };
 
-    on_ready.send(Ok(())).unwrap(); // unwrap(): crashing is probably not a terrible solution on failure
+    let local_addr = match incoming.local_addr() {
+        Ok(addr) => addr,
+        Err(e) => {
+            on_ready.send(Err(format!("Failed to get the bound address: {e}").into_boxed_str())).unwrap();
+            return;
+        }
+    };
+    on_ready.send(Ok(local_addr)).unwrap(); // unwrap(): crashing is probably not a terrible solution on failure
 
     poll_new_connections(incoming, channels).await;
     debug!("Network thread terminating...");
@@ -40,7 +47,7 @@ async fn net_main(
 pub fn start(
     address: SocketAddr,
     channels: NetChannels,
-    on_ready: oneshot::Sender<Result<(), Box<str>>>
+    on_ready: oneshot::Sender<Result<SocketAddr, Box<str>>>
 ) {
     net_main(address, channels, on_ready);
 }
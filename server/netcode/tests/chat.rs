@@ -0,0 +1,172 @@
+// The server and client netcode talking to each other over loopback, with this test
+// playing the server's main thread: everyone gets in, chat messages go to everyone,
+// movement makes it to the server and teleports back to the client, and kicked players
+// get disconnected.
+
+use std::{
+    mem,
+    thread,
+    time::{Duration, Instant},
+};
+
+use client_netcode::ServerConnection;
+use glam::{Vec2, Vec3};
+use netcode::{
+    login_listener::LoginResponse,
+    message::{InMsg, PlayerChannels, ServerMsg},
+    NetServer,
+};
+use shared::{
+    net::{ChatEvent, ChatMessage, GameEvent, NetworkId, MAX_CHAT_MESSAGE_LEN},
+    serialization::ByteWriter,
+};
+use tokio::sync::oneshot;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+struct TestServer {
+    net: NetServer,
+    // Dropping the channels would disconnect the player
+    players: Vec<(NetworkId, Box<str>, PlayerChannels)>,
+    next_nid: u16,
+    moves: Vec<(NetworkId, Vec3, Vec2)>,
+}
+
+impl TestServer {
+    fn start() -> Self {
+        Self {
+            net: NetServer::start("127.0.0.1:0".parse().unwrap()).unwrap(),
+            players: Vec::new(),
+            next_nid: 1,
+            moves: Vec::new(),
+        }
+    }
+
+    fn tick(&mut self) {
+        let channels = self.net.channels().expect("The network thread stopped");
+        while let Ok(msg) = channels.server_messages.try_recv() {
+            match msg {
+                ServerMsg::LoginRequest { id_channel, .. } => {
+                    _ = id_channel.send(LoginResponse::Accepted {
+                        nid: NetworkId::from_raw(self.next_nid),
+                        position: Vec3::ZERO,
+                        head_rotation: Vec2::ZERO,
+                        world_seed: 0,
+                    });
+                    self.next_nid += 1;
+                }
+                ServerMsg::PlayerJoined(join) => {
+                    self.players.push((join.nid, join.username.as_str().into(), join.channels));
+                }
+                ServerMsg::PlayerLeft(nid) => self.players.retain(|(player, ..)| *player != nid),
+            }
+        }
+
+        while let Some((nid, bytes)) = self.net.poll() {
+            let text = match InMsg::decode(&bytes) {
+                Some(InMsg::Chat(text)) => text,
+                Some(InMsg::Move { position, head_rotation }) => {
+                    self.moves.push((nid, position, head_rotation));
+                    continue;
+                }
+                _ => continue,
+            };
+            let sender_name = self.players.iter().find(|(player, ..)| *player == nid).unwrap().1.clone();
+            let event = ChatEvent::Message(ChatMessage { sender: nid, sender_name, text: text.into() });
+
+            let mut buf = vec![0; event.encoded_len()];
+            event.encode(&mut ByteWriter::new(&mut buf));
+            for (.., channels) in &self.players {
+                _ = channels.chat.send(buf.clone().into_boxed_slice());
+            }
+        }
+    }
+
+    // Ticks until `done` returns something
+    fn run_until<T>(&mut self, mut done: impl FnMut(&mut Self) -> Option<T>) -> T {
+        let start = Instant::now();
+        loop {
+            self.tick();
+            if let Some(result) = done(self) {
+                return result;
+            }
+            assert!(start.elapsed() < TIMEOUT, "Timed out");
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    fn connect(&mut self, username: &'static str) -> ServerConnection {
+        let mut connecting = client_netcode::try_connect(self.net.local_addr(), username.into(), 0);
+        let (_, connection) = self.run_until(|_| connecting.tick().unwrap());
+        // Only in the chat once the server knows about them
+        self.run_until(|server| server.players.iter().any(|(_, name, _)| &**name == username).then_some(()));
+        connection
+    }
+}
+
+#[test]
+fn chat_messages_reach_everyone() {
+    let mut server = TestServer::start();
+    let mut alice = server.connect("alice");
+    let mut bob = server.connect("bob");
+
+    assert!(!alice.send_chat(&"a".repeat(MAX_CHAT_MESSAGE_LEN + 1)));
+    assert!(alice.send_chat("hello bob"));
+
+    for client in [&mut alice, &mut bob] {
+        let ChatEvent::Message(message) = server.run_until(|_| client.poll_chat()) else {
+            panic!("Expected a chat message");
+        };
+        assert_eq!(message.sender, NetworkId::from_raw(1));
+        assert_eq!(&*message.sender_name, "alice");
+        assert_eq!(&*message.text, "hello bob");
+    }
+
+    alice.stop();
+    bob.stop();
+    server.net.stop();
+}
+
+#[test]
+fn movement_reaches_the_server() {
+    let mut server = TestServer::start();
+    let mut alice = server.connect("alice");
+
+    let (position, head_rotation) = (Vec3::new(1000.5, 70.0, -3.25), Vec2::new(1.5, -0.5));
+    assert!(alice.send_movement(position, head_rotation));
+    let moved = server.run_until(|server| server.moves.pop());
+    assert_eq!(moved, (NetworkId::from_raw(1), position, head_rotation));
+
+    alice.stop();
+    server.net.stop();
+}
+
+#[test]
+fn teleports_reach_the_client() {
+    let mut server = TestServer::start();
+    let mut alice = server.connect("alice");
+
+    let event = GameEvent::Teleport(Vec3::new(-12.5, 80.0, 4096.0));
+    let mut buf = vec![0; event.encoded_len()];
+    event.encode(&mut ByteWriter::new(&mut buf));
+    _ = server.players[0].2.state.send(buf.into_boxed_slice());
+    assert_eq!(server.run_until(|_| alice.poll_game_event()), event);
+    // Not mixed up with the chat
+    assert!(alice.poll_chat().is_none());
+
+    alice.stop();
+    server.net.stop();
+}
+
+#[test]
+fn kicked_players_get_disconnected() {
+    let mut server = TestServer::start();
+    let alice = server.connect("alice");
+
+    // Only the kick channel, dropping the others would disconnect them anyway
+    let (.., channels) = &mut server.players[0];
+    _ = mem::replace(&mut channels.kick, oneshot::channel().0).send("bye".into());
+    server.run_until(|_| (!alice.open()).then_some(()));
+
+    server.net.stop();
+}
@@ -0,0 +1,132 @@
+// Chat. Players only send the text, which gets checked here and then passed on to
+// everyone, the sender included, with who it's from. The server itself can also say
+// things, like who joined, or tell a single player what was wrong with their message.
+// Lines starting with a slash are commands, and are handed back to be run instead.
+
+use std::collections::HashMap;
+
+use log::info;
+use shared::{
+    net::{ChatEvent, ChatMessage, NetworkId, MAX_CHAT_MESSAGE_LEN},
+    serialization::ByteWriter,
+};
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::runner::TICKS_PER_SECOND;
+
+mod tests;
+
+/// How many messages a player can send in a row before they have to slow down.
+pub const CHAT_BURST: u32 = 5;
+/// After that, one message a second.
+pub const CHAT_COOLDOWN_TICKS: u32 = TICKS_PER_SECOND;
+
+struct ChatPlayer {
+    username: Box<str>,
+    /// Encoded `ChatEvent`s, see `PlayerChannels::chat`
+    channel: UnboundedSender<Box<[u8]>>,
+    // Every message pushes this CHAT_COOLDOWN_TICKS further into the future, and the
+    // messages stop going through once it's CHAT_BURST messages ahead of now
+    cooldown_until: u32,
+}
+
+#[derive(Default)]
+pub struct Chat {
+    players: HashMap<NetworkId, ChatPlayer>,
+}
+
+impl Chat {
+    pub fn new() -> Self {
+        Self { players: HashMap::new() }
+    }
+
+    /// `channel` is the player's chat channel from `PlayerChannels`.
+    pub fn player_joined(&mut self, nid: NetworkId, username: &str, channel: UnboundedSender<Box<[u8]>>) {
+        self.players.insert(nid, ChatPlayer {
+            username: username.into(),
+            channel,
+            cooldown_until: 0,
+        });
+        self.broadcast(&ChatMessage::from_server(format!("{username} joined the game")));
+    }
+
+    pub fn player_left(&mut self, nid: NetworkId) {
+        if let Some(player) = self.players.remove(&nid) {
+            self.broadcast(&ChatMessage::from_server(format!("{} left the game", player.username)));
+        }
+    }
+
+    /// A message a player sent. Goes to everyone if it's fine, otherwise only the
+    /// sender hears back about what was wrong with it. Commands count towards the rate
+    /// limit too, and are returned without the slash.
+    pub fn on_message(&mut self, from: NetworkId, text: &str, current_tick: u32) -> Option<String> {
+        let player = self.players.get_mut(&from)?;
+
+        let text = match clean(text) {
+            Ok(text) if text.is_empty() => return None,
+            Ok(text) => text,
+            Err(reason) => {
+                send(&player.channel, &ChatEvent::Message(ChatMessage::from_server(reason)));
+                return None;
+            }
+        };
+
+        let cooldown_until = player.cooldown_until.max(current_tick);
+        if cooldown_until - current_tick >= CHAT_BURST * CHAT_COOLDOWN_TICKS {
+            send(&player.channel, &ChatEvent::Message(ChatMessage::from_server("You're sending messages too quickly")));
+            return None;
+        }
+        player.cooldown_until = cooldown_until + CHAT_COOLDOWN_TICKS;
+
+        if let Some(command) = text.strip_prefix('/') {
+            return Some(command.to_owned());
+        }
+
+        let message = ChatMessage {
+            sender: from,
+            sender_name: player.username.clone(),
+            text: text.into(),
+        };
+        self.broadcast(&message);
+        None
+    }
+
+    /// To every player.
+    pub fn broadcast(&self, message: &ChatMessage) {
+        info!("{message}");
+        let bytes = encode(&ChatEvent::Message(message.clone()));
+        for player in self.players.values() {
+            _ = player.channel.send(bytes.clone());
+        }
+    }
+
+    /// To a single player, if they're still around.
+    pub fn send_to(&self, nid: NetworkId, message: &ChatMessage) {
+        self.send_event(nid, &ChatEvent::Message(message.clone()));
+    }
+
+    /// Anything else that goes over the chat stream, like tab completions.
+    pub fn send_event(&self, nid: NetworkId, event: &ChatEvent) {
+        if let Some(player) = self.players.get(&nid) {
+            send(&player.channel, event);
+        }
+    }
+}
+
+// Without control characters and surrounding whitespace, or why it's not going anywhere
+fn clean(text: &str) -> Result<String, &'static str> {
+    if text.len() > MAX_CHAT_MESSAGE_LEN {
+        return Err("Your message is too long");
+    }
+    Ok(text.trim().chars().filter(|c| !c.is_control()).collect())
+}
+
+fn send(channel: &UnboundedSender<Box<[u8]>>, event: &ChatEvent) {
+    _ = channel.send(encode(event));
+}
+
+fn encode(event: &ChatEvent) -> Box<[u8]> {
+    let mut buf = vec![0; event.encoded_len()];
+    event.encode(&mut ByteWriter::new(&mut buf));
+    buf.into_boxed_slice()
+}
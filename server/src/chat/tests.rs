@@ -0,0 +1,79 @@
+#![cfg(test)]
+
+use shared::{
+    net::{ChatEvent, ChatMessage, NetworkId, MAX_CHAT_MESSAGE_LEN},
+    serialization::ByteReader,
+};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
+
+use super::{Chat, CHAT_BURST, CHAT_COOLDOWN_TICKS};
+
+fn join(chat: &mut Chat, nid: u16, username: &str) -> UnboundedReceiver<Box<[u8]>> {
+    let (send, recv) = unbounded_channel();
+    chat.player_joined(NetworkId::from_raw(nid), username, send);
+    recv
+}
+
+fn received(channel: &mut UnboundedReceiver<Box<[u8]>>) -> Vec<ChatMessage> {
+    std::iter::from_fn(|| channel.try_recv().ok())
+        .map(|bytes| match ChatEvent::decode(&mut ByteReader::new(&bytes)) {
+            Some(ChatEvent::Message(message)) => message,
+            event => panic!("Expected a message, got {event:?}"),
+        })
+        .collect()
+}
+
+#[test]
+fn messages_go_to_everyone_with_the_sender() {
+    let mut chat = Chat::new();
+    let mut alice = join(&mut chat, 1, "alice");
+    let mut bob = join(&mut chat, 2, "bob");
+    received(&mut alice);
+    received(&mut bob);
+
+    chat.on_message(NetworkId::from_raw(2), "  hi\u{7} there ", 0);
+    for channel in [&mut alice, &mut bob] {
+        let messages = received(channel);
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].sender, NetworkId::from_raw(2));
+        assert_eq!(&*messages[0].sender_name, "bob");
+        assert_eq!(&*messages[0].text, "hi there");
+    }
+
+    // Only whitespace doesn't go anywhere, and neither do messages from nobody or commands
+    chat.on_message(NetworkId::from_raw(2), " \n ", 0);
+    chat.on_message(NetworkId::from_raw(3), "hello?", 0);
+    assert_eq!(chat.on_message(NetworkId::from_raw(2), "/list ", 0).as_deref(), Some("list"));
+    assert!(received(&mut alice).is_empty());
+
+    chat.player_left(NetworkId::from_raw(2));
+    let messages = received(&mut alice);
+    assert!(messages[0].from_server_itself());
+    assert_eq!(&*messages[0].text, "bob left the game");
+}
+
+#[test]
+fn only_the_sender_hears_about_rejected_messages() {
+    let mut chat = Chat::new();
+    let mut alice = join(&mut chat, 1, "alice");
+    let mut bob = join(&mut chat, 2, "bob");
+    received(&mut alice);
+    received(&mut bob);
+
+    let alice_nid = NetworkId::from_raw(1);
+    chat.on_message(alice_nid, &"a".repeat(MAX_CHAT_MESSAGE_LEN + 1), 0);
+    assert!(received(&mut bob).is_empty());
+    assert!(received(&mut alice)[0].from_server_itself());
+
+    for _ in 0..CHAT_BURST {
+        chat.on_message(alice_nid, "spam", 10);
+    }
+    assert_eq!(received(&mut bob).len(), CHAT_BURST as usize);
+    chat.on_message(alice_nid, "spam", 10);
+    assert!(received(&mut bob).is_empty());
+    assert!(received(&mut alice).last().unwrap().from_server_itself());
+
+    // Calmed down
+    chat.on_message(alice_nid, "sorry", 10 + CHAT_COOLDOWN_TICKS);
+    assert_eq!(&*received(&mut bob)[0].text, "sorry");
+}
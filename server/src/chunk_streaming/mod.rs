@@ -0,0 +1,144 @@
+use std::collections::HashSet;
+
+use glam::IVec3;
+use shared::{
+    net::{write_ivec3, ChunkRequest},
+    serialization::ByteWriter,
+    world::chunk::Chunk,
+};
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::world::World;
+
+mod tests;
+
+/// Keeps track of which chunks a single client wants and which it already has,
+/// and feeds them to it a few at a time, closest first.
+pub struct ChunkStream {
+    sender: UnboundedSender<Box<[u8]>>,
+    // The chunk the player is in as far as the server knows, see `set_center()`
+    center: IVec3,
+
+    // Requested but not sent yet. Once sorted, furthest from `center` comes first,
+    // so that the next chunk to send can just be popped off the end.
+    pending: Vec<IVec3>,
+    needs_sort: bool,
+
+    // Sent and not cancelled since, i.e. what the client has loaded. These get
+    // sent again when modified.
+    sent: HashSet<IVec3>,
+}
+
+impl ChunkStream {
+    /// Sending is limited per player, because chunks that haven't been visited before
+    /// need to be generated first, on the main thread.
+    pub const CHUNKS_PER_TICK: usize = 16;
+
+    /// Requests further than this (in chunks, along any axis) from the player are ignored.
+    pub const MAX_DISTANCE: i32 = 32;
+
+    // Puts a limit on how much memory a single client can make the server waste
+    const MAX_PENDING: usize = 128 * 1024;
+
+    pub fn new(sender: UnboundedSender<Box<[u8]>>, center: IVec3) -> Self {
+        Self {
+            sender,
+            center,
+            pending: Vec::new(),
+            needs_sort: false,
+            sent: HashSet::new(),
+        }
+    }
+
+    /// Where the player is, in chunks. The center the client sends along with its
+    /// requests isn't trusted, or it could make the server load the whole world.
+    pub fn set_center(&mut self, center: IVec3) {
+        if center == self.center {
+            return;
+        }
+        self.center = center;
+        self.needs_sort = true;
+        // Out of range now, so these don't get updates anymore, and don't keep the
+        // chunks loaded. The client cancels them soon enough anyway
+        self.sent.retain(|&pos| Self::in_range(center, pos));
+    }
+
+    pub fn handle_request(&mut self, request: ChunkRequest) {
+        if !request.cancelled.is_empty() {
+            let cancelled: HashSet<IVec3> = request.cancelled.into_iter().collect();
+            self.pending.retain(|pos| !cancelled.contains(pos));
+            for pos in &cancelled {
+                self.sent.remove(pos);
+            }
+        }
+
+        for pos in request.requested {
+            if self.pending.len() >= Self::MAX_PENDING {
+                break;
+            }
+            if !Self::in_range(self.center, pos) {
+                continue;
+            }
+            // Requesting an already sent chunk again is fine, the client might have
+            // thrown it away without telling. Duplicates get dropped when sorting.
+            self.sent.remove(&pos);
+            self.pending.push(pos);
+            self.needs_sort = true;
+        }
+    }
+
+    /// The chunks this stream needs loaded: the ones still to be sent, and the ones the
+    /// client has, for the updates.
+    pub fn chunks(&self) -> impl Iterator<Item = IVec3> + '_ {
+        self.pending.iter().chain(&self.sent).copied()
+    }
+
+    /// Queues the chunk to be sent again, if the client has it.
+    pub fn on_chunk_modified(&mut self, chunk_pos: IVec3) {
+        if self.sent.remove(&chunk_pos) {
+            self.pending.push(chunk_pos);
+            self.needs_sort = true;
+        }
+    }
+
+    /// Sends up to `max_count` of the pending chunks closest to the player,
+    /// generating them as needed. `buf` is scratch space.
+    pub fn send_pending(&mut self, world: &mut World, max_count: usize, buf: &mut Vec<u8>) {
+        if self.needs_sort {
+            self.sort_pending();
+        }
+
+        buf.resize(12 + Chunk::MAX_ENCODED_SIZE, 0);
+        for _ in 0..max_count {
+            let Some(chunk_pos) = self.pending.pop() else {
+                break;
+            };
+
+            let mut writer = ByteWriter::new(buf);
+            write_ivec3(&mut writer, chunk_pos);
+            world.load_or_generate(chunk_pos).encode(&mut writer);
+
+            // Only fails if the connection is gone, in which case the player
+            // is about to be removed anyway
+            _ = self.sender.send(writer.bytes().into());
+            self.sent.insert(chunk_pos);
+        }
+    }
+
+    fn in_range(center: IVec3, chunk_pos: IVec3) -> bool {
+        (chunk_pos - center).abs().max_element() <= Self::MAX_DISTANCE
+    }
+
+    fn sort_pending(&mut self) {
+        let center = self.center;
+        let distance = |pos: &IVec3| (*pos - center).dot(*pos - center);
+
+        // The center may have moved since these were requested
+        self.pending.retain(|&pos| Self::in_range(center, pos));
+        self.pending.sort_unstable_by(|a, b| {
+            distance(b).cmp(&distance(a)).then_with(|| a.to_array().cmp(&b.to_array()))
+        });
+        self.pending.dedup();
+        self.needs_sort = false;
+    }
+}
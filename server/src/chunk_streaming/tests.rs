@@ -0,0 +1,53 @@
+#![cfg(test)]
+
+use glam::IVec3;
+use shared::{
+    net::{read_ivec3, ChunkRequest},
+    serialization::ByteReader,
+};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
+
+use crate::world::World;
+
+use super::ChunkStream;
+
+fn request(center: IVec3, requested: &[IVec3]) -> ChunkRequest {
+    ChunkRequest { center, requested: requested.to_vec(), cancelled: Vec::new() }
+}
+
+fn received(receiver: &mut UnboundedReceiver<Box<[u8]>>) -> Vec<IVec3> {
+    std::iter::from_fn(|| receiver.try_recv().ok())
+        .map(|bytes| read_ivec3(&mut ByteReader::new(&bytes)))
+        .collect()
+}
+
+#[test]
+fn requests_follow_the_player() {
+    let (sender, mut receiver) = unbounded_channel();
+    let mut stream = ChunkStream::new(sender, IVec3::ZERO);
+    let mut world = World::new(1234);
+    let mut buf = Vec::new();
+
+    // Whatever center the client claims, it's nowhere near that as far as the server knows
+    let far = IVec3::new(ChunkStream::MAX_DISTANCE * 3, 0, 0);
+    stream.handle_request(request(far, &[far]));
+    stream.send_pending(&mut world, ChunkStream::CHUNKS_PER_TICK, &mut buf);
+    assert!(received(&mut receiver).is_empty());
+
+    // Walked all the way there
+    let near = IVec3::new(1, 0, 0);
+    stream.handle_request(request(IVec3::ZERO, &[near]));
+    stream.send_pending(&mut world, ChunkStream::CHUNKS_PER_TICK, &mut buf);
+    assert_eq!(received(&mut receiver), [near]);
+    stream.set_center(far);
+    stream.handle_request(request(far, &[far + IVec3::X, far]));
+    stream.send_pending(&mut world, ChunkStream::CHUNKS_PER_TICK, &mut buf);
+    assert_eq!(received(&mut receiver), [far, far + IVec3::X]);
+
+    // The chunks back at spawn don't get updates anymore, the ones around the player do
+    assert!(stream.chunks().all(|chunk_pos| chunk_pos != near));
+    stream.on_chunk_modified(near);
+    stream.on_chunk_modified(far);
+    stream.send_pending(&mut world, ChunkStream::CHUNKS_PER_TICK, &mut buf);
+    assert_eq!(received(&mut receiver), [far]);
+}
@@ -0,0 +1,164 @@
+// The commands every server has.
+
+use anyhow::bail;
+use glam::Vec3;
+use shared::net::{ChatMessage, GameEvent, NetworkId, MAX_ONLINE_PLAYERS};
+
+use crate::{ecs::{Connection, Position, Username}, runner::TICKS_PER_SECOND};
+
+use super::{Args, Command, CommandContext, CommandSender, Commands, Param, ParamKind, PermissionLevel};
+
+const TP_PARAMS: &[Param] = &[
+    Param::required("x", ParamKind::Number),
+    Param::required("y", ParamKind::Number),
+    Param::required("z", ParamKind::Number),
+    Param::optional("player", ParamKind::Player),
+];
+const KICK_PARAMS: &[Param] = &[
+    Param::required("player", ParamKind::Player),
+    Param::optional("reason", ParamKind::Text),
+];
+const BROADCAST_PARAMS: &[Param] = &[Param::required("message", ParamKind::Text)];
+
+pub fn register(commands: &mut Commands) {
+    commands.register(Command {
+        name: "help",
+        description: "Lists the commands you can use",
+        params: &[],
+        permission: PermissionLevel::Player,
+        run: help,
+    });
+    commands.register(Command {
+        name: "list",
+        description: "Lists who's online",
+        params: &[],
+        permission: PermissionLevel::Player,
+        run: list,
+    });
+    commands.register(Command {
+        name: "time",
+        description: "Shows the current tick and how long the server has been up",
+        params: &[],
+        permission: PermissionLevel::Player,
+        run: time,
+    });
+    commands.register(Command {
+        name: "seed",
+        description: "Shows the world seed",
+        params: &[],
+        permission: PermissionLevel::Player,
+        run: seed,
+    });
+    commands.register(Command {
+        name: "tp",
+        description: "Teleports you, or someone else",
+        params: TP_PARAMS,
+        permission: PermissionLevel::Operator,
+        run: tp,
+    });
+    commands.register(Command {
+        name: "kick",
+        description: "Disconnects a player",
+        params: KICK_PARAMS,
+        permission: PermissionLevel::Operator,
+        run: kick,
+    });
+    commands.register(Command {
+        name: "broadcast",
+        description: "Says something to everyone, as the server",
+        params: BROADCAST_PARAMS,
+        permission: PermissionLevel::Operator,
+        run: broadcast,
+    });
+    commands.register(Command {
+        name: "save",
+        description: "Saves the world",
+        params: &[],
+        permission: PermissionLevel::Operator,
+        run: save,
+    });
+    commands.register(Command {
+        name: "stop",
+        description: "Saves the world and stops the server",
+        params: &[],
+        permission: PermissionLevel::Console,
+        run: stop,
+    });
+}
+
+fn help(ctx: &mut CommandContext, _: &Args) -> anyhow::Result<()> {
+    let level = ctx.permission_level();
+    for command in ctx.commands.iter().filter(|command| command.permission <= level) {
+        ctx.reply(format!("{} - {}", command.usage(), command.description));
+    }
+    Ok(())
+}
+
+fn list(ctx: &mut CommandContext, _: &Args) -> anyhow::Result<()> {
+    let mut query = ctx.state.ecs.query::<(&NetworkId, &Username)>();
+    let mut usernames = query.iter().map(|(_, (_, username))| &*username.0).collect::<Vec<_>>();
+    usernames.sort_unstable();
+    ctx.reply(format!("{}/{MAX_ONLINE_PLAYERS} online: {}", usernames.len(), usernames.join(", ")));
+    Ok(())
+}
+
+fn time(ctx: &mut CommandContext, _: &Args) -> anyhow::Result<()> {
+    let tick = ctx.state.current_tick;
+    let secs = tick / TICKS_PER_SECOND;
+    ctx.reply(format!("Tick {tick}, up for {}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60));
+    Ok(())
+}
+
+fn seed(ctx: &mut CommandContext, _: &Args) -> anyhow::Result<()> {
+    ctx.reply(format!("Seed: {}", ctx.state.world.seed()));
+    Ok(())
+}
+
+fn tp(ctx: &mut CommandContext, args: &Args) -> anyhow::Result<()> {
+    let position = Vec3::new(args.number(0).unwrap(), args.number(1).unwrap(), args.number(2).unwrap());
+    let target = match (args.player(3), ctx.sender) {
+        (Some(nid), _) | (None, CommandSender::Player(nid)) => nid,
+        (None, CommandSender::Console) => bail!("Say who to teleport"),
+    };
+
+    let player = ctx.state.players.entity(target).and_then(|entity| ctx.state.ecs.query_one_mut::<(&mut Position, &Connection)>(entity).ok());
+    if let Some((pos, connection)) = player {
+        pos.0 = position;
+        connection.send(&GameEvent::Teleport(position));
+    }
+    ctx.reply(format!("Teleported {} to {} {} {}", username(ctx, target), position.x, position.y, position.z));
+    Ok(())
+}
+
+fn kick(ctx: &mut CommandContext, args: &Args) -> anyhow::Result<()> {
+    let target = args.player(0).unwrap();
+    let reason = args.text(1).unwrap_or("Kicked by an operator");
+    let username = username(ctx, target);
+
+    if !ctx.state.players.kick(&ctx.state.ecs, target, reason) {
+        bail!("{username} is already on their way out");
+    }
+    ctx.state.chat.broadcast(&ChatMessage::from_server(format!("{username} was kicked: {reason}")));
+    Ok(())
+}
+
+fn broadcast(ctx: &mut CommandContext, args: &Args) -> anyhow::Result<()> {
+    ctx.state.chat.broadcast(&ChatMessage::from_server(args.text(0).unwrap()));
+    Ok(())
+}
+
+fn save(ctx: &mut CommandContext, _: &Args) -> anyhow::Result<()> {
+    let saved = ctx.state.world.save()?;
+    ctx.reply(format!("Saved {saved} chunks"));
+    Ok(())
+}
+
+// Saving happens in Server::shutdown()
+fn stop(ctx: &mut CommandContext, _: &Args) -> anyhow::Result<()> {
+    ctx.state.stopping = true;
+    Ok(())
+}
+
+fn username(ctx: &CommandContext, nid: NetworkId) -> String {
+    ctx.state.players.username(&ctx.state.ecs, nid).map_or_else(|| nid.to_string(), String::from)
+}
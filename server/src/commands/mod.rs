@@ -0,0 +1,309 @@
+// Commands, typed into the chat with a slash in front. Each one says what arguments it
+// takes and who's allowed to use it, and this takes care of parsing them, telling the
+// sender what they got wrong, and tab completion. The commands themselves are in builtin.
+
+use std::{collections::HashSet, fs, io};
+
+use log::{info, warn};
+use shared::net::{ChatMessage, NetworkId, MAX_COMPLETIONS};
+
+use crate::{
+    ecs::{Username, ECS},
+    players::{self, Players},
+    server::State,
+};
+
+mod builtin;
+mod tests;
+
+/// Usernames of the operators, one per line. There's no authentication yet, so whoever
+/// logs in with one of these names is an operator. All that stops them is that only one
+/// player can have a name at a time.
+pub const OPERATORS_FILE: &str = "ops.txt";
+
+/// Who can do what. Every level can use the commands of the ones below it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PermissionLevel {
+    Player,
+    /// Whoever's listed in `OPERATORS_FILE`, going by the username alone
+    Operator,
+    /// Whoever's typing into the server's terminal
+    Console,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommandSender {
+    Player(NetworkId),
+    Console,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParamKind {
+    /// The username of someone online
+    Player,
+    Number,
+    /// The rest of the line, spaces and all, so only makes sense last
+    Text,
+}
+
+pub struct Param {
+    pub name: &'static str,
+    pub kind: ParamKind,
+    pub optional: bool,
+}
+
+impl Param {
+    pub const fn required(name: &'static str, kind: ParamKind) -> Self {
+        Self { name, kind, optional: false }
+    }
+
+    /// Only followed by more optional ones.
+    pub const fn optional(name: &'static str, kind: ParamKind) -> Self {
+        Self { name, kind, optional: true }
+    }
+}
+
+pub struct Command {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub params: &'static [Param],
+    pub permission: PermissionLevel,
+    /// Errors are passed on to the sender
+    pub run: fn(&mut CommandContext, &Args) -> anyhow::Result<()>,
+}
+
+impl Command {
+    /// Like "/kick <player> [reason]".
+    pub fn usage(&self) -> String {
+        let mut usage = format!("/{}", self.name);
+        for param in self.params {
+            let (open, close) = if param.optional { ('[', ']') } else { ('<', '>') };
+            usage.push_str(&format!(" {open}{}{close}", param.name));
+        }
+        usage
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Arg {
+    Player(NetworkId),
+    Number(f32),
+    Text(Box<str>),
+}
+
+/// In the same order as `Command::params`, with the optional ones that weren't given
+/// missing from the end. Required ones are always there, so unwrapping those is fine.
+#[derive(Debug, Default, PartialEq)]
+pub struct Args(Vec<Arg>);
+
+impl Args {
+    pub fn player(&self, i: usize) -> Option<NetworkId> {
+        match self.0.get(i) {
+            Some(Arg::Player(nid)) => Some(*nid),
+            _ => None,
+        }
+    }
+
+    pub fn number(&self, i: usize) -> Option<f32> {
+        match self.0.get(i) {
+            Some(Arg::Number(n)) => Some(*n),
+            _ => None,
+        }
+    }
+
+    pub fn text(&self, i: usize) -> Option<&str> {
+        match self.0.get(i) {
+            Some(Arg::Text(text)) => Some(text),
+            _ => None,
+        }
+    }
+}
+
+pub struct CommandContext<'a> {
+    pub state: &'a mut State,
+    pub sender: CommandSender,
+    pub commands: &'a Commands,
+}
+
+impl CommandContext<'_> {
+    /// To whoever ran the command.
+    pub fn reply(&self, text: impl Into<Box<str>>) {
+        match self.sender {
+            CommandSender::Player(nid) => self.state.chat.send_to(nid, &ChatMessage::from_server(text)),
+            CommandSender::Console => info!("{}", text.into()),
+        }
+    }
+
+    pub fn permission_level(&self) -> PermissionLevel {
+        self.commands.permission_level(self.sender, &self.state.ecs, &self.state.players)
+    }
+}
+
+pub struct Commands {
+    commands: Vec<Command>,
+    // Lowercase, like the usernames are compared
+    operators: HashSet<Box<str>>,
+}
+
+impl Default for Commands {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Commands {
+    /// With the builtin commands, and the operators from `OPERATORS_FILE` if there is one.
+    pub fn new() -> Self {
+        let mut commands = Self {
+            commands: Vec::new(),
+            operators: HashSet::new(),
+        };
+        builtin::register(&mut commands);
+
+        match fs::read_to_string(OPERATORS_FILE) {
+            Ok(operators) => {
+                commands.set_operators(operators.lines());
+                info!("{} operators", commands.operators.len());
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => warn!("Couldn't read {OPERATORS_FILE}: {e}"),
+        }
+        commands
+    }
+
+    pub fn register(&mut self, command: Command) {
+        debug_assert!(self.get(command.name).is_none(), "/{} registered twice", command.name);
+        self.commands.push(command);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Command> {
+        self.commands.iter().find(|command| command.name == name)
+    }
+
+    /// In the order they were registered.
+    pub fn iter(&self) -> impl Iterator<Item = &Command> {
+        self.commands.iter()
+    }
+
+    pub fn set_operators<'a>(&mut self, usernames: impl IntoIterator<Item = &'a str>) {
+        self.operators = usernames.into_iter()
+            .map(str::trim)
+            .filter(|username| !username.is_empty())
+            .map(|username| username.to_ascii_lowercase().into())
+            .collect();
+    }
+
+    pub fn permission_level(&self, sender: CommandSender, ecs: &ECS, players: &Players) -> PermissionLevel {
+        match sender {
+            CommandSender::Player(nid) => match players.username(ecs, nid) {
+                Some(username) if self.operators.contains(&*username.to_ascii_lowercase()) => PermissionLevel::Operator,
+                _ => PermissionLevel::Player,
+            },
+            CommandSender::Console => PermissionLevel::Console,
+        }
+    }
+
+    /// `line` is without the slash. Anything that goes wrong is only reported to the sender.
+    pub fn run(&self, state: &mut State, sender: CommandSender, line: &str) {
+        let line = line.trim();
+        let (name, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+
+        if let CommandSender::Player(nid) = sender {
+            let username = state.players.username(&state.ecs, nid).unwrap_or_else(|| "?".into());
+            info!("{username} issued /{line}");
+        }
+
+        let mut ctx = CommandContext { state, sender, commands: self };
+        let Some(command) = self.get(name) else {
+            ctx.reply(format!("There's no /{name}, try /help"));
+            return;
+        };
+        if ctx.permission_level() < command.permission {
+            ctx.reply(format!("You're not allowed to use /{name}"));
+            return;
+        }
+
+        match parse_args(command, rest, &ctx.state.ecs) {
+            Ok(args) => {
+                if let Err(e) = (command.run)(&mut ctx, &args) {
+                    ctx.reply(e.to_string());
+                }
+            }
+            Err(e) => ctx.reply(e),
+        }
+    }
+
+    /// What the last word of `input` (with the slash) could be: a command name, or a
+    /// username where a player goes.
+    pub fn complete(&self, level: PermissionLevel, input: &str, ecs: &ECS) -> Vec<Box<str>> {
+        let Some(line) = input.strip_prefix('/') else {
+            return Vec::new();
+        };
+        let words = line.split(' ').collect::<Vec<_>>();
+        let (last, before) = words.split_last().unwrap();
+
+        let mut suggestions: Vec<Box<str>> = match before.split_first() {
+            None => self.commands.iter()
+                .filter(|command| command.permission <= level && command.name.starts_with(last))
+                .map(|command| format!("/{}", command.name).into())
+                .collect(),
+            Some((name, args)) => {
+                let Some(command) = self.get(name).filter(|command| command.permission <= level) else {
+                    return Vec::new();
+                };
+                // Double spaces don't make for empty arguments
+                let param = command.params.get(args.iter().filter(|arg| !arg.is_empty()).count());
+                if !matches!(param, Some(Param { kind: ParamKind::Player, .. })) {
+                    return Vec::new();
+                }
+                ecs.query::<(&NetworkId, &Username)>()
+                    .iter()
+                    .map(|(_, (_, username))| &username.0)
+                    .filter(|username| matches!(username.get(..last.len()), Some(start) if start.eq_ignore_ascii_case(last)))
+                    .cloned()
+                    .collect()
+            }
+        };
+        suggestions.sort_unstable();
+        suggestions.truncate(MAX_COMPLETIONS);
+        suggestions
+    }
+}
+
+// What's after the command name into Args, or what's wrong with it
+fn parse_args(command: &Command, mut rest: &str, ecs: &ECS) -> Result<Args, String> {
+    let mut args = Vec::new();
+    for param in command.params {
+        rest = rest.trim_start();
+        if rest.is_empty() {
+            if param.optional {
+                break;
+            }
+            return Err(format!("Usage: {}", command.usage()));
+        }
+
+        let (word, after) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+        let arg = match param.kind {
+            ParamKind::Player => {
+                let nid = players::find(ecs, word).ok_or_else(|| format!("There's no player called {word}"))?;
+                Arg::Player(nid)
+            }
+            ParamKind::Number => {
+                let n = word.parse::<f32>().ok().filter(|n| n.is_finite()).ok_or_else(|| format!("{word} isn't a number"))?;
+                Arg::Number(n)
+            }
+            ParamKind::Text => {
+                args.push(Arg::Text(rest.trim_end().into()));
+                rest = "";
+                continue;
+            }
+        };
+        args.push(arg);
+        rest = after;
+    }
+
+    if !rest.trim().is_empty() {
+        return Err(format!("Usage: {}", command.usage()));
+    }
+    Ok(Args(args))
+}
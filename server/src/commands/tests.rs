@@ -0,0 +1,76 @@
+#![cfg(test)]
+
+use glam::{Vec2, Vec3};
+use shared::net::NetworkId;
+use tokio::sync::{mpsc::unbounded_channel, oneshot};
+
+use crate::{
+    ecs::{Connection, ECS},
+    players::{self, Players},
+};
+
+use super::{parse_args, Arg, Args, CommandSender, Commands, PermissionLevel};
+
+fn players(usernames: &[&str]) -> (ECS, Players) {
+    let mut ecs = ECS::new();
+    let mut players = Players::new();
+    for (i, username) in usernames.iter().enumerate() {
+        // Kicking and teleporting aren't tested here, so the receivers can go
+        let connection = Connection::new(oneshot::channel().0, unbounded_channel().0);
+        players.spawn(&mut ecs, NetworkId::from_raw(i as u16 + 1), username, Vec3::ZERO, Vec2::ZERO, connection);
+    }
+    (ecs, players)
+}
+
+#[test]
+fn arguments_are_checked() {
+    let commands = Commands::new();
+    let (ecs, _players) = players(&["alice", "bob"]);
+    let tp = commands.get("tp").unwrap();
+    let kick = commands.get("kick").unwrap();
+
+    assert_eq!(
+        parse_args(tp, " 1  -2.5 3 Bob", &ecs),
+        Ok(Args(vec![Arg::Number(1.0), Arg::Number(-2.5), Arg::Number(3.0), Arg::Player(NetworkId::from_raw(2))])),
+    );
+    assert_eq!(parse_args(tp, "1 2 3", &ecs).unwrap().player(3), None);
+    assert_eq!(parse_args(tp, "1 2", &ecs), Err("Usage: /tp <x> <y> <z> [player]".to_owned()));
+    assert_eq!(parse_args(tp, "1 2 3 alice bob", &ecs), Err("Usage: /tp <x> <y> <z> [player]".to_owned()));
+    assert_eq!(parse_args(tp, "1 two 3", &ecs), Err("two isn't a number".to_owned()));
+    assert_eq!(parse_args(tp, "1 NaN 3", &ecs), Err("NaN isn't a number".to_owned()));
+    assert_eq!(parse_args(tp, "1 2 3 carol", &ecs), Err("There's no player called carol".to_owned()));
+
+    let args = parse_args(kick, "bob  go  away ", &ecs).unwrap();
+    assert_eq!(args.text(1), Some("go  away"));
+}
+
+#[test]
+fn permissions_and_completions() {
+    let mut commands = Commands::new();
+    commands.set_operators(["", " Alice "]);
+    let (mut ecs, mut players) = players(&["alice", "bob", "Alicia"]);
+
+    let alice = commands.permission_level(CommandSender::Player(NetworkId::from_raw(1)), &ecs, &players);
+    let bob = commands.permission_level(CommandSender::Player(NetworkId::from_raw(2)), &ecs, &players);
+    assert_eq!(alice, PermissionLevel::Operator);
+    assert_eq!(bob, PermissionLevel::Player);
+    assert_eq!(commands.permission_level(CommandSender::Console, &ecs, &players), PermissionLevel::Console);
+    // What keeps someone else from logging in as ALICE
+    assert_eq!(players::find(&ecs, "ALICE"), Some(NetworkId::from_raw(1)));
+
+    // Only what they're allowed to use
+    assert_eq!(commands.complete(alice, "/t", &ecs), ["/time".into(), "/tp".into()]);
+    assert_eq!(commands.complete(bob, "/t", &ecs), ["/time".into()]);
+    assert!(commands.complete(bob, "/kick a", &ecs).is_empty());
+
+    assert_eq!(commands.complete(alice, "/kick al", &ecs), ["Alicia".into(), "alice".into()]);
+    assert_eq!(commands.complete(alice, "/tp 1  2 3 B", &ecs), ["bob".into()]);
+    // Not where a player goes
+    assert!(commands.complete(alice, "/tp a", &ecs).is_empty());
+    assert!(commands.complete(alice, "/kick bob a", &ecs).is_empty());
+    assert!(commands.complete(alice, "not a command", &ecs).is_empty());
+
+    // Gone once they've left
+    assert!(players.despawn(&mut ecs, NetworkId::from_raw(3)));
+    assert_eq!(commands.complete(alice, "/kick al", &ecs), ["alice".into()]);
+}
@@ -0,0 +1,53 @@
+// Commands typed into the terminal the server runs in. They're the same ones players
+// use in the chat, the slash is optional, and everything's allowed.
+
+use std::{
+    io,
+    sync::mpsc::{self, Receiver},
+    thread,
+};
+
+use log::{debug, warn};
+
+pub struct Console {
+    lines: Receiver<String>,
+}
+
+impl Console {
+    /// Reads stdin on its own thread, which is left blocked on it when the server stops.
+    pub fn start() -> Self {
+        let (send, lines) = mpsc::channel();
+        thread::Builder::new()
+            .name("console".into())
+            .spawn(move || {
+                for line in io::stdin().lines() {
+                    match line {
+                        Ok(line) => {
+                            if send.send(line).is_err() {
+                                return;
+                            }
+                        }
+                        Err(e) => {
+                            warn!("Couldn't read from the console: {e}");
+                            return;
+                        }
+                    }
+                }
+                // Like when running without a terminal
+                debug!("Console closed");
+            })
+            .unwrap();
+        Self { lines }
+    }
+
+    /// The next command, without the slash. Empty lines are skipped.
+    pub fn poll(&self) -> Option<String> {
+        loop {
+            let line = self.lines.try_recv().ok()?;
+            let line = line.trim();
+            if !line.is_empty() {
+                return Some(line.strip_prefix('/').unwrap_or(line).to_owned());
+            }
+        }
+    }
+}
@@ -0,0 +1,46 @@
+use glam::{Vec2, Vec3};
+use shared::{net::GameEvent, serialization::ByteWriter};
+use tokio::sync::{mpsc::UnboundedSender, oneshot};
+
+pub type ECS = hecs::World;
+
+// List of all components in the game. Players also have their NetworkId, see
+// `Players::spawn()` for everything they're made of.
+
+pub struct Position(pub Vec3);
+
+/// Yaw and pitch, in radians.
+pub struct HeadRotation(pub Vec2);
+
+pub struct Username(pub Box<str>);
+
+/// What the main thread has of a player's connection, other than the channels that went
+/// to the chunk stream and the chat.
+pub struct Connection {
+    // Taken when kicking, see `PlayerChannels::kick`
+    kick: Option<oneshot::Sender<Box<str>>>,
+    // See `PlayerChannels::state`
+    state: UnboundedSender<Box<[u8]>>,
+}
+
+impl Connection {
+    pub fn new(kick: oneshot::Sender<Box<str>>, state: UnboundedSender<Box<[u8]>>) -> Self {
+        Self { kick: Some(kick), state }
+    }
+
+    pub fn send(&self, event: &GameEvent) {
+        let mut buf = vec![0; event.encoded_len()];
+        event.encode(&mut ByteWriter::new(&mut buf));
+        _ = self.state.send(buf.into_boxed_slice());
+    }
+
+    /// Closes the connection. The player is only gone once the network thread says so
+    /// with `ServerMsg::PlayerLeft`. Returns false if they were already kicked.
+    pub fn kick(&mut self, reason: &str) -> bool {
+        let Some(kick) = self.kick.take() else {
+            return false;
+        };
+        _ = kick.send(reason.into());
+        true
+    }
+}
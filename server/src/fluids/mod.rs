@@ -0,0 +1,177 @@
+// Water flowing around. Whenever a block changes, the blocks around it get a look a
+// little later (FLOW_DELAY_TICKS), and those that turn out to be wrong for their
+// surroundings change too, which gets their neighbors looked at in turn. That's all
+// the spreading there is: every block only ever decides what it should be itself.
+//
+// Everything is in ticks and in a fixed order, so the same changes always play out
+// the same way, no matter the frame rate or the order of the HashMaps.
+
+use std::collections::{BTreeMap, HashSet};
+
+use glam::IVec3;
+use shared::world::{
+    block::Block,
+    block_entity::BlockEntity,
+    chunk::WorldBlockPos,
+    fluid::{FluidState, MAX_FLOW_DISTANCE},
+    registry::BlockRegistry,
+};
+
+use crate::{runner::TICKS_PER_SECOND, world::World};
+
+mod tests;
+
+/// Water moves 4 blocks a second.
+pub const FLOW_DELAY_TICKS: u32 = TICKS_PER_SECOND / 4;
+
+const HORIZONTAL: [IVec3; 4] = [IVec3::X, IVec3::NEG_X, IVec3::Z, IVec3::NEG_Z];
+
+pub struct FluidSim {
+    // Due tick -> blocks to update then, in the order they were scheduled
+    scheduled: BTreeMap<u32, Vec<WorldBlockPos>>,
+    // Everything in `scheduled`, so that nothing is in there twice
+    pending: HashSet<WorldBlockPos>,
+}
+
+impl FluidSim {
+    pub fn new() -> Self {
+        Self {
+            scheduled: BTreeMap::new(),
+            pending: HashSet::new(),
+        }
+    }
+
+    /// Blocks waiting to be updated.
+    pub fn pending_count(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Updates the blocks due this tick, and schedules updates around every block
+    /// changed since the last call (by the simulation or anything else).
+    pub fn tick(&mut self, world: &mut World, current_tick: u32) {
+        let mut due = Vec::new();
+        while let Some(entry) = self.scheduled.first_entry() {
+            if *entry.key() > current_tick {
+                break;
+            }
+            due.extend(entry.remove());
+        }
+
+        // Decide everything before changing anything, so that the order within
+        // a tick doesn't matter
+        let updates: Vec<(WorldBlockPos, Block)> = due
+            .into_iter()
+            .filter_map(|pos| {
+                self.pending.remove(&pos);
+                let current = world.block_at(pos)?;
+                let next = next_state(world, pos, current)?;
+                (next != current).then_some((pos, next))
+            })
+            .collect();
+
+        for (pos, block) in updates {
+            world.set_block_at(pos, block);
+        }
+
+        let due_tick = current_tick + FLOW_DELAY_TICKS;
+        for pos in world.drain_changed_blocks().collect::<Vec<_>>() {
+            for pos in std::iter::once(pos).chain([IVec3::X, IVec3::NEG_X, IVec3::Y, IVec3::NEG_Y, IVec3::Z, IVec3::NEG_Z].map(|dir| pos + dir)) {
+                if self.pending.insert(pos) {
+                    self.scheduled.entry(due_tick).or_default().push(pos);
+                }
+            }
+        }
+    }
+}
+
+/// Puts a water source at `pos`, or waterlogs the block there if it can hold water.
+/// Returns false if neither works (or the chunk isn't loaded).
+pub fn place_water(world: &mut World, blocks: &BlockRegistry, pos: WorldBlockPos) -> bool {
+    let Some(block) = world.block_at(pos) else {
+        return false;
+    };
+
+    if block == Block::AIR || matches!(FluidState::of(block), Some(FluidState::Flowing(_) | FluidState::Falling)) {
+        world.set_block_at(pos, FluidState::Source.water())
+    } else if blocks.is_waterloggable(block.id()) {
+        set_waterlogged(world, pos, block, true)
+    } else {
+        false
+    }
+}
+
+/// Takes away a source, or the water from a waterlogged block. Whatever
+/// flowed out of it dries up by itself. Returns false if there's no source.
+pub fn remove_water(world: &mut World, pos: WorldBlockPos) -> bool {
+    let Some(block) = world.block_at(pos) else {
+        return false;
+    };
+
+    if block.is_waterlogged() {
+        set_waterlogged(world, pos, block, false)
+    } else if FluidState::of(block) == Some(FluidState::Source) {
+        world.set_block_at(pos, Block::AIR)
+    } else {
+        false
+    }
+}
+
+// Complex blocks keep their entity (which way they face...), only the block in it changes
+fn set_waterlogged(world: &mut World, pos: WorldBlockPos, block: Block, waterlogged: bool) -> bool {
+    match world.block_entity_at(pos) {
+        Some(entity) => {
+            let entity = BlockEntity { block: block.with_waterlogged(waterlogged), ..entity.clone() };
+            world.set_block_entity_at(pos, entity)
+        }
+        None => world.set_block_at(pos, block.with_waterlogged(waterlogged)),
+    }
+}
+
+// What the block at `pos` should be, given the blocks around it. None for blocks
+// fluids don't get to change: anything but air and water that isn't a source.
+fn next_state(world: &World, pos: WorldBlockPos, current: Block) -> Option<Block> {
+    let replaceable = current == Block::AIR
+        || matches!(FluidState::of(current), Some(FluidState::Flowing(_) | FluidState::Falling));
+    if !replaceable {
+        return None;
+    }
+
+    // Unloaded blocks act like walls
+    let fluid_at = |pos| world.block_at(pos).and_then(FluidState::of);
+    let holds_up = |pos| world.block_at(pos).map_or(true, holds_fluid_up);
+
+    if fluid_at(pos + IVec3::Y).is_some() {
+        return Some(FluidState::Falling.water());
+    }
+
+    let mut sources = 0;
+    let mut distance = None::<u8>;
+    for dir in HORIZONTAL {
+        let neighbor = pos + dir;
+        let Some(state) = fluid_at(neighbor) else {
+            continue;
+        };
+        if state == FluidState::Source {
+            sources += 1;
+        }
+        // Only sources spread sideways while they could still flow down
+        if state == FluidState::Source || holds_up(neighbor - IVec3::Y) {
+            distance = Some(distance.map_or(state.distance() + 1, |d| d.min(state.distance() + 1)));
+        }
+    }
+
+    // Water between two sources becomes one, as long as it has something to rest on
+    if sources >= 2 && holds_up(pos - IVec3::Y) {
+        return Some(FluidState::Source.water());
+    }
+
+    Some(match distance {
+        Some(distance) if distance <= MAX_FLOW_DISTANCE => FluidState::Flowing(distance).water(),
+        _ => Block::AIR,
+    })
+}
+
+// Fluid on top of this spreads sideways instead of falling through
+fn holds_fluid_up(block: Block) -> bool {
+    block != Block::AIR && !matches!(FluidState::of(block), Some(FluidState::Flowing(_) | FluidState::Falling))
+}
@@ -0,0 +1,206 @@
+#![cfg(test)]
+
+use glam::{IVec3, UVec3};
+use shared::world::{
+    block::Block,
+    block_entity::{BlockEntity, Orientation},
+    chunk::Chunk,
+    fluid::{FluidState, MAX_FLOW_DISTANCE},
+    registry::BlockRegistry,
+};
+
+use crate::world::World;
+
+use super::{place_water, remove_water, FluidSim, FLOW_DELAY_TICKS};
+
+// 3x3 chunks of air around the origin, with a stone floor at y = 0
+fn flat_world() -> World {
+    let mut world = World::new(0);
+    for x in -1..=1 {
+        for z in -1..=1 {
+            let mut chunk = Chunk::new();
+            for i in 0..16 * 16 {
+                chunk.set_at(UVec3::new(i % 16, 0, i / 16), Block::STONE);
+            }
+            world.insert_chunk(IVec3::new(x, 0, z), chunk);
+        }
+    }
+    world
+}
+
+struct Sim {
+    world: World,
+    fluids: FluidSim,
+    tick: u32,
+}
+
+impl Sim {
+    fn new() -> Self {
+        Self { world: flat_world(), fluids: FluidSim::new(), tick: 0 }
+    }
+
+    fn run(&mut self, ticks: u32) {
+        for _ in 0..ticks {
+            self.fluids.tick(&mut self.world, self.tick);
+            self.tick += 1;
+        }
+    }
+
+    fn settle(&mut self) {
+        self.run(1);
+        while self.fluids.pending_count() > 0 {
+            assert!(self.tick < 10_000, "Fluids never settled");
+            self.run(1);
+        }
+    }
+
+    fn fluid_at(&self, pos: IVec3) -> Option<FluidState> {
+        FluidState::of(self.world.block_at(pos).unwrap())
+    }
+
+    // Every block in the loaded area, to compare runs
+    fn snapshot(&self) -> Vec<u16> {
+        let mut blocks = Vec::new();
+        for x in -16..32 {
+            for z in -16..32 {
+                for y in 0..16 {
+                    blocks.push(self.world.block_at(IVec3::new(x, y, z)).unwrap().raw());
+                }
+            }
+        }
+        blocks
+    }
+}
+
+#[test]
+fn source_spreads_over_the_floor() {
+    let mut sim = Sim::new();
+    let source = IVec3::new(0, 1, 0);
+    assert!(place_water(&mut sim.world, &BlockRegistry::builtin(), source));
+
+    // One block per FLOW_DELAY_TICKS
+    sim.run(1);
+    sim.run(FLOW_DELAY_TICKS);
+    assert_eq!(sim.fluid_at(source + IVec3::X), Some(FluidState::Flowing(1)));
+    assert_eq!(sim.fluid_at(source + IVec3::X * 2), None);
+
+    sim.settle();
+    for x in -10..=10i32 {
+        for z in -10..=10i32 {
+            let distance = x.abs() + z.abs();
+            let expected = match distance {
+                0 => Some(FluidState::Source),
+                1..=7 => Some(FluidState::Flowing(distance as u8)),
+                _ => None,
+            };
+            assert_eq!(sim.fluid_at(IVec3::new(x, 1, z)), expected, "at {x}, {z}");
+            assert_eq!(sim.fluid_at(IVec3::new(x, 2, z)), None);
+        }
+    }
+}
+
+#[test]
+fn water_falls_before_spreading() {
+    let mut sim = Sim::new();
+    let source = IVec3::new(3, 6, 3);
+    place_water(&mut sim.world, &BlockRegistry::builtin(), source);
+    sim.settle();
+
+    for y in 1..6 {
+        assert_eq!(sim.fluid_at(IVec3::new(3, y, 3)), Some(FluidState::Falling));
+    }
+    // The source itself spreads a bit in mid-air, but what flows off it doesn't
+    assert_eq!(sim.fluid_at(source + IVec3::X), Some(FluidState::Flowing(1)));
+    assert_eq!(sim.fluid_at(source + IVec3::X * 2), None);
+    // Landing next to the column, so that's where the distance starts over
+    assert_eq!(sim.fluid_at(IVec3::new(4 + MAX_FLOW_DISTANCE as i32, 1, 3)), Some(FluidState::Flowing(MAX_FLOW_DISTANCE)));
+}
+
+#[test]
+fn removing_the_source_dries_everything_up() {
+    let mut sim = Sim::new();
+    let source = IVec3::new(5, 4, 5);
+    place_water(&mut sim.world, &BlockRegistry::builtin(), source);
+    sim.settle();
+
+    assert!(remove_water(&mut sim.world, source));
+    sim.settle();
+    assert!(sim.snapshot().iter().all(|&raw| FluidState::of(Block::from_raw(raw)).is_none()));
+}
+
+#[test]
+fn two_sources_make_a_third() {
+    let mut sim = Sim::new();
+    let blocks = BlockRegistry::builtin();
+    place_water(&mut sim.world, &blocks, IVec3::new(0, 1, 0));
+    place_water(&mut sim.world, &blocks, IVec3::new(2, 1, 0));
+    sim.settle();
+    assert_eq!(sim.fluid_at(IVec3::new(1, 1, 0)), Some(FluidState::Source));
+
+    // Which keeps the water around after the others are gone
+    remove_water(&mut sim.world, IVec3::new(0, 1, 0));
+    remove_water(&mut sim.world, IVec3::new(2, 1, 0));
+    sim.settle();
+    assert_eq!(sim.fluid_at(IVec3::new(1, 1, 0)), Some(FluidState::Source));
+    assert_eq!(sim.fluid_at(IVec3::new(0, 1, 0)), Some(FluidState::Flowing(1)));
+}
+
+#[test]
+fn waterlogged_blocks_are_sources() {
+    let mut sim = Sim::new();
+    let blocks = BlockRegistry::builtin();
+    let slab = Block::new(blocks.id_of("stone_slab").unwrap());
+    let pos = IVec3::new(4, 1, 4);
+    sim.world.set_block_at(pos, slab);
+
+    assert!(!place_water(&mut sim.world, &blocks, IVec3::new(4, 0, 4))); // stone
+    assert!(place_water(&mut sim.world, &blocks, pos));
+    assert_eq!(sim.world.block_at(pos), Some(slab.with_waterlogged(true)));
+
+    sim.settle();
+    assert_eq!(sim.world.block_at(pos).unwrap().id(), slab.id());
+    assert_eq!(sim.fluid_at(pos + IVec3::Z), Some(FluidState::Flowing(1)));
+
+    assert!(remove_water(&mut sim.world, pos));
+    sim.settle();
+    assert_eq!(sim.world.block_at(pos), Some(slab));
+    assert_eq!(sim.fluid_at(pos + IVec3::Z), None);
+}
+
+#[test]
+fn waterlogging_keeps_block_entities() {
+    let mut sim = Sim::new();
+    let blocks = BlockRegistry::builtin();
+    let slab = Block::new(blocks.id_of("stone_slab").unwrap());
+    let pos = IVec3::new(4, 1, 4);
+    let entity = BlockEntity { orientation: Orientation::Nz, ..BlockEntity::new(slab) };
+    assert!(sim.world.set_block_entity_at(pos, entity.clone()));
+
+    assert!(place_water(&mut sim.world, &blocks, pos));
+    assert_eq!(sim.world.block_at(pos), Some(slab.with_waterlogged(true)));
+    assert_eq!(sim.world.block_entity_at(pos).unwrap().orientation, Orientation::Nz);
+
+    assert!(remove_water(&mut sim.world, pos));
+    assert_eq!(sim.world.block_entity_at(pos), Some(&entity));
+}
+
+#[test]
+fn simulation_is_deterministic() {
+    let play = || {
+        let mut sim = Sim::new();
+        let blocks = BlockRegistry::builtin();
+        let mut snapshots = Vec::new();
+        for (i, pos) in [IVec3::new(0, 5, 0), IVec3::new(6, 1, 2), IVec3::new(-4, 3, 9)].into_iter().enumerate() {
+            place_water(&mut sim.world, &blocks, pos);
+            // Walls in the way, changed while the water is moving
+            sim.world.set_block_at(IVec3::new(i as i32 + 2, 1, 1), Block::DIRT);
+            sim.run(FLOW_DELAY_TICKS * 3 + i as u32);
+            snapshots.push(sim.snapshot());
+        }
+        sim.settle();
+        snapshots.push(sim.snapshot());
+        snapshots
+    };
+
+    assert!(play() == play());
+}
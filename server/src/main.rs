@@ -4,8 +4,18 @@ use log::{error, info};
 use runner::run;
 use server::Server;
 
+pub mod chat;
+pub mod chunk_streaming;
+pub mod commands;
+pub mod console;
+pub mod ecs;
+pub mod fluids;
+pub mod network_ids;
+pub mod players;
 pub mod runner;
 pub mod server;
+pub mod storage;
+pub mod world;
 
 fn main() {
     init_logger();
@@ -0,0 +1,86 @@
+// Hands out the NetworkIds players are known by. Ids that were freed only come back
+// after a while, so that anything still on its way about the last owner of an id can't
+// be mistaken for being about the next one.
+
+use std::collections::{HashSet, VecDeque};
+
+use shared::net::{NetworkId, RawNetworkId, MAX_ONLINE_PLAYERS};
+
+use crate::runner::TICKS_PER_SECOND;
+
+mod tests;
+
+/// How long a freed id stays unused.
+pub const RECYCLE_DELAY_TICKS: u32 = 5 * TICKS_PER_SECOND;
+
+/// Ids go up to this, and no further. Fresh ids are taken while the recycled ones are
+/// too recent, so without a limit they'd all be gone eventually. This leaves room for
+/// plenty of players coming and going within `RECYCLE_DELAY_TICKS` on top of the ones
+/// online.
+pub const MAX_NETWORK_ID: RawNetworkId = MAX_ONLINE_PLAYERS * 16;
+
+pub struct NetworkIdAllocator {
+    in_use: HashSet<NetworkId>,
+    // Oldest first, with the tick they were freed on
+    recycled: VecDeque<(NetworkId, u32)>,
+    // Never handed out before, up to MAX_NETWORK_ID
+    next_fresh: RawNetworkId,
+}
+
+impl Default for NetworkIdAllocator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NetworkIdAllocator {
+    pub fn new() -> Self {
+        Self {
+            in_use: HashSet::new(),
+            recycled: VecDeque::new(),
+            // 0 is NetworkId::INVALID
+            next_fresh: 1,
+        }
+    }
+
+    /// Returns None if the server is full, see `is_full()`, or if every free id was freed
+    /// too recently to be handed out again. That passes in `RECYCLE_DELAY_TICKS` at most.
+    pub fn allocate(&mut self, current_tick: u32) -> Option<NetworkId> {
+        if self.is_full() {
+            return None;
+        }
+
+        let nid = match self.recycled.front() {
+            Some(&(nid, freed_at)) if current_tick.saturating_sub(freed_at) >= RECYCLE_DELAY_TICKS => {
+                self.recycled.pop_front();
+                nid
+            }
+            // All of them are still too recent
+            _ if self.next_fresh <= MAX_NETWORK_ID => {
+                self.next_fresh += 1;
+                NetworkId::from_raw(self.next_fresh - 1)
+            }
+            _ => return None,
+        };
+        self.in_use.insert(nid);
+        Some(nid)
+    }
+
+    /// Returns false if the id wasn't in use.
+    pub fn free(&mut self, nid: NetworkId, current_tick: u32) -> bool {
+        if !self.in_use.remove(&nid) {
+            return false;
+        }
+        self.recycled.push_back((nid, current_tick));
+        true
+    }
+
+    pub fn in_use(&self) -> usize {
+        self.in_use.len()
+    }
+
+    /// `MAX_ONLINE_PLAYERS` ids are in use.
+    pub fn is_full(&self) -> bool {
+        self.in_use.len() >= MAX_ONLINE_PLAYERS as usize
+    }
+}
@@ -0,0 +1,54 @@
+#![cfg(test)]
+
+use std::collections::HashSet;
+
+use shared::net::{NetworkId, MAX_ONLINE_PLAYERS};
+
+use super::{NetworkIdAllocator, MAX_NETWORK_ID, RECYCLE_DELAY_TICKS};
+
+#[test]
+fn ids_are_unique_and_bounded() {
+    let mut ids = NetworkIdAllocator::new();
+    let allocated = (0..MAX_ONLINE_PLAYERS).map(|_| ids.allocate(0).unwrap()).collect::<HashSet<_>>();
+    assert_eq!(allocated.len(), MAX_ONLINE_PLAYERS as usize);
+    assert!(!allocated.contains(&NetworkId::INVALID));
+    assert_eq!(ids.allocate(0), None);
+
+    let freed = *allocated.iter().next().unwrap();
+    assert!(ids.free(freed, 0));
+    assert!(!ids.free(freed, 0));
+    assert!(!allocated.contains(&ids.allocate(0).unwrap()));
+}
+
+#[test]
+fn freed_ids_come_back_later() {
+    let mut ids = NetworkIdAllocator::new();
+    let first = ids.allocate(0).unwrap();
+    ids.free(first, 10);
+
+    let too_soon = ids.allocate(10 + RECYCLE_DELAY_TICKS - 1).unwrap();
+    assert_ne!(too_soon, first);
+    assert_eq!(ids.allocate(10 + RECYCLE_DELAY_TICKS), Some(first));
+    assert_eq!(ids.in_use(), 2);
+}
+
+#[test]
+fn ids_never_run_out_for_good() {
+    let mut ids = NetworkIdAllocator::new();
+    // Someone reconnecting every tick, for way longer than there are ids
+    for tick in 0..u16::MAX as u32 * 4 {
+        let nid = ids.allocate(tick).expect("Ran out of ids");
+        assert!(nid.raw() <= MAX_NETWORK_ID);
+        ids.free(nid, tick);
+    }
+
+    // Everyone at once though, and they do run out until the first ones come back
+    let mut ids = NetworkIdAllocator::new();
+    for _ in 0..MAX_NETWORK_ID {
+        let nid = ids.allocate(0).unwrap();
+        ids.free(nid, 0);
+    }
+    assert_eq!(ids.allocate(1), None);
+    assert!(!ids.is_full());
+    assert!(ids.allocate(RECYCLE_DELAY_TICKS).is_some());
+}
@@ -0,0 +1,88 @@
+// Players are entities in the ECS, spawned when they join and despawned when they
+// leave. This keeps track of which entity is which player, by NetworkId.
+
+use std::collections::HashMap;
+
+use glam::{Vec2, Vec3};
+use hecs::Entity;
+use shared::net::NetworkId;
+
+use crate::ecs::{Connection, HeadRotation, Position, Username, ECS};
+
+#[derive(Default)]
+pub struct Players {
+    entities: HashMap<NetworkId, Entity>,
+}
+
+impl Players {
+    pub fn new() -> Self {
+        Self { entities: HashMap::new() }
+    }
+
+    pub fn spawn(
+        &mut self,
+        ecs: &mut ECS,
+        nid: NetworkId,
+        username: &str,
+        position: Vec3,
+        head_rotation: Vec2,
+        connection: Connection,
+    ) -> Entity {
+        let entity = ecs.spawn((
+            nid,
+            Username(username.into()),
+            Position(position),
+            HeadRotation(head_rotation),
+            connection,
+        ));
+        if let Some(old) = self.entities.insert(nid, entity) {
+            // Ids aren't handed out twice, so this shouldn't happen
+            _ = ecs.despawn(old);
+        }
+        entity
+    }
+
+    /// Returns false if there's no such player.
+    pub fn despawn(&mut self, ecs: &mut ECS, nid: NetworkId) -> bool {
+        let Some(entity) = self.entities.remove(&nid) else {
+            return false;
+        };
+        _ = ecs.despawn(entity);
+        true
+    }
+
+    pub fn entity(&self, nid: NetworkId) -> Option<Entity> {
+        self.entities.get(&nid).copied()
+    }
+
+    pub fn username(&self, ecs: &ECS, nid: NetworkId) -> Option<Box<str>> {
+        let username = ecs.get::<Username>(self.entity(nid)?).ok()?;
+        Some(username.0.clone())
+    }
+
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+
+    /// See `Connection::kick()`. Returns false if there's no such player, or they
+    /// were already kicked.
+    pub fn kick(&self, ecs: &ECS, nid: NetworkId, reason: &str) -> bool {
+        let Some(mut connection) = self.entity(nid).and_then(|entity| ecs.get_mut::<Connection>(entity).ok()) else {
+            return false;
+        };
+        connection.kick(reason)
+    }
+}
+
+/// Ignores case. The server turns away anyone whose name is already online, ignoring
+/// case too, so there's only ever one match.
+pub fn find(ecs: &ECS, username: &str) -> Option<NetworkId> {
+    ecs.query::<(&NetworkId, &Username)>()
+        .iter()
+        .find(|(_, (_, name))| name.0.eq_ignore_ascii_case(username))
+        .map(|(_, (nid, _))| *nid)
+}
@@ -21,7 +21,7 @@ pub fn run(server: &mut Server) {
     let mut updates = 0;
 
     let server_start_time = Instant::now();
-    while !SHOULD_STOP.load(Ordering::Relaxed) {
+    while !SHOULD_STOP.load(Ordering::Relaxed) && !server.state.stopping {
         if let Err(e) = Server::tick(server) {
             error!("Error while ticking server: {e}");
         }
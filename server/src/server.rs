@@ -1,15 +1,59 @@
-use glam::{Vec3, Vec2};
-use log::{error, info};
+use std::{
+    collections::{HashMap, HashSet},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Context;
+
+use glam::{IVec3, Vec3, Vec2};
+use log::{debug, error, info, warn};
 use netcode::{message::{InMsg, ServerMsg}, NetServer, login_listener::LoginResponse};
-use shared::net::NetworkId;
+use shared::{net::{ChatEvent, NetworkId}, world::{chunk::WorldBlockPosExt, registry::BlockRegistry}};
+
+use crate::{
+    chat::Chat,
+    chunk_streaming::ChunkStream,
+    commands::{CommandSender, Commands},
+    console::Console,
+    ecs::{Connection, HeadRotation, Position, ECS},
+    fluids::FluidSim,
+    network_ids::NetworkIdAllocator,
+    players::{self, Players},
+    runner::TICKS_PER_SECOND,
+    storage::WorldStorage,
+    world::World,
+};
+
+/// Where the world is saved, relative to the working directory.
+pub const WORLD_DIR: &str = "world";
+
+// How often the chunks no player needs anymore are unloaded
+const UNLOAD_INTERVAL_TICKS: u32 = 10 * TICKS_PER_SECOND;
 
 pub struct State {
     pub current_tick: u32,
     pub net_server: NetServer,
+    pub world: World,
+    pub blocks: BlockRegistry,
+    pub fluids: FluidSim,
+    pub spawn_position: Vec3,
+    pub chunk_streams: HashMap<NetworkId, ChunkStream>,
+    pub chat: Chat,
+    pub ecs: ECS,
+    /// Which entity each player is
+    pub players: Players,
+    pub network_ids: NetworkIdAllocator,
+    /// Set by /stop, the server stops after this tick
+    pub stopping: bool,
+    chunk_buf: Vec<u8>,
 }
 
 pub struct Server {
     pub state: State,
+    // Not part of the state so that commands can have all of it
+    pub commands: Commands,
+    console: Console,
+    next_unload_tick: u32,
 }
 
 impl Server {}
@@ -20,57 +64,212 @@ impl Server {
         if let Err(e) = self.process_net_messages() {
             error!("Error while processing incoming network data: {e}");
         }
+        while let Some(command) = self.console.poll() {
+            self.commands.run(&mut self.state, CommandSender::Console, &command);
+        }
+
+        let State { world, fluids, current_tick, .. } = &mut self.state;
+        fluids.tick(world, *current_tick);
+
+        self.stream_chunks();
+        if self.state.current_tick >= self.next_unload_tick {
+            self.unload_chunks();
+            self.next_unload_tick = self.state.current_tick + UNLOAD_INTERVAL_TICKS;
+        }
 
         self.state.current_tick += 1;
         Ok(())
     }
 
     fn process_net_messages(&mut self) -> anyhow::Result<()> {
+        let spawn_position = self.state.spawn_position;
+        let world_seed = self.state.world.seed();
+        let block_checksum = self.state.blocks.checksum();
+        let current_tick = self.state.current_tick;
+
         let Some(channels) = self.state.net_server.channels() else {
             return Ok(());
         };
 
         while let Ok(msg) = channels.server_messages.try_recv() {
             match msg {
-                ServerMsg::LoginRequest { username: _, id_channel } => {
-                    _ = id_channel.send(LoginResponse::Accepted {
-                        nid: NetworkId::from_raw(0),
-                        position: Vec3::ZERO,
+                ServerMsg::LoginRequest { username, block_checksum: client_checksum, id_channel } => {
+                    if client_checksum != block_checksum {
+                        info!("Turned {username} away, their block definitions don't match");
+                        _ = id_channel.send(LoginResponse::Denied {
+                            reason: "Block definitions don't match the server's".into(),
+                        });
+                        continue;
+                    }
+                    // Operators go by their username, so nobody gets to be someone else
+                    if players::find(&self.state.ecs, &username).is_some() {
+                        info!("Turned {username} away, they're already online");
+                        _ = id_channel.send(LoginResponse::Denied {
+                            reason: "Someone with that name is already online".into(),
+                        });
+                        continue;
+                    }
+                    let Some(nid) = self.state.network_ids.allocate(current_tick) else {
+                        let reason = if self.state.network_ids.is_full() {
+                            info!("Turned {username} away, the server is full");
+                            "The server is full"
+                        } else {
+                            // Lots of players came and went just now
+                            info!("Turned {username} away, no network ids are free yet");
+                            "Too many logins at once, try again in a few seconds"
+                        };
+                        _ = id_channel.send(LoginResponse::Denied { reason: reason.into() });
+                        continue;
+                    };
+                    let accepted = id_channel.send(LoginResponse::Accepted {
+                        nid,
+                        position: spawn_position,
                         head_rotation: Vec2::ZERO,
-                        world_seed: 0,
+                        world_seed,
                     });
+                    // Gave up on logging in already, so there won't be a PlayerLeft
+                    if accepted.is_err() {
+                        self.state.network_ids.free(nid, current_tick);
+                    }
                 },
                 ServerMsg::PlayerJoined(info) => {
+                    // Logged in twice at the same time, the first one to get here wins
+                    if players::find(&self.state.ecs, &info.username).is_some() {
+                        info!("Kicked {} ({}), they're already online", info.username, info.nid);
+                        Connection::new(info.channels.kick, info.channels.state).kick("Someone with that name is already online");
+                        continue;
+                    }
                     info!("Player {} joined! ({})", info.username, info.nid);
+                    let stream = ChunkStream::new(info.channels.chunks, chunk_pos_of(spawn_position));
+                    self.state.chunk_streams.insert(info.nid, stream);
+                    self.state.chat.player_joined(info.nid, &info.username, info.channels.chat);
+                    self.state.players.spawn(
+                        &mut self.state.ecs,
+                        info.nid,
+                        &info.username,
+                        spawn_position,
+                        Vec2::ZERO,
+                        Connection::new(info.channels.kick, info.channels.state),
+                    );
                 },
                 ServerMsg::PlayerLeft(nid) => {
                     info!("Player {} left", nid);
+                    self.state.chunk_streams.remove(&nid);
+                    self.state.chat.player_left(nid);
+                    self.state.players.despawn(&mut self.state.ecs, nid);
+                    self.state.network_ids.free(nid, current_tick);
                 },
             }
         }
 
         while let Some((nid, bytes)) = self.state.net_server.poll() {
             match InMsg::decode(&bytes) {
-                InMsg::Chat(msg) => info!("Received chat message '{msg}' from {nid}"),
+                Some(InMsg::Chat(msg)) => {
+                    if let Some(command) = self.state.chat.on_message(nid, msg, self.state.current_tick) {
+                        self.commands.run(&mut self.state, CommandSender::Player(nid), &command);
+                    }
+                },
+                Some(InMsg::ChunkRequest(request)) => {
+                    if let Some(stream) = self.state.chunk_streams.get_mut(&nid) {
+                        stream.handle_request(request);
+                    }
+                },
+                Some(InMsg::Complete(input)) => {
+                    let level = self.commands.permission_level(CommandSender::Player(nid), &self.state.ecs, &self.state.players);
+                    let suggestions = self.commands.complete(level, input, &self.state.ecs);
+                    self.state.chat.send_event(nid, &ChatEvent::Completions { input: input.into(), suggestions });
+                },
+                Some(InMsg::Move { position, head_rotation }) => {
+                    let State { ecs, players, .. } = &mut self.state;
+                    let player = players.entity(nid).and_then(|entity| ecs.query_one_mut::<(&mut Position, &mut HeadRotation)>(entity).ok());
+                    if let Some((pos, rotation)) = player {
+                        pos.0 = position;
+                        rotation.0 = head_rotation;
+                    }
+                },
+                None => warn!("Received malformed message from {nid}"),
             }
         }
         Ok(())
     }
+
+    fn stream_chunks(&mut self) {
+        let State { world, chunk_streams, chunk_buf, ecs, players, .. } = &mut self.state;
+
+        for (&nid, stream) in chunk_streams.iter_mut() {
+            if let Some(position) = players.entity(nid).and_then(|entity| ecs.get::<Position>(entity).ok()) {
+                stream.set_center(chunk_pos_of(position.0));
+            }
+        }
+
+        for chunk_pos in world.drain_dirty().collect::<Vec<_>>() {
+            for stream in chunk_streams.values_mut() {
+                stream.on_chunk_modified(chunk_pos);
+            }
+        }
+
+        for stream in chunk_streams.values_mut() {
+            stream.send_pending(world, ChunkStream::CHUNKS_PER_TICK, chunk_buf);
+        }
+    }
+
+    fn unload_chunks(&mut self) {
+        let State { world, chunk_streams, .. } = &mut self.state;
+        let needed = chunk_streams.values().flat_map(ChunkStream::chunks).collect::<HashSet<_>>();
+
+        match world.unload_unneeded(|chunk_pos| needed.contains(&chunk_pos)) {
+            Ok(0) => {}
+            Ok(unloaded) => debug!("Unloaded {unloaded} chunks, {} left", world.loaded_chunk_count()),
+            Err(e) => error!("Couldn't unload chunks: {e:#}"),
+        }
+    }
+}
+
+fn chunk_pos_of(position: Vec3) -> IVec3 {
+    position.floor().as_ivec3().to_chunk_pos()
 }
 
 impl Server {
     pub fn start() -> anyhow::Result<Self> {
+        // Only used if there's no world yet
+        let new_seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_nanos() as u64);
+        let world = World::open(WorldStorage::open(WORLD_DIR)?, new_seed).context("Couldn't open the world")?;
+        info!("World seed: {}", world.seed());
+
+        let spawn_height = world.generator().column_at(0, 0).height;
+
         let state = State {
             current_tick: 0,
             net_server: NetServer::start("0.0.0.0:29477".parse().unwrap())?,
+            world,
+            blocks: BlockRegistry::builtin(),
+            fluids: FluidSim::new(),
+            spawn_position: Vec3::new(0.5, spawn_height as f32 + 2.0, 0.5),
+            chunk_streams: HashMap::new(),
+            chat: Chat::new(),
+            ecs: ECS::new(),
+            players: Players::new(),
+            network_ids: NetworkIdAllocator::new(),
+            stopping: false,
+            chunk_buf: Vec::new(),
         };
 
-        let server = Server { state };
+        let server = Server {
+            state,
+            commands: Commands::new(),
+            console: Console::start(),
+            next_unload_tick: UNLOAD_INTERVAL_TICKS,
+        };
 
         Ok(server)
     }
 
-    pub fn shutdown(self: Server) -> anyhow::Result<()> {
+    pub fn shutdown(mut self: Server) -> anyhow::Result<()> {
+        self.state.net_server.stop();
+        let saved = self.state.world.save().context("Couldn't save the world")?;
+        info!("Saved {saved} chunks");
         Ok(())
     }
 }
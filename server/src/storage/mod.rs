@@ -0,0 +1,82 @@
+// Where the world goes between runs: a directory with the seed in `seed.txt`, and every
+// chunk that's been changed from what the generator makes in `chunks/`, one file each in
+// the network format. Anything else gets generated again.
+
+use std::{fs, io, path::PathBuf};
+
+use anyhow::Context;
+use glam::IVec3;
+use shared::{
+    serialization::{ByteReader, ByteWriter},
+    world::chunk::Chunk,
+};
+
+mod tests;
+
+pub struct WorldStorage {
+    dir: PathBuf,
+}
+
+impl WorldStorage {
+    /// Creates the directory if it isn't there yet.
+    pub fn open(dir: impl Into<PathBuf>) -> anyhow::Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(dir.join("chunks")).with_context(|| format!("Couldn't create {}", dir.display()))?;
+        Ok(Self { dir })
+    }
+
+    /// None for a new world.
+    pub fn load_seed(&self) -> anyhow::Result<Option<u64>> {
+        let Some(seed) = read_if_exists(self.dir.join("seed.txt"))? else {
+            return Ok(None);
+        };
+        let seed = String::from_utf8(seed).ok()
+            .and_then(|seed| seed.trim().parse().ok())
+            .context("The seed file is corrupted")?;
+        Ok(Some(seed))
+    }
+
+    pub fn save_seed(&self, seed: u64) -> anyhow::Result<()> {
+        write_atomically(self.dir.join("seed.txt"), seed.to_string().as_bytes())
+    }
+
+    /// None if the chunk was never saved.
+    pub fn load_chunk(&self, chunk_pos: IVec3) -> anyhow::Result<Option<Box<Chunk>>> {
+        let Some(bytes) = read_if_exists(self.chunk_path(chunk_pos))? else {
+            return Ok(None);
+        };
+        let mut reader = ByteReader::new(&bytes);
+        let chunk = Chunk::decode(&mut reader)
+            .filter(|_| !reader.has_n_more(1))
+            .with_context(|| format!("Chunk {chunk_pos} is corrupted"))?;
+        Ok(Some(chunk))
+    }
+
+    /// `buf` is only there to be reused between chunks.
+    pub fn save_chunk(&self, chunk_pos: IVec3, chunk: &Chunk, buf: &mut Vec<u8>) -> anyhow::Result<()> {
+        buf.resize(Chunk::MAX_ENCODED_SIZE, 0);
+        let mut writer = ByteWriter::new(buf);
+        chunk.encode(&mut writer);
+        write_atomically(self.chunk_path(chunk_pos), writer.bytes())
+    }
+
+    fn chunk_path(&self, IVec3 { x, y, z }: IVec3) -> PathBuf {
+        self.dir.join("chunks").join(format!("{x}_{y}_{z}.chunk"))
+    }
+}
+
+fn read_if_exists(path: PathBuf) -> anyhow::Result<Option<Vec<u8>>> {
+    match fs::read(&path) {
+        Ok(bytes) => Ok(Some(bytes)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e).with_context(|| format!("Couldn't read {}", path.display())),
+    }
+}
+
+// So that crashing halfway through leaves the old file there instead of half a new one
+fn write_atomically(path: PathBuf, bytes: &[u8]) -> anyhow::Result<()> {
+    let tmp_path = path.with_extension("tmp");
+    fs::write(&tmp_path, bytes)
+        .and_then(|_| fs::rename(&tmp_path, &path))
+        .with_context(|| format!("Couldn't write {}", path.display()))
+}
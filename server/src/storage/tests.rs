@@ -0,0 +1,68 @@
+#![cfg(test)]
+
+use std::fs;
+
+use glam::IVec3;
+use shared::world::block::Block;
+
+use crate::world::World;
+
+use super::WorldStorage;
+
+#[test]
+fn changes_survive_a_restart() {
+    let dir = std::env::temp_dir().join(format!("server01-storage-test-{}", std::process::id()));
+    _ = fs::remove_dir_all(&dir);
+
+    let mut world = World::open(WorldStorage::open(&dir).unwrap(), 1234).unwrap();
+    let chunk_pos = IVec3::new(-1, 2, 3);
+    world.load_or_generate(chunk_pos);
+    world.load_or_generate(IVec3::ZERO);
+    let pos = chunk_pos * 16 + IVec3::new(1, 2, 3);
+    // Whatever the generator didn't put there
+    let block = if world.block_at(pos) == Some(Block::STONE) { Block::AIR } else { Block::STONE };
+    assert!(world.set_block_at(pos, block));
+    assert_eq!(world.save().unwrap(), 1);
+    // Nothing new to save
+    assert_eq!(world.save().unwrap(), 0);
+
+    // The seed given to an existing world doesn't matter
+    let mut world = World::open(WorldStorage::open(&dir).unwrap(), 5678).unwrap();
+    assert_eq!(world.seed(), 1234);
+    world.load_or_generate(chunk_pos);
+    assert_eq!(world.block_at(pos), Some(block));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn unloading_saves_changes_first() {
+    let dir = std::env::temp_dir().join(format!("server01-unload-test-{}", std::process::id()));
+    _ = fs::remove_dir_all(&dir);
+
+    let mut world = World::open(WorldStorage::open(&dir).unwrap(), 1234).unwrap();
+    let kept = IVec3::new(5, 0, 0);
+    world.load_or_generate(kept);
+    world.load_or_generate(IVec3::ZERO);
+    let pos = IVec3::new(1, 2, 3);
+    let block = if world.block_at(pos) == Some(Block::STONE) { Block::AIR } else { Block::STONE };
+    assert!(world.set_block_at(pos, block));
+
+    assert_eq!(world.unload_unneeded(|chunk_pos| chunk_pos == kept).unwrap(), 1);
+    assert!(world.is_loaded(kept));
+    assert!(!world.is_loaded(IVec3::ZERO));
+    // Already saved
+    assert_eq!(world.save().unwrap(), 0);
+    world.load_or_generate(IVec3::ZERO);
+    assert_eq!(world.block_at(pos), Some(block));
+
+    // With nowhere to save them, changed chunks have to stay
+    let mut world = World::new(1234);
+    world.load_or_generate(IVec3::ZERO);
+    world.load_or_generate(kept);
+    assert!(world.set_block_at(pos, block));
+    assert_eq!(world.unload_unneeded(|_| false).unwrap(), 1);
+    assert!(world.is_loaded(IVec3::ZERO));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
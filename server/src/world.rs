@@ -0,0 +1,213 @@
+use std::collections::{HashMap, HashSet};
+
+use glam::IVec3;
+use log::warn;
+use shared::{
+    world::{
+        block::Block,
+        block_entity::BlockEntity,
+        chunk::{Chunk, WorldBlockPos, WorldBlockPosExt},
+    },
+    worldgen::TerrainGenerator,
+};
+
+use crate::storage::WorldStorage;
+
+/// The authoritative copy of the world. Clients only ever get to see
+/// (parts of) this, so every block change has to go through here.
+///
+/// Chunks touched since the last call to `drain_dirty()` are tracked, so that
+/// the changes can be sent out to the clients, and separately since the last
+/// `save()`. So are the individual blocks, for whatever needs to react to them
+/// (fluids). Chunks stay loaded until `unload_unneeded()`.
+pub struct World {
+    chunks: HashMap<IVec3, Box<Chunk>>,
+    dirty: HashSet<IVec3>,
+    unsaved: HashSet<IVec3>,
+    changed_blocks: Vec<WorldBlockPos>,
+    generator: TerrainGenerator,
+    storage: Option<WorldStorage>,
+}
+
+impl World {
+    /// Only kept in memory.
+    pub fn new(seed: u64) -> Self {
+        Self {
+            chunks: HashMap::new(),
+            dirty: HashSet::new(),
+            unsaved: HashSet::new(),
+            changed_blocks: Vec::new(),
+            generator: TerrainGenerator::new(seed),
+            storage: None,
+        }
+    }
+
+    /// Continues the world in `storage`, or starts a new one there with `new_seed`.
+    pub fn open(storage: WorldStorage, new_seed: u64) -> anyhow::Result<Self> {
+        let seed = match storage.load_seed()? {
+            Some(seed) => seed,
+            None => {
+                storage.save_seed(new_seed)?;
+                new_seed
+            }
+        };
+        Ok(Self { storage: Some(storage), ..Self::new(seed) })
+    }
+
+    /// Writes out every chunk changed since the last save, returning how many.
+    /// Does nothing for worlds that aren't stored anywhere.
+    pub fn save(&mut self) -> anyhow::Result<usize> {
+        let Some(storage) = &self.storage else {
+            return Ok(0);
+        };
+
+        let mut buf = Vec::new();
+        let mut saved = 0;
+        for chunk_pos in self.unsaved.iter().copied().collect::<Vec<_>>() {
+            if let Some(chunk) = self.chunks.get(&chunk_pos) {
+                storage.save_chunk(chunk_pos, chunk, &mut buf)?;
+                saved += 1;
+            }
+            // Only once it's saved, so the rest is tried again next time
+            self.unsaved.remove(&chunk_pos);
+        }
+        Ok(saved)
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.generator.seed()
+    }
+
+    pub fn generator(&self) -> &TerrainGenerator {
+        &self.generator
+    }
+
+    pub fn is_loaded(&self, chunk_pos: IVec3) -> bool {
+        self.chunks.contains_key(&chunk_pos)
+    }
+
+    pub fn chunk(&self, chunk_pos: IVec3) -> Option<&Chunk> {
+        self.chunks.get(&chunk_pos).map(|chunk| &**chunk)
+    }
+
+    pub fn loaded_chunk_count(&self) -> usize {
+        self.chunks.len()
+    }
+
+    /// Loads the chunk from storage, or generates it if it was never saved. Freshly
+    /// generated chunks are not marked as modified, because anyone can reproduce them
+    /// from the seed.
+    pub fn load_or_generate(&mut self, chunk_pos: IVec3) -> &Chunk {
+        let Self { chunks, generator, storage, .. } = self;
+        chunks
+            .entry(chunk_pos)
+            .or_insert_with(|| {
+                let stored = storage.as_ref().map_or(Ok(None), |storage| storage.load_chunk(chunk_pos));
+                match stored {
+                    Ok(Some(chunk)) => chunk,
+                    Ok(None) => generator.generate_chunk(chunk_pos),
+                    Err(e) => {
+                        warn!("Generating chunk {chunk_pos} again: {e:#}");
+                        generator.generate_chunk(chunk_pos)
+                    }
+                }
+            })
+    }
+
+    /// Inserts a chunk, replacing (and returning) any chunk at the same position.
+    /// The chunk counts as modified.
+    pub fn insert_chunk(&mut self, chunk_pos: IVec3, chunk: Box<Chunk>) -> Option<Box<Chunk>> {
+        self.dirty.insert(chunk_pos);
+        self.unsaved.insert(chunk_pos);
+        self.chunks.insert(chunk_pos, chunk)
+    }
+
+    /// Any changes that weren't saved yet are lost.
+    pub fn remove_chunk(&mut self, chunk_pos: IVec3) -> Option<Box<Chunk>> {
+        self.dirty.remove(&chunk_pos);
+        self.unsaved.remove(&chunk_pos);
+        self.chunks.remove(&chunk_pos)
+    }
+
+    /// Unloads every chunk that `needed` says no to, saving the ones with changes first.
+    /// Changed chunks of a world that isn't stored anywhere stay loaded, as there's
+    /// nowhere to save them. Returns how many were unloaded.
+    pub fn unload_unneeded(&mut self, mut needed: impl FnMut(IVec3) -> bool) -> anyhow::Result<usize> {
+        let unneeded = self.chunks.keys().copied().filter(|&chunk_pos| !needed(chunk_pos)).collect::<Vec<_>>();
+
+        let mut buf = Vec::new();
+        let mut unloaded = 0;
+        for chunk_pos in unneeded {
+            if self.unsaved.contains(&chunk_pos) {
+                let Some(storage) = &self.storage else {
+                    continue;
+                };
+                // Stays loaded if this fails, so nothing is lost
+                storage.save_chunk(chunk_pos, &self.chunks[&chunk_pos], &mut buf)?;
+            }
+            self.remove_chunk(chunk_pos);
+            unloaded += 1;
+        }
+        Ok(unloaded)
+    }
+
+    /// Returns None if the chunk containing the block isn't loaded.
+    pub fn block_at(&self, pos: WorldBlockPos) -> Option<Block> {
+        self.chunk(pos.to_chunk_pos())
+            .map(|chunk| chunk.get_at(pos.to_local()))
+    }
+
+    /// Returns false (and does nothing) if the chunk containing the block isn't loaded.
+    pub fn set_block_at(&mut self, pos: WorldBlockPos, block: Block) -> bool {
+        let chunk_pos = pos.to_chunk_pos();
+        let Some(chunk) = self.chunks.get_mut(&chunk_pos) else {
+            return false;
+        };
+
+        if chunk.get_at(pos.to_local()) != block {
+            chunk.set_at(pos.to_local(), block);
+            self.dirty.insert(chunk_pos);
+            self.unsaved.insert(chunk_pos);
+            self.changed_blocks.push(pos);
+        }
+        true
+    }
+
+    /// Returns None if there's no block entity there, or the chunk isn't loaded.
+    pub fn block_entity_at(&self, pos: WorldBlockPos) -> Option<&BlockEntity> {
+        self.chunk(pos.to_chunk_pos())?.block_entity_at(pos.to_local())
+    }
+
+    /// Returns false (and does nothing) if the chunk isn't loaded, or it won't take
+    /// the entity, see `Chunk::set_block_entity_at()`.
+    pub fn set_block_entity_at(&mut self, pos: WorldBlockPos, entity: BlockEntity) -> bool {
+        let chunk_pos = pos.to_chunk_pos();
+        let Some(chunk) = self.chunks.get_mut(&chunk_pos) else {
+            return false;
+        };
+
+        if !chunk.set_block_entity_at(pos.to_local(), entity) {
+            return false;
+        }
+        self.dirty.insert(chunk_pos);
+        self.unsaved.insert(chunk_pos);
+        self.changed_blocks.push(pos);
+        true
+    }
+
+    pub fn is_dirty(&self, chunk_pos: IVec3) -> bool {
+        self.dirty.contains(&chunk_pos)
+    }
+
+    /// Returns the positions of all chunks modified since the last call, and
+    /// marks them clean.
+    pub fn drain_dirty(&mut self) -> impl Iterator<Item = IVec3> + '_ {
+        self.dirty.drain()
+    }
+
+    /// Returns the blocks changed since the last call, in the order they were changed.
+    /// Whole chunks being inserted or generated don't count.
+    pub fn drain_changed_blocks(&mut self) -> impl Iterator<Item = WorldBlockPos> + '_ {
+        self.changed_blocks.drain(..)
+    }
+}
@@ -42,3 +42,9 @@ impl<T> AntiJitterBuf<T> {
         result
     }
 }
+
+impl<T> Default for AntiJitterBuf<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1,6 +1,9 @@
 pub mod anti_jitter;
 pub mod net;
 pub mod serialization;
+pub mod util;
+pub mod world;
+pub mod worldgen;
 
 use std::time::Duration;
 
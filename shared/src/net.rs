@@ -1,13 +1,33 @@
+use glam::{IVec3, Vec3};
 
-pub const PROTOCOL_VERSION: u16 = 0;
+use crate::serialization::{ByteReader, ByteWriter};
+
+
+pub const PROTOCOL_VERSION: u16 = 5;
 pub const PROTOCOL_MAGIC: u16 = 0xB7C1;
 
 pub const MAX_ONLINE_PLAYERS: u16 = 64;
 
+/// The longest chat message a player can send, in bytes.
+pub const MAX_CHAT_MESSAGE_LEN: usize = 256;
+
+/// At most this many tab completions are sent back.
+pub const MAX_COMPLETIONS: usize = 32;
+
+/// The first byte written to every stream the client opens, telling the server what
+/// the stream is for. (QUIC doesn't tell the peer about a new stream until something
+/// has been written to it anyway.)
+pub mod stream_id {
+    pub const CHUNKS: u8 = 1;
+    pub const CHAT: u8 = 2;
+    /// Where the player is and which way they're looking, and `GameEvent`s coming back
+    pub const STATE: u8 = 3;
+}
+
 pub type RawNetworkId = u16;
 
 // A per-entity unique identifier shared with all connected clients to identify entities.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct NetworkId(RawNetworkId);
 
 impl NetworkId {
@@ -28,3 +48,226 @@ impl std::fmt::Display for NetworkId {
     }
 }
 
+
+/// Sent by the client on the chunk stream whenever the set of chunks it wants changes,
+/// so that the server never has to know the client's view distance. `center` is the
+/// chunk the player is in. The server doesn't go by it though, but by where it thinks
+/// the player is: it only sends the chunks around that, closest first.
+///
+/// Cancelling a chunk that has already been received tells the server the client has
+/// unloaded it, so it won't get updates for it anymore.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChunkRequest {
+    pub center: IVec3,
+    pub requested: Vec<IVec3>,
+    pub cancelled: Vec<IVec3>,
+}
+
+impl ChunkRequest {
+    /// Keeps every request comfortably within the u16 message length limit.
+    /// Bigger changes just need to be split into multiple requests.
+    pub const MAX_POSITIONS: usize = 2048;
+
+    pub fn encoded_len(&self) -> usize {
+        12 + 2 + 2 + (self.requested.len() + self.cancelled.len()) * 12
+    }
+
+    pub fn encode(&self, writer: &mut ByteWriter) {
+        debug_assert!(self.requested.len() + self.cancelled.len() <= Self::MAX_POSITIONS);
+
+        write_ivec3(writer, self.center);
+        writer.write_u16(self.requested.len() as u16);
+        self.requested.iter().for_each(|&pos| write_ivec3(writer, pos));
+        writer.write_u16(self.cancelled.len() as u16);
+        self.cancelled.iter().for_each(|&pos| write_ivec3(writer, pos));
+    }
+
+    /// Comes straight from the client, so returns None rather than panicking on garbage.
+    pub fn decode(reader: &mut ByteReader) -> Option<Self> {
+        fn read_positions(reader: &mut ByteReader) -> Option<Vec<IVec3>> {
+            if !reader.has_n_more(2) {
+                return None;
+            }
+            let count = reader.read_u16() as usize;
+            if count > ChunkRequest::MAX_POSITIONS || !reader.has_n_more(count * 12) {
+                return None;
+            }
+            Some((0..count).map(|_| read_ivec3(reader)).collect())
+        }
+
+        if !reader.has_n_more(12) {
+            return None;
+        }
+        Some(Self {
+            center: read_ivec3(reader),
+            requested: read_positions(reader)?,
+            cancelled: read_positions(reader)?,
+        })
+    }
+}
+
+/// A chat line, as the server sends it out to every client. Clients only ever send the
+/// text, the server fills in who it's from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChatMessage {
+    /// `NetworkId::INVALID` for messages from the server itself
+    pub sender: NetworkId,
+    /// Empty for messages from the server
+    pub sender_name: Box<str>,
+    pub text: Box<str>,
+}
+
+impl ChatMessage {
+    pub fn from_server(text: impl Into<Box<str>>) -> Self {
+        Self {
+            sender: NetworkId::INVALID,
+            sender_name: "".into(),
+            text: text.into(),
+        }
+    }
+
+    pub fn from_server_itself(&self) -> bool {
+        self.sender == NetworkId::INVALID
+    }
+
+    pub fn encoded_len(&self) -> usize {
+        2 + 2 + self.sender_name.len() + 2 + self.text.len()
+    }
+
+    pub fn encode(&self, writer: &mut ByteWriter) {
+        writer
+            .write_u16(self.sender.raw())
+            .write_str(&self.sender_name)
+            .write_str(&self.text);
+    }
+
+    /// Returns None if the message is malformed.
+    pub fn decode(reader: &mut ByteReader) -> Option<Self> {
+        if !reader.has_n_more(2) {
+            return None;
+        }
+        Some(Self {
+            sender: NetworkId::from_raw(reader.read_u16()),
+            sender_name: reader.try_read_str()?.into(),
+            text: reader.try_read_str()?.into(),
+        })
+    }
+}
+
+impl std::fmt::Display for ChatMessage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.from_server_itself() {
+            write!(f, "[Server] {}", self.text)
+        } else {
+            write!(f, "<{}> {}", self.sender_name, self.text)
+        }
+    }
+}
+
+/// What the server sends on the chat stream: the chat itself, and tab completions.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChatEvent {
+    Message(ChatMessage),
+    /// The answer to a tab completion request. `input` is the line it's for, and the
+    /// suggestions replace its last word.
+    Completions { input: Box<str>, suggestions: Vec<Box<str>> },
+}
+
+impl ChatEvent {
+    const MESSAGE: u8 = 1;
+    const COMPLETIONS: u8 = 2;
+
+    pub fn encoded_len(&self) -> usize {
+        1 + match self {
+            ChatEvent::Message(message) => message.encoded_len(),
+            ChatEvent::Completions { input, suggestions } => {
+                2 + input.len() + 2 + suggestions.iter().map(|suggestion| 2 + suggestion.len()).sum::<usize>()
+            }
+        }
+    }
+
+    pub fn encode(&self, writer: &mut ByteWriter) {
+        match self {
+            ChatEvent::Message(message) => {
+                writer.write_u8(Self::MESSAGE);
+                message.encode(writer);
+            }
+            ChatEvent::Completions { input, suggestions } => {
+                debug_assert!(suggestions.len() <= MAX_COMPLETIONS);
+                writer.write_u8(Self::COMPLETIONS).write_str(input).write_u16(suggestions.len() as u16);
+                for suggestion in suggestions {
+                    writer.write_str(suggestion);
+                }
+            }
+        }
+    }
+
+    /// Returns None if the event is malformed or of an unknown type.
+    pub fn decode(reader: &mut ByteReader) -> Option<Self> {
+        if !reader.has_n_more(1) {
+            return None;
+        }
+        match reader.read_u8() {
+            Self::MESSAGE => ChatMessage::decode(reader).map(Self::Message),
+            Self::COMPLETIONS => {
+                let input = reader.try_read_str()?.into();
+                if !reader.has_n_more(2) {
+                    return None;
+                }
+                let count = reader.read_u16() as usize;
+                if count > MAX_COMPLETIONS {
+                    return None;
+                }
+                let suggestions = (0..count).map(|_| reader.try_read_str().map(Into::into)).collect::<Option<_>>()?;
+                Some(Self::Completions { input, suggestions })
+            }
+            _ => None,
+        }
+    }
+}
+
+/// What the server sends on the state stream: things happening to the player.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GameEvent {
+    /// From /tp
+    Teleport(Vec3),
+}
+
+impl GameEvent {
+    const TELEPORT: u8 = 1;
+
+    pub fn encoded_len(&self) -> usize {
+        1 + match self {
+            GameEvent::Teleport(_) => 12,
+        }
+    }
+
+    pub fn encode(&self, writer: &mut ByteWriter) {
+        match self {
+            GameEvent::Teleport(position) => {
+                writer.write_u8(Self::TELEPORT).write_f32(position.x).write_f32(position.y).write_f32(position.z);
+            }
+        }
+    }
+
+    /// Returns None if the event is malformed or of an unknown type.
+    pub fn decode(reader: &mut ByteReader) -> Option<Self> {
+        if !reader.has_n_more(1) {
+            return None;
+        }
+        match reader.read_u8() {
+            Self::TELEPORT if reader.has_n_more(12) => {
+                Some(Self::Teleport(Vec3::new(reader.read_f32(), reader.read_f32(), reader.read_f32())))
+            }
+            _ => None,
+        }
+    }
+}
+
+pub fn write_ivec3(writer: &mut ByteWriter, v: IVec3) {
+    writer.write_i32(v.x).write_i32(v.y).write_i32(v.z);
+}
+
+pub fn read_ivec3(reader: &mut ByteReader) -> IVec3 {
+    IVec3::new(reader.read_i32(), reader.read_i32(), reader.read_i32())
+}
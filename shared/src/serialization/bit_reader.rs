@@ -27,7 +27,7 @@ impl<'a> BitReader<'a> {
         let mut bytes = [0u8; 4];
         bytes[..left].copy_from_slice(&self.buf[self.buf_pos..self.buf_pos + left]);
         self.buf_pos += left;
-        return u32::from_le_bytes(bytes);
+        u32::from_le_bytes(bytes)
     }
 
     #[inline]
@@ -10,7 +10,7 @@ impl<'a> BitWriter<'a> {
     #[inline]
     pub fn new(buf: &'a mut [u8]) -> Self {
         debug_assert!(
-            buf.len() % 4 == 0,
+            buf.len().is_multiple_of(4),
             "Buffer length must be a multiple of 4 to avoid surprises"
         );
         Self {
@@ -82,6 +82,6 @@ impl<'a> BitWriter<'a> {
 
     #[inline]
     pub fn compute_bytes_written(&self) -> usize {
-        (self.bits_written + 7) / 8
+        self.bits_written.div_ceil(8)
     }
 }
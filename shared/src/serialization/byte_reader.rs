@@ -107,6 +107,23 @@ impl<'a> ByteReader<'a> {
         std::str::from_utf8(&self.src[pos..self.pos]).unwrap()
     }
 
+    /// Like `read_str()`, but returns None instead of panicking when the string runs past
+    /// the end or isn't valid UTF-8, for reading what came from the other side. Nothing is
+    /// read in that case.
+    pub fn try_read_str(&mut self) -> Option<&'a str> {
+        if !self.has_n_more(2) {
+            return None;
+        }
+        let len = self.read_u16() as usize;
+        let string = self.src.get(self.pos..self.pos + len).and_then(|bytes| std::str::from_utf8(bytes).ok());
+        if string.is_some() {
+            self.pos += len;
+        } else {
+            self.pos -= 2;
+        }
+        string
+    }
+
     pub fn read_bool(&mut self) -> bool {
         self.read_u8() != 0
     }
@@ -1,5 +1,3 @@
-use log::debug;
-
 pub struct ByteWriter<'a> {
     dst: &'a mut [u8],
     pos: usize,
@@ -13,6 +13,8 @@ pub use bit_writer::*;
 pub use byte_reader::*;
 pub use byte_writer::*;
 
+// Older than running clippy on the tests
+#[allow(clippy::bool_assert_comparison)]
 mod tests;
 
 #[inline]
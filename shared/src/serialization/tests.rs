@@ -78,4 +78,21 @@ fn test_byte_rw_roundtrip() {
     assert_eq!(reader.read_i32(), -1);
     assert_eq!(reader.read_u64(), 0x1234_5678_9876_5432);
     assert_eq!(reader.read_i64(), -0x123456789);
-}
\ No newline at end of file
+}
+
+#[test]
+pub fn test_try_read_str() {
+    let mut buf = [0u8; 16];
+    let mut writer = super::ByteWriter::new(&mut buf);
+    writer.write_str("hello");
+    writer.write_u16(200); // Way past the end
+    let written = writer.bytes_written();
+
+    let mut reader = super::ByteReader::new(&buf[..written]);
+    assert_eq!(reader.try_read_str(), Some("hello"));
+    assert_eq!(reader.try_read_str(), None);
+    assert_eq!(reader.bytes_remaining(), 2);
+
+    let invalid = [2, 0, 0xC3, 0x28];
+    assert_eq!(super::ByteReader::new(&invalid).try_read_str(), None);
+}
@@ -0,0 +1,119 @@
+
+// Block id is a number rather than an enum primarily because
+// mapping an int back ot an enum is a nightmare
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BlockId(u16);
+
+// What a block is like (opaque, collidable...) is up to the `BlockRegistry`.
+impl BlockId {
+    pub const fn raw(self) -> u16 {
+        self.0
+    }
+
+    /// Only the low 10 bits are used.
+    pub const fn from_raw(raw: u16) -> Self {
+        Self(raw & 0x3FF)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Block(u16);
+
+impl Block {
+    pub const fn new(id: BlockId) -> Self {
+        Self(id.0)
+    }
+
+    /// NOTE: This returns complete bogus if is_complex() is true! `Chunk::get_at()`
+    /// never returns complex blocks, so that's only a concern for the chunk itself.
+    pub const fn id(self) -> BlockId {
+        BlockId(self.0 & 0x3FF) // 10 bits
+    }
+
+    /// NOTE: This returns complete bogus if is_complex() is true!
+    pub const fn data(self) -> u16 {
+        self.0 >> 12 // 4 bits starting from bit 12
+    }
+
+    pub const fn raw(self) -> u16 {
+        self.0
+    }
+
+    pub const fn from_raw(raw: u16) -> Self {
+        Self(raw)
+    }
+
+    /// Only the low 4 bits of `data` are used.
+    pub const fn with_data(self, data: u16) -> Self {
+        Self((self.0 & 0x0FFF) | (data << 12))
+    }
+}
+
+impl Block {
+    const COMPLEX_FLAG : u16 = 1 << 10;
+    const WATERLOGGED_FLAG : u16 = 1 << 11;
+
+    pub const fn is_complex(self) -> bool {
+        (self.0 & Self::COMPLEX_FLAG) != 0
+    }
+
+    pub const fn is_waterlogged(self) -> bool {
+        (self.0 & Self::WATERLOGGED_FLAG) != 0
+    }
+
+    pub const fn with_waterlogged(self, waterlogged: bool) -> Self {
+        if waterlogged {
+            Self(self.0 | Self::WATERLOGGED_FLAG)
+        } else {
+            Self(self.0 & !Self::WATERLOGGED_FLAG)
+        }
+    }
+
+    /// A block that stands in for the entry at `index` in its chunk's block entities.
+    pub const fn complex(index: u16) -> Self {
+        Self(Self::COMPLEX_FLAG | (index & 0x3FF))
+    }
+
+    /// Only meaningful if is_complex() is true.
+    pub const fn complex_index(self) -> u16 {
+        self.0 & 0x3FF
+    }
+}
+
+
+// The blocks the code needs to refer to. These have to be the first ones in the
+// definition file, in this order, which the registry checks.
+impl BlockId {
+    pub const AIR: BlockId = BlockId(0);
+    pub const TEST: BlockId = BlockId(1);
+    pub const WATER: BlockId = BlockId(2);
+
+    pub const STONE: BlockId = BlockId(3);
+    pub const DIRT: BlockId = BlockId(4);
+    pub const GRASS: BlockId = BlockId(5);
+    pub const SAND: BlockId = BlockId(6);
+    pub const SNOW: BlockId = BlockId(7);
+
+    pub const BUILTIN: [(&'static str, BlockId); 8] = [
+        ("air", Self::AIR),
+        ("test", Self::TEST),
+        ("water", Self::WATER),
+        ("stone", Self::STONE),
+        ("dirt", Self::DIRT),
+        ("grass", Self::GRASS),
+        ("sand", Self::SAND),
+        ("snow", Self::SNOW),
+    ];
+}
+
+impl Block {
+    pub const AIR: Block = Block::new(BlockId::AIR);
+    pub const TEST: Block = Block::new(BlockId::TEST);
+    pub const WATER: Block = Block::new(BlockId::WATER);
+
+    pub const STONE: Block = Block::new(BlockId::STONE);
+    pub const DIRT: Block = Block::new(BlockId::DIRT);
+    pub const GRASS: Block = Block::new(BlockId::GRASS);
+    pub const SAND: Block = Block::new(BlockId::SAND);
+    pub const SNOW: Block = Block::new(BlockId::SNOW);
+}
@@ -0,0 +1,280 @@
+// State for blocks that need more than the 16 bits of a `Block`: which way a block
+// faces, what's in a chest, what a sign says. In the chunk's block storage, such a
+// block is a "complex" block whose low bits are an index into the chunk's table of
+// block entities, and the entry holds the actual block plus everything else.
+//
+// The number of entities per chunk and the size of each one are limited, so that an
+// encoded chunk still fits in a single network message.
+
+use glam::UVec3;
+
+use crate::serialization::{ByteReader, ByteWriter};
+
+use super::block::{Block, BlockId};
+
+pub const MAX_BLOCK_ENTITIES: usize = 256;
+/// In bytes
+pub const MAX_SIGN_TEXT_LEN: usize = 128;
+pub const MAX_INVENTORY_SLOTS: usize = 27;
+
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Orientation {
+    #[default]
+    Px,
+    Nx,
+    Py,
+    Ny,
+    Pz,
+    Nz,
+}
+
+impl Orientation {
+    pub const ALL: [Orientation; 6] = [Self::Px, Self::Nx, Self::Py, Self::Ny, Self::Pz, Self::Nz];
+
+    pub fn from_raw(raw: u8) -> Option<Self> {
+        Self::ALL.get(raw as usize).copied()
+    }
+}
+
+/// Blocks double as items, for now.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ItemStack {
+    pub item: BlockId,
+    /// 0 for an empty slot
+    pub count: u8,
+}
+
+impl ItemStack {
+    pub const EMPTY: ItemStack = ItemStack { item: BlockId::AIR, count: 0 };
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum BlockEntityData {
+    #[default]
+    None,
+    /// At most `MAX_INVENTORY_SLOTS` slots
+    Inventory(Box<[ItemStack]>),
+    /// At most `MAX_SIGN_TEXT_LEN` bytes
+    Sign(Box<str>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockEntity {
+    /// What the block is, as far as everything that doesn't care about the rest
+    /// (meshing, collisions...) is concerned. Can't be a complex block itself.
+    pub block: Block,
+    pub orientation: Orientation,
+    pub data: BlockEntityData,
+}
+
+impl BlockEntity {
+    pub fn new(block: Block) -> Self {
+        Self {
+            block,
+            orientation: Orientation::default(),
+            data: BlockEntityData::None,
+        }
+    }
+
+    /// Within the size limits, and not pointing at another complex block.
+    pub fn is_valid(&self) -> bool {
+        !self.block.is_complex()
+            && match &self.data {
+                BlockEntityData::None => true,
+                BlockEntityData::Inventory(slots) => slots.len() <= MAX_INVENTORY_SLOTS,
+                BlockEntityData::Sign(text) => text.len() <= MAX_SIGN_TEXT_LEN,
+            }
+    }
+
+    fn heap_size(&self) -> usize {
+        match &self.data {
+            BlockEntityData::None => 0,
+            BlockEntityData::Inventory(slots) => std::mem::size_of_val(&**slots),
+            BlockEntityData::Sign(text) => text.len(),
+        }
+    }
+}
+
+/// The per-chunk table complex blocks index into. Every entry knows the position
+/// of its block, so that the chunk can check that the two agree.
+#[derive(Default)]
+pub struct BlockEntities {
+    slots: Vec<Option<(UVec3, BlockEntity)>>,
+    len: usize,
+}
+
+impl BlockEntities {
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn get(&self, index: u16) -> Option<&(UVec3, BlockEntity)> {
+        self.slots.get(index as usize)?.as_ref()
+    }
+
+    pub fn get_mut(&mut self, index: u16) -> Option<&mut (UVec3, BlockEntity)> {
+        self.slots.get_mut(index as usize)?.as_mut()
+    }
+
+    /// Returns the index of the new entry, or None if the table is full.
+    pub fn insert(&mut self, pos: UVec3, entity: BlockEntity) -> Option<u16> {
+        // Linear, but there are never more than a few hundred of these
+        let index = match self.slots.iter().position(Option::is_none) {
+            Some(index) => index,
+            None if self.slots.len() < MAX_BLOCK_ENTITIES => {
+                self.slots.push(None);
+                self.slots.len() - 1
+            }
+            None => return None,
+        };
+        self.slots[index] = Some((pos, entity));
+        self.len += 1;
+        Some(index as u16)
+    }
+
+    pub fn remove(&mut self, index: u16) -> Option<(UVec3, BlockEntity)> {
+        let removed = self.slots.get_mut(index as usize)?.take()?;
+        self.len -= 1;
+
+        while let Some(None) = self.slots.last() {
+            self.slots.pop();
+        }
+        Some(removed)
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    /// With the index of each entry.
+    pub fn iter(&self) -> impl Iterator<Item = (u16, UVec3, &BlockEntity)> {
+        self.slots
+            .iter()
+            .enumerate()
+            .filter_map(|(index, slot)| slot.as_ref().map(|(pos, entity)| (index as u16, *pos, entity)))
+    }
+
+    pub fn heap_size(&self) -> usize {
+        self.slots.capacity() * std::mem::size_of::<Option<(UVec3, BlockEntity)>>()
+            + self.iter().map(|(_, _, entity)| entity.heap_size()).sum::<usize>()
+    }
+}
+
+// Network format:
+// [entity count: u16], then for every entity
+// [index: u16][position: u16, as x << 8 | z << 4 | y][block: u16][orientation: u8][data]
+// where data is one of
+// [0] for none
+// [1][slot count: u8][item: u16, count: u8 for every slot] for an inventory
+// [2][text length: u16][text: utf-8] for a sign
+impl BlockEntities {
+    const MAX_ENTITY_SIZE: usize = 2 + 2 + 2 + 1 + 1 + {
+        let inventory = 1 + MAX_INVENTORY_SLOTS * 3;
+        let sign = 2 + MAX_SIGN_TEXT_LEN;
+        if inventory > sign { inventory } else { sign }
+    };
+    pub const MAX_ENCODED_SIZE: usize = 2 + MAX_BLOCK_ENTITIES * Self::MAX_ENTITY_SIZE;
+
+    /// The entities have to be valid, see `BlockEntity::is_valid()`.
+    pub fn encode(&self, writer: &mut ByteWriter) {
+        writer.write_u16(self.len as u16);
+        for (index, pos, entity) in self.iter() {
+            writer
+                .write_u16(index)
+                .write_u16(((pos.x << 8) | (pos.z << 4) | pos.y) as u16)
+                .write_u16(entity.block.raw())
+                .write_u8(entity.orientation as u8);
+
+            match &entity.data {
+                BlockEntityData::None => {
+                    writer.write_u8(0);
+                }
+                BlockEntityData::Inventory(slots) => {
+                    writer.write_u8(1).write_u8(slots.len() as u8);
+                    for stack in slots.iter() {
+                        writer.write_u16(stack.item.raw()).write_u8(stack.count);
+                    }
+                }
+                BlockEntityData::Sign(text) => {
+                    writer.write_u8(2).write_str(text);
+                }
+            }
+        }
+    }
+
+    /// Returns None if the data is malformed. Whether the entities match the blocks
+    /// is up to the chunk to check.
+    pub fn decode(reader: &mut ByteReader) -> Option<Self> {
+        if !reader.has_n_more(2) {
+            return None;
+        }
+        let count = reader.read_u16() as usize;
+        if count > MAX_BLOCK_ENTITIES {
+            return None;
+        }
+
+        let mut entities = Self::default();
+        for _ in 0..count {
+            if !reader.has_n_more(8) {
+                return None;
+            }
+            let index = reader.read_u16() as usize;
+            let pos = reader.read_u16() as u32;
+            let block = Block::from_raw(reader.read_u16());
+            let orientation = Orientation::from_raw(reader.read_u8())?;
+
+            let data = match reader.read_u8() {
+                0 => BlockEntityData::None,
+                1 => {
+                    if !reader.has_n_more(1) {
+                        return None;
+                    }
+                    let slot_count = reader.read_u8() as usize;
+                    if slot_count > MAX_INVENTORY_SLOTS || !reader.has_n_more(slot_count * 3) {
+                        return None;
+                    }
+                    let slots = (0..slot_count)
+                        .map(|_| ItemStack {
+                            item: BlockId::from_raw(reader.read_u16()),
+                            count: reader.read_u8(),
+                        })
+                        .collect();
+                    BlockEntityData::Inventory(slots)
+                }
+                2 => {
+                    if !reader.has_n_more(2) {
+                        return None;
+                    }
+                    let len = reader.read_u16() as usize;
+                    if len > MAX_SIGN_TEXT_LEN || !reader.has_n_more(len) {
+                        return None;
+                    }
+                    let mut text = vec![0; len];
+                    reader.read(&mut text);
+                    BlockEntityData::Sign(String::from_utf8(text).ok()?.into_boxed_str())
+                }
+                _ => return None,
+            };
+
+            if index >= MAX_BLOCK_ENTITIES || pos >= 1 << 12 || block.is_complex() {
+                return None;
+            }
+            if entities.slots.len() <= index {
+                entities.slots.resize_with(index + 1, || None);
+            }
+            if entities.slots[index].is_some() {
+                return None;
+            }
+
+            let pos = UVec3::new(pos >> 8, pos & 15, (pos >> 4) & 15);
+            entities.slots[index] = Some((pos, BlockEntity { block, orientation, data }));
+            entities.len += 1;
+        }
+        Some(entities)
+    }
+}
@@ -0,0 +1,321 @@
+use glam::{IVec3, UVec3};
+
+use crate::serialization::{ByteReader, ByteWriter};
+
+use super::{
+    block::Block,
+    block_entity::{BlockEntities, BlockEntity},
+    light::{Light, LightMap},
+    palette::PalettedBlocks,
+};
+
+pub const CHUNK_SIZE_LOG2: usize = 4;
+pub const CHUNK_SIZE: usize = 1 << CHUNK_SIZE_LOG2;
+pub const CHUNK_VOLUME: usize = CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE;
+
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ChunkFace {
+    NX, NY, NZ, PX, PY, PZ,
+}
+
+pub type WorldBlockPos = IVec3;
+
+pub trait WorldBlockPosExt {
+    fn to_block_index(self) -> usize;
+
+    fn to_local(self) -> ChunkBlockPos;
+
+    fn to_chunk_pos(self) -> IVec3;
+}
+
+impl WorldBlockPosExt for WorldBlockPos {
+    fn to_block_index(self) -> usize {
+        const XZ_BITS: usize = 24;
+
+        ((self.y as u32 as usize) << (2 * XZ_BITS))
+            | ((self.z as u32 as usize) << XZ_BITS)
+            | (self.x as u32 as usize)
+    }
+
+    fn to_local(self) -> ChunkBlockPos {
+        ChunkBlockPos::new(self.x as u8, self.y as u8, self.z as u8)
+    }
+
+    fn to_chunk_pos(self) -> IVec3 {
+        self >> CHUNK_SIZE_LOG2 as i32
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ChunkBlockPos {
+    pub x: u8,
+    pub y: u8,
+    pub z: u8,
+    pub extra: u8,
+}
+
+impl ChunkBlockPos {
+    pub const COORD_MASK: u8 = CHUNK_SIZE as u8 - 1;
+
+    pub const fn new(x: u8, y: u8, z: u8) -> Self {
+        Self {
+            x: x & Self::COORD_MASK,
+            y: y & Self::COORD_MASK,
+            z: z & Self::COORD_MASK,
+            extra: 0,
+        }
+    }
+
+    pub const fn to_block_index(self) -> usize {
+        self.z as usize * CHUNK_SIZE * CHUNK_SIZE + self.x as usize * CHUNK_SIZE + self.y as usize
+    }
+}
+
+impl From<WorldBlockPos> for ChunkBlockPos {
+    fn from(pos: WorldBlockPos) -> Self {
+        pos.to_local()
+    }
+}
+
+impl From<ChunkBlockPos> for UVec3 {
+    fn from(pos: ChunkBlockPos) -> Self {
+        UVec3::new(pos.x as u32, pos.y as u32, pos.z as u32)
+    }
+}
+
+pub struct Chunk {
+    blocks: PalettedBlocks,
+    // What the complex blocks in `blocks` point to
+    entities: BlockEntities,
+    // Only ever filled in on the client, see `LightEngine`
+    light: LightMap,
+}
+
+impl Chunk {
+    pub fn new() -> Box<Self> {
+        // Boxed mostly for historical reasons (it used to be 8 KiB of blocks),
+        // but it also keeps moving chunks around cheap
+        Box::new(Self {
+            blocks: PalettedBlocks::uniform(Block::AIR),
+            entities: BlockEntities::default(),
+            light: LightMap::default(),
+        })
+    }
+
+    /// For complex blocks, this is the block their entity stands for, so the result
+    /// is never complex.
+    #[inline(always)]
+    pub fn get_at(&self, pos: impl Into<UVec3>) -> Block {
+        self.resolve(self.blocks.get(block_idx(pos.into())))
+    }
+
+    /// `block` can't be complex, use `set_block_entity_at()` for those. Overwriting
+    /// a complex block removes its entity.
+    pub fn set_at(&mut self, pos: impl Into<UVec3>, block: Block) {
+        assert!(!block.is_complex(), "Complex blocks have to be set through set_block_entity_at()");
+        let idx = block_idx(pos.into());
+        self.remove_entity_at_idx(idx);
+        self.blocks.set(idx, block);
+        // Left to do:
+        // - update the density map
+        // - tell the light engine, if there is one (see `LightEngine::on_block_changed()`)
+        // - update bitmaps if we have those on the client as well
+        // - mark as "needs to be remeshed"
+    }
+
+    #[inline(always)]
+    pub fn fill(&mut self, block: Block) {
+        assert!(!block.is_complex(), "Can't fill a chunk with a complex block");
+        self.blocks.fill(block);
+        self.entities.clear();
+    }
+
+    /// Same as `get_at()` for every block, so complex blocks are resolved.
+    #[inline(always)]
+    pub fn iter(&self) -> impl Iterator<Item = Block> + '_ {
+        self.blocks.iter().map(|block| self.resolve(block))
+    }
+
+    pub fn block_entity_at(&self, pos: impl Into<UVec3>) -> Option<&BlockEntity> {
+        let block = self.blocks.get(block_idx(pos.into()));
+        let (_, entity) = self.entities.get(block.complex_index()).filter(|_| block.is_complex())?;
+        Some(entity)
+    }
+
+    /// Changing `block` through this is fine, as long as it doesn't become complex.
+    pub fn block_entity_at_mut(&mut self, pos: impl Into<UVec3>) -> Option<&mut BlockEntity> {
+        let block = self.blocks.get(block_idx(pos.into()));
+        let (_, entity) = self.entities.get_mut(block.complex_index()).filter(|_| block.is_complex())?;
+        Some(entity)
+    }
+
+    /// Replaces the block (and its entity, if it had one) with a complex block. Returns false
+    /// (and does nothing) if the entity is invalid or the chunk already has as many as it can.
+    pub fn set_block_entity_at(&mut self, pos: impl Into<UVec3>, entity: BlockEntity) -> bool {
+        if !entity.is_valid() {
+            return false;
+        }
+
+        let pos = pos.into();
+        let idx = block_idx(pos);
+        let old = self.remove_entity_at_idx(idx);
+        let Some(index) = self.entities.insert(pos, entity) else {
+            // Full, so there can't have been an old entity to remove
+            debug_assert!(old.is_none());
+            return false;
+        };
+        self.blocks.set(idx, Block::complex(index));
+        true
+    }
+
+    /// Turns a complex block back into a plain one, the block its entity stood for.
+    pub fn remove_block_entity_at(&mut self, pos: impl Into<UVec3>) -> Option<BlockEntity> {
+        let idx = block_idx(pos.into());
+        let entity = self.remove_entity_at_idx(idx)?;
+        self.blocks.set(idx, entity.block);
+        Some(entity)
+    }
+
+    pub fn block_entities(&self) -> impl Iterator<Item = (UVec3, &BlockEntity)> {
+        self.entities.iter().map(|(_, pos, entity)| (pos, entity))
+    }
+
+    pub fn block_entity_count(&self) -> usize {
+        self.entities.len()
+    }
+
+    /// Not sent over the network, so it's dark until it's been lit.
+    #[inline(always)]
+    pub fn light_at(&self, pos: impl Into<UVec3>) -> Light {
+        self.light.get(block_idx(pos.into()))
+    }
+
+    #[inline(always)]
+    pub fn set_light_at(&mut self, pos: impl Into<UVec3>, light: Light) {
+        self.light.set(block_idx(pos.into()), light);
+    }
+
+    pub fn light(&self) -> &LightMap {
+        &self.light
+    }
+
+    pub fn set_light(&mut self, light: LightMap) {
+        self.light = light;
+    }
+
+    pub fn clear_light(&mut self) {
+        self.light.clear();
+    }
+
+    #[inline(always)]
+    fn resolve(&self, block: Block) -> Block {
+        if block.is_complex() {
+            self.resolve_complex(block)
+        } else {
+            block
+        }
+    }
+
+    #[cold]
+    fn resolve_complex(&self, block: Block) -> Block {
+        // The table and the blocks are always kept in sync, and decode() checks them
+        self.entities.get(block.complex_index()).expect("Complex block without an entity").1.block
+    }
+
+    // Leaves the complex block in place, the caller has to overwrite it
+    fn remove_entity_at_idx(&mut self, idx: usize) -> Option<BlockEntity> {
+        let block = self.blocks.get(idx);
+        if !block.is_complex() {
+            return None;
+        }
+        self.entities.remove(block.complex_index()).map(|(_, entity)| entity)
+    }
+
+    /// If the chunk consists of a single block type, returns that block.
+    /// Not exact: can return None even if it happens to be uniform.
+    pub fn uniform_block(&self) -> Option<Block> {
+        self.blocks.is_uniform().then(|| self.blocks.palette()[0])
+    }
+
+    /// Total memory used by the chunk, in bytes, for statistics.
+    pub fn memory_usage(&self) -> usize {
+        std::mem::size_of::<Self>() + self.blocks.heap_size() + self.entities.heap_size() + self.light.heap_size()
+    }
+}
+
+// Network format: the palette storage as-is, so there's nothing to compress or decompress,
+// followed by the block entities.
+impl Chunk {
+    pub const MAX_ENCODED_SIZE: usize = PalettedBlocks::MAX_ENCODED_SIZE + BlockEntities::MAX_ENCODED_SIZE;
+
+    /// `writer` must have at least `Chunk::MAX_ENCODED_SIZE` bytes of space left.
+    pub fn encode(&self, writer: &mut ByteWriter) {
+        self.blocks.encode(writer);
+        self.entities.encode(writer);
+    }
+
+    /// Returns None if the data is malformed.
+    pub fn decode(reader: &mut ByteReader) -> Option<Box<Chunk>> {
+        let blocks = PalettedBlocks::decode(reader)?;
+        let entities = BlockEntities::decode(reader)?;
+
+        // Every complex block needs an entity, and every entity a complex block where
+        // it says it is. Counting makes sure there are no entities left over.
+        if blocks.palette().iter().any(|block| block.is_complex()) || !entities.is_empty() {
+            let mut complex_blocks = 0;
+            for idx in (0..CHUNK_VOLUME).filter(|&idx| blocks.get(idx).is_complex()) {
+                let (pos, _) = entities.get(blocks.get(idx).complex_index())?;
+                if block_idx(*pos) != idx {
+                    return None;
+                }
+                complex_blocks += 1;
+            }
+            if complex_blocks != entities.len() {
+                return None;
+            }
+        }
+
+        Some(Box::new(Chunk { blocks, entities, light: LightMap::default() }))
+    }
+}
+
+// Chunks are sent as a single message, which has a u16 length
+const _: () = assert!(Chunk::MAX_ENCODED_SIZE + 64 <= u16::MAX as usize);
+
+// Blocks are stored in sections of 4x4x2 (x, y, z), so that blocks close to each other
+// in any direction tend to be close in memory too. The index is the section index (7 bits)
+// followed by the index within the section (5 bits), both gathered from the bits of the
+// coordinates: with BMI2 that's one `pext` each, otherwise a few shifts and masks.
+#[inline(always)]
+fn block_idx(UVec3 { x, y, z }: UVec3) -> usize {
+    let u = (x << 8) | (z << 4) | y;
+
+    #[cfg(all(target_arch = "x86_64", target_feature = "bmi2"))]
+    let idx = unsafe { block_idx_bmi2(u) }; // safe: the whole build targets BMI2
+    #[cfg(not(all(target_arch = "x86_64", target_feature = "bmi2")))]
+    let idx = block_idx_portable(u);
+
+    idx
+}
+
+#[cfg(target_arch = "x86_64")]
+#[cfg_attr(not(target_feature = "bmi2"), allow(dead_code))] // only the tests use it then
+#[target_feature(enable = "bmi2")]
+pub(super) unsafe fn block_idx_bmi2(u: u32) -> usize {
+    use std::arch::x86_64::_pext_u32;
+
+    let sect_idx = _pext_u32(u, 0b1100_1110_1100);
+    let block_idx = _pext_u32(u, 0b0011_0001_0011);
+    sect_idx as usize * 32 + block_idx as usize
+}
+
+#[cfg_attr(all(target_arch = "x86_64", target_feature = "bmi2"), allow(dead_code))]
+#[inline(always)]
+pub(super) fn block_idx_portable(u: u32) -> usize {
+    // Same as the pext()s above, spelled out: y2 y3 | z1 z2 z3 | x2 x3
+    let sect_idx = ((u >> 2) & 0b11) | ((u >> 3) & 0b1_1100) | ((u >> 5) & 0b110_0000);
+    // y0 y1 | z0 | x0 x1
+    let block_idx = (u & 0b11) | ((u >> 2) & 0b100) | ((u >> 5) & 0b1_1000);
+    sect_idx as usize * 32 + block_idx as usize
+}
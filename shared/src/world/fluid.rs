@@ -0,0 +1,55 @@
+// Fluids keep track of how they got where they are in the 4 data bits of the block:
+// a source stays put, flowing fluid gets thinner the further it is from where it came
+// from, and falling fluid is whatever is coming down from the block above. The
+// simulation itself runs on the server; this is what both sides need to agree on.
+
+use super::block::{Block, BlockId};
+
+/// How far flowing fluid makes it from a source (or from where it landed).
+pub const MAX_FLOW_DISTANCE: u8 = 7;
+
+const FALLING: u16 = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FluidState {
+    Source,
+    /// 1..=MAX_FLOW_DISTANCE
+    Flowing(u8),
+    Falling,
+}
+
+impl FluidState {
+    /// None if there's no fluid in the block. Waterlogged blocks count as a source.
+    pub fn of(block: Block) -> Option<Self> {
+        if block.is_waterlogged() {
+            return Some(Self::Source);
+        }
+        if block.id() != BlockId::WATER {
+            return None;
+        }
+        Some(match block.data() {
+            0 => Self::Source,
+            distance @ 1..=7 => Self::Flowing(distance as u8),
+            _ => Self::Falling,
+        })
+    }
+
+    /// A water block in this state.
+    pub fn water(self) -> Block {
+        let data = match self {
+            Self::Source => 0,
+            Self::Flowing(distance) => distance.clamp(1, MAX_FLOW_DISTANCE) as u16,
+            Self::Falling => FALLING,
+        };
+        Block::WATER.with_data(data)
+    }
+
+    /// How far this is from where the fluid came from. Falling fluid spreads
+    /// like a source once it lands.
+    pub fn distance(self) -> u8 {
+        match self {
+            Self::Source | Self::Falling => 0,
+            Self::Flowing(distance) => distance,
+        }
+    }
+}
@@ -0,0 +1,347 @@
+// Sky light and block light. Every block has a level of 0-15 of both: sky light comes
+// in from above the world and keeps its full level going straight down, block light
+// comes from blocks that emit it. Both lose a level per block in every other direction,
+// and neither goes into opaque blocks.
+//
+// Light is spread breadth-first, from chunk to chunk. Taking it away works the same way:
+// when a light source goes (or gets covered), everything that was lit by it is zeroed,
+// and the light around the zeroed area is spread back into it. Chunks that aren't loaded
+// count as dark and don't get any light, so a chunk arriving only ever adds light.
+
+use std::collections::{HashSet, VecDeque};
+
+use glam::{IVec3, UVec3};
+
+use super::{
+    block::Block,
+    chunk::{Chunk, WorldBlockPos, WorldBlockPosExt, CHUNK_SIZE, CHUNK_VOLUME},
+    registry::BlockRegistry,
+};
+
+pub const MAX_LIGHT: u8 = 15;
+
+/// Sky light in the high nibble, block light in the low one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Light(u8);
+
+impl Light {
+    pub const DARK: Light = Light(0);
+    /// Open sky, the light above the world.
+    pub const SKY: Light = Light(MAX_LIGHT << 4);
+
+    /// Both 0-15
+    pub const fn new(sky: u8, block: u8) -> Self {
+        Self((sky << 4) | (block & 0xF))
+    }
+
+    pub const fn from_raw(raw: u8) -> Self {
+        Self(raw)
+    }
+
+    pub const fn raw(self) -> u8 {
+        self.0
+    }
+
+    pub const fn sky(self) -> u8 {
+        self.0 >> 4
+    }
+
+    pub const fn block(self) -> u8 {
+        self.0 & 0xF
+    }
+}
+
+/// The light of every block in a chunk, indexed the same way as the blocks. Doesn't
+/// allocate anything as long as it's completely dark, which is the case for every
+/// chunk the server has.
+#[derive(Clone, Default)]
+pub struct LightMap(Option<Box<[Light; CHUNK_VOLUME]>>);
+
+impl LightMap {
+    #[inline(always)]
+    pub(super) fn get(&self, idx: usize) -> Light {
+        self.0.as_ref().map_or(Light::DARK, |lights| lights[idx])
+    }
+
+    #[inline(always)]
+    pub(super) fn set(&mut self, idx: usize, light: Light) {
+        match &mut self.0 {
+            Some(lights) => lights[idx] = light,
+            None if light == Light::DARK => {}
+            None => self.0.insert(Box::new([Light::DARK; CHUNK_VOLUME]))[idx] = light,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.0 = None;
+    }
+
+    pub fn heap_size(&self) -> usize {
+        self.0.as_ref().map_or(0, |_| CHUNK_VOLUME * std::mem::size_of::<Light>())
+    }
+}
+
+/// Whatever holds the chunks light is spread through (the client's chunk map, mostly).
+pub trait LightWorld {
+    /// Chunks at this height and above are never loaded, and above them is open sky.
+    const HEIGHT_CHUNKS: i32;
+
+    fn chunk(&self, chunk_pos: IVec3) -> Option<&Chunk>;
+
+    fn chunk_mut(&mut self, chunk_pos: IVec3) -> Option<&mut Chunk>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Channel {
+    Sky,
+    Block,
+}
+
+impl Channel {
+    fn get(self, light: Light) -> u8 {
+        match self {
+            Channel::Sky => light.sky(),
+            Channel::Block => light.block(),
+        }
+    }
+
+    fn with(self, light: Light, level: u8) -> Light {
+        match self {
+            Channel::Sky => Light::new(level, light.block()),
+            Channel::Block => Light::new(light.sky(), level),
+        }
+    }
+}
+
+const DIRECTIONS: [IVec3; 6] = [IVec3::X, IVec3::NEG_X, IVec3::Y, IVec3::NEG_Y, IVec3::Z, IVec3::NEG_Z];
+
+/// Keeps the light in a `LightWorld` up to date as chunks arrive and blocks change.
+/// Holds on to its queues between updates, so that they don't get reallocated every time.
+#[derive(Default)]
+pub struct LightEngine {
+    // Blocks whose light has to be spread to their neighbors
+    spread: VecDeque<WorldBlockPos>,
+    // Blocks that were zeroed, with the level they had before
+    unspread: VecDeque<(WorldBlockPos, Channel, u8)>,
+    // Every chunk whose mesh depends on light that changed
+    changed: HashSet<IVec3>,
+}
+
+impl LightEngine {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Call after the chunk has been put into the world. `replaced` is the chunk that was
+    /// there before, if any. Then only the blocks that differ are updated, which also takes
+    /// care of the light that spread from the old chunk into its neighbors.
+    pub fn on_chunk_loaded<W: LightWorld>(
+        &mut self,
+        world: &mut W,
+        chunk_pos: IVec3,
+        replaced: Option<&Chunk>,
+        registry: &BlockRegistry,
+    ) {
+        let Some(chunk) = world.chunk_mut(chunk_pos) else {
+            return;
+        };
+        let origin = chunk_pos * CHUNK_SIZE as i32;
+
+        if let Some(old) = replaced {
+            chunk.set_light(old.light().clone());
+            let changes: Vec<_> = chunk_positions()
+                .filter(|&pos| chunk.get_at(pos) != old.get_at(pos))
+                .map(|pos| (origin + pos.as_ivec3(), old.get_at(pos)))
+                .collect();
+            self.on_blocks_changed(world, changes, registry);
+            return;
+        }
+
+        chunk.clear_light();
+        for pos in chunk_positions() {
+            let world_pos = origin + pos.as_ivec3();
+            let own = own_light::<W>(world_pos, chunk.get_at(pos), registry);
+            if own != Light::DARK {
+                chunk.set_light_at(pos, own);
+                self.spread.push_back(world_pos);
+                self.mark_changed(world_pos);
+            }
+        }
+
+        // Plus whatever the neighbors have on their side of the border
+        for dir in DIRECTIONS {
+            let Some(neighbor) = world.chunk(chunk_pos + dir) else {
+                continue;
+            };
+            let neighbor_origin = origin + dir * CHUNK_SIZE as i32;
+            for pos in chunk_layer(-dir) {
+                if neighbor.light_at(pos) != Light::DARK {
+                    self.spread.push_back(neighbor_origin + pos.as_ivec3());
+                }
+            }
+        }
+
+        self.run(world, registry);
+    }
+
+    /// Call after the block at `pos` has been replaced by a different one.
+    pub fn on_block_changed(&mut self, world: &mut impl LightWorld, pos: WorldBlockPos, old: Block, registry: &BlockRegistry) {
+        self.on_blocks_changed(world, [(pos, old)], registry);
+    }
+
+    /// Same as `on_block_changed()`, for a bunch of blocks at once. Cheaper than
+    /// one at a time, since the light around them is only spread once.
+    pub fn on_blocks_changed<W: LightWorld>(
+        &mut self,
+        world: &mut W,
+        changes: impl IntoIterator<Item = (WorldBlockPos, Block)>,
+        registry: &BlockRegistry,
+    ) {
+        for (pos, old) in changes {
+            let Some((block, light)) = lookup(world, pos) else {
+                continue;
+            };
+            // Only opacity and emission matter here
+            if registry.is_opaque(block.id()) == registry.is_opaque(old.id())
+                && registry.light_emission(block.id()) == registry.light_emission(old.id())
+            {
+                continue;
+            }
+
+            // Whatever the block was lit by comes back through the unspreading, which
+            // pushes the light around the zeroed area back in
+            self.set_light_at(world, pos, Light::DARK);
+            self.unspread.push_back((pos, Channel::Sky, light.sky()));
+            self.unspread.push_back((pos, Channel::Block, light.block()));
+
+            let own = own_light::<W>(pos, block, registry);
+            if own != Light::DARK {
+                self.set_light_at(world, pos, own);
+                self.spread.push_back(pos);
+            }
+        }
+
+        self.run(world, registry);
+    }
+
+    /// The chunks that need to be remeshed, because light their faces look into changed.
+    pub fn take_changed_chunks(&mut self) -> impl Iterator<Item = IVec3> + '_ {
+        self.changed.drain()
+    }
+
+    fn run<W: LightWorld>(&mut self, world: &mut W, registry: &BlockRegistry) {
+        while let Some((pos, channel, level)) = self.unspread.pop_front() {
+            for dir in DIRECTIONS {
+                let neighbor_pos = pos + dir;
+                let Some((block, light)) = lookup(world, neighbor_pos) else {
+                    continue;
+                };
+                let neighbor_level = channel.get(light);
+                if neighbor_level == 0 {
+                    continue;
+                }
+
+                let lit_by_pos = neighbor_level < level
+                    || (channel == Channel::Sky && dir == IVec3::NEG_Y && level == MAX_LIGHT);
+                if !lit_by_pos {
+                    // Lit by something else, which might reach into the zeroed area now
+                    self.spread.push_back(neighbor_pos);
+                    continue;
+                }
+
+                self.set_light_at(world, neighbor_pos, channel.with(light, 0));
+                self.unspread.push_back((neighbor_pos, channel, neighbor_level));
+
+                // Light sources keep their own light, of course
+                let own = channel.get(own_light::<W>(neighbor_pos, block, registry));
+                if own > 0 {
+                    self.set_light_at(world, neighbor_pos, channel.with(light, own));
+                    self.spread.push_back(neighbor_pos);
+                }
+            }
+        }
+
+        while let Some(pos) = self.spread.pop_front() {
+            let Some((_, light)) = lookup(world, pos) else {
+                continue;
+            };
+            for dir in DIRECTIONS {
+                let neighbor_pos = pos + dir;
+                let Some((block, neighbor_light)) = lookup(world, neighbor_pos) else {
+                    continue;
+                };
+                if registry.is_opaque(block.id()) {
+                    continue;
+                }
+
+                let sky = if dir == IVec3::NEG_Y && light.sky() == MAX_LIGHT {
+                    MAX_LIGHT
+                } else {
+                    light.sky().saturating_sub(1)
+                };
+                let new = Light::new(
+                    neighbor_light.sky().max(sky),
+                    neighbor_light.block().max(light.block().saturating_sub(1)),
+                );
+                if new != neighbor_light {
+                    self.set_light_at(world, neighbor_pos, new);
+                    self.spread.push_back(neighbor_pos);
+                }
+            }
+        }
+    }
+
+    fn set_light_at(&mut self, world: &mut impl LightWorld, pos: WorldBlockPos, light: Light) {
+        if let Some(chunk) = world.chunk_mut(pos.to_chunk_pos()) {
+            chunk.set_light_at(pos.to_local(), light);
+            self.mark_changed(pos);
+        }
+    }
+
+    fn mark_changed(&mut self, pos: WorldBlockPos) {
+        // Meshes look one block past the chunk, edges and corners included, so a block on
+        // the border changes the meshes of up to 7 other chunks
+        let chunk_pos = pos.to_chunk_pos();
+        let local = pos & (CHUNK_SIZE as i32 - 1);
+        let offsets = |axis: usize| match local[axis] {
+            0 => -1..=0,
+            l if l == CHUNK_SIZE as i32 - 1 => 0..=1,
+            _ => 0..=0,
+        };
+        for x in offsets(0) {
+            for y in offsets(1) {
+                for z in offsets(2) {
+                    self.changed.insert(chunk_pos + IVec3::new(x, y, z));
+                }
+            }
+        }
+    }
+}
+
+fn lookup(world: &impl LightWorld, pos: WorldBlockPos) -> Option<(Block, Light)> {
+    let chunk = world.chunk(pos.to_chunk_pos())?;
+    let local = pos.to_local();
+    Some((chunk.get_at(local), chunk.light_at(local)))
+}
+
+// The light a block has no matter what's around it: its emission, plus the open sky
+// if it's at the very top of the world
+fn own_light<W: LightWorld>(pos: WorldBlockPos, block: Block, registry: &BlockRegistry) -> Light {
+    let top = W::HEIGHT_CHUNKS * CHUNK_SIZE as i32 - 1;
+    let sky = if pos.y == top && !registry.is_opaque(block.id()) { MAX_LIGHT } else { 0 };
+    Light::new(sky, registry.light_emission(block.id()))
+}
+
+fn chunk_positions() -> impl Iterator<Item = UVec3> {
+    let size = CHUNK_SIZE as u32;
+    (0..size).flat_map(move |x| (0..size).flat_map(move |y| (0..size).map(move |z| UVec3::new(x, y, z))))
+}
+
+// The blocks of a chunk on its side towards `dir`
+fn chunk_layer(dir: IVec3) -> impl Iterator<Item = UVec3> {
+    let last = CHUNK_SIZE as i32 - 1;
+    chunk_positions().filter(move |pos| {
+        let pos = pos.as_ivec3();
+        (0..3).any(|axis| (dir[axis] > 0 && pos[axis] == last) || (dir[axis] < 0 && pos[axis] == 0))
+    })
+}
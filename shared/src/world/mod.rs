@@ -0,0 +1,12 @@
+// The parts of the world representation that both the client and the
+// server need to agree on: what a block is and how chunks store them.
+
+pub mod block;
+pub mod block_entity;
+pub mod chunk;
+pub mod fluid;
+pub mod light;
+pub mod palette;
+pub mod registry;
+
+mod tests;
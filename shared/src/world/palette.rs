@@ -0,0 +1,189 @@
+// Block storage for a single chunk. Instead of storing every block as-is, each
+// distinct block in the chunk gets an entry in the palette, and the blocks are
+// stored as indices into it, using only as many bits per block as the palette
+// size requires. A typical terrain chunk has only a handful of distinct blocks,
+// so this ends up at 2 or 4 bits per block instead of 16, and chunks made of a
+// single block (air, most of the time) take no space at all beyond the palette.
+//
+// Index widths are powers of two, so that an index never straddles two words.
+
+use crate::serialization::{ByteReader, ByteWriter};
+
+use super::{block::Block, chunk::CHUNK_VOLUME};
+
+pub struct PalettedBlocks {
+    palette: Vec<Block>,
+    // Bits per index: 0 (uniform, `data` is empty), 1, 2, 4, 8 or 16
+    bits: u32,
+    data: Box<[u64]>,
+}
+
+impl PalettedBlocks {
+    pub fn uniform(block: Block) -> Self {
+        Self {
+            palette: vec![block],
+            bits: 0,
+            data: Box::new([]),
+        }
+    }
+
+    pub fn is_uniform(&self) -> bool {
+        self.bits == 0
+    }
+
+    pub fn bits_per_block(&self) -> u32 {
+        self.bits
+    }
+
+    /// May contain blocks that no longer occur in the chunk: the palette is
+    /// only cleaned up when it runs out of room.
+    pub fn palette(&self) -> &[Block] {
+        &self.palette
+    }
+
+    /// Heap memory used, in bytes.
+    pub fn heap_size(&self) -> usize {
+        self.palette.capacity() * std::mem::size_of::<Block>() + self.data.len() * 8
+    }
+
+    #[inline]
+    pub fn get(&self, idx: usize) -> Block {
+        self.palette[self.index_at(idx)]
+    }
+
+    pub fn set(&mut self, idx: usize, block: Block) {
+        if self.is_uniform() && self.palette[0] == block {
+            return;
+        }
+
+        let palette_idx = match self.palette.iter().position(|&b| b == block) {
+            Some(palette_idx) => palette_idx,
+            None => {
+                // With 16 bits there'd be room for 65536 entries, but a chunk can only
+                // use 4096 of them at a time, so clean up before the palette gets silly
+                if self.palette.len() >= (1 << self.bits).min(CHUNK_VOLUME) {
+                    self.make_room();
+                }
+                self.palette.push(block);
+                self.palette.len() - 1
+            }
+        };
+        self.set_index(idx, palette_idx);
+    }
+
+    pub fn fill(&mut self, block: Block) {
+        *self = Self::uniform(block);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Block> + '_ {
+        (0..CHUNK_VOLUME).map(|idx| self.get(idx))
+    }
+
+    #[inline]
+    fn index_at(&self, idx: usize) -> usize {
+        if self.bits == 0 {
+            return 0;
+        }
+        let per_word = 64 / self.bits as usize;
+        let word = self.data[idx / per_word];
+        let shift = (idx % per_word) as u32 * self.bits;
+        ((word >> shift) & ((1 << self.bits) - 1)) as usize
+    }
+
+    #[inline]
+    fn set_index(&mut self, idx: usize, palette_idx: usize) {
+        let per_word = 64 / self.bits as usize;
+        let word = &mut self.data[idx / per_word];
+        let shift = (idx % per_word) as u32 * self.bits;
+        let mask = ((1u64 << self.bits) - 1) << shift;
+        *word = (*word & !mask) | ((palette_idx as u64) << shift);
+    }
+
+    // Called when the palette is full and a new block needs to be added. Drops
+    // palette entries that are no longer used, and widens the indices if that
+    // didn't free up enough room.
+    fn make_room(&mut self) {
+        let mut used = vec![false; self.palette.len()];
+        for idx in 0..CHUNK_VOLUME {
+            used[self.index_at(idx)] = true;
+        }
+
+        let mut remap = vec![0; self.palette.len()];
+        let mut palette = Vec::with_capacity(self.palette.len() + 1);
+        for (i, &block) in self.palette.iter().enumerate() {
+            if used[i] {
+                remap[i] = palette.len();
+                palette.push(block);
+            }
+        }
+
+        let bits = bits_for(palette.len() + 1);
+        let mut repacked = Self {
+            palette,
+            bits,
+            data: vec![0; word_count(bits)].into_boxed_slice(),
+        };
+        for idx in 0..CHUNK_VOLUME {
+            repacked.set_index(idx, remap[self.index_at(idx)]);
+        }
+        *self = repacked;
+    }
+}
+
+// Network format, which is just the in-memory representation:
+// [bits per block: u8][palette length: u16][palette: u16 each][data: u64 each]
+impl PalettedBlocks {
+    pub const MAX_ENCODED_SIZE: usize = 1 + 2 + (CHUNK_VOLUME + 1) * 2 + CHUNK_VOLUME * 2;
+
+    pub fn encode(&self, writer: &mut ByteWriter) {
+        writer.write_u8(self.bits as u8);
+        writer.write_u16(self.palette.len() as u16);
+        for block in &self.palette {
+            writer.write_u16(block.raw());
+        }
+        for &word in self.data.iter() {
+            writer.write_u64(word);
+        }
+    }
+
+    /// Returns None if the data is malformed.
+    pub fn decode(reader: &mut ByteReader) -> Option<Self> {
+        if !reader.has_n_more(3) {
+            return None;
+        }
+        let bits = reader.read_u8() as u32;
+        let palette_len = reader.read_u16() as usize;
+        if !matches!(bits, 0 | 1 | 2 | 4 | 8 | 16)
+            || palette_len == 0
+            || palette_len > (1 << bits).min(CHUNK_VOLUME + 1)
+            || !reader.has_n_more(palette_len * 2 + word_count(bits) * 8)
+        {
+            return None;
+        }
+
+        let palette = (0..palette_len).map(|_| Block::from_raw(reader.read_u16())).collect();
+        let data = (0..word_count(bits)).map(|_| reader.read_u64()).collect();
+        let blocks = Self { palette, bits, data };
+
+        // Out-of-range indices would make get() panic later on
+        if blocks.bits != 0 && (0..CHUNK_VOLUME).any(|idx| blocks.index_at(idx) >= palette_len) {
+            return None;
+        }
+        Some(blocks)
+    }
+}
+
+fn bits_for(palette_len: usize) -> u32 {
+    match palette_len {
+        0..=1 => 0,
+        2 => 1,
+        3..=4 => 2,
+        5..=16 => 4,
+        17..=256 => 8,
+        _ => 16,
+    }
+}
+
+fn word_count(bits: u32) -> usize {
+    CHUNK_VOLUME * bits as usize / 64
+}
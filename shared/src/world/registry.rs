@@ -0,0 +1,286 @@
+// What every block is like, as read from a definition file. Block ids are assigned
+// in the order of the file, so the client and the server have to be using the exact
+// same definitions, or they'll disagree on what every block in the world is. The
+// checksum is compared at login to catch that.
+
+use std::collections::HashMap;
+
+use anyhow::{bail, Context};
+use glam::Vec3;
+
+use super::block::BlockId;
+
+/// The definitions the game ships with.
+pub const DEFAULT_DEFINITIONS: &str = include_str!("../../assets/blocks.txt");
+
+// Ids are 10 bits
+const MAX_BLOCKS: usize = 1 << 10;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CollisionShape {
+    None,
+    Full,
+    /// In block coordinates, 0..1
+    Box { min: Vec3, max: Vec3 },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockDef {
+    pub name: Box<str>,
+    /// In the order +X, -X, +Y, -Y, +Z, -Z, same as the faces in the meshes
+    pub textures: [Box<str>; 6],
+    /// Opaque as in *fully* opaque: hides the faces of blocks next to it.
+    pub opaque: bool,
+    pub collision: CollisionShape,
+    /// 0-15
+    pub light_emission: u8,
+    pub hardness: f32,
+    /// Can hold water without being replaced by it, see `Block::is_waterlogged()`.
+    pub waterloggable: bool,
+}
+
+pub struct BlockRegistry {
+    defs: Vec<BlockDef>,
+    by_name: HashMap<Box<str>, BlockId>,
+    // Indexed by the raw id, for the lookups that happen for every block. All ids
+    // fit in, so unknown ids don't need special handling: they're just not opaque
+    opaque: Box<[bool; MAX_BLOCKS]>,
+    collidable: Box<[bool; MAX_BLOCKS]>,
+    light_emission: Box<[u8; MAX_BLOCKS]>,
+    checksum: u64,
+}
+
+impl BlockRegistry {
+    /// Panics if the built-in definitions are broken, which the tests make sure they aren't.
+    pub fn builtin() -> Self {
+        Self::parse(DEFAULT_DEFINITIONS).expect("Built-in block definitions are invalid")
+    }
+
+    pub fn parse(src: &str) -> anyhow::Result<Self> {
+        let defs = parse_definitions(src)?;
+        if defs.len() > MAX_BLOCKS {
+            bail!("Too many blocks: {}, the limit is {MAX_BLOCKS}", defs.len());
+        }
+
+        let mut by_name = HashMap::new();
+        for (id, def) in defs.iter().enumerate() {
+            if by_name.insert(def.name.clone(), BlockId::from_raw(id as u16)).is_some() {
+                bail!("Block '{}' is defined twice", def.name);
+            }
+        }
+
+        for (name, id) in BlockId::BUILTIN {
+            if by_name.get(name) != Some(&id) {
+                bail!("Block '{name}' has to be defined, as block number {}", id.raw());
+            }
+        }
+
+        let mut opaque = Box::new([false; MAX_BLOCKS]);
+        let mut collidable = Box::new([false; MAX_BLOCKS]);
+        let mut light_emission = Box::new([0; MAX_BLOCKS]);
+        for (id, def) in defs.iter().enumerate() {
+            opaque[id] = def.opaque;
+            collidable[id] = def.collision != CollisionShape::None;
+            light_emission[id] = def.light_emission;
+        }
+
+        Ok(Self {
+            checksum: checksum(&defs),
+            defs,
+            by_name,
+            opaque,
+            collidable,
+            light_emission,
+        })
+    }
+
+    /// None for ids that aren't defined.
+    pub fn get(&self, id: BlockId) -> Option<&BlockDef> {
+        self.defs.get(id.raw() as usize)
+    }
+
+    pub fn id_of(&self, name: &str) -> Option<BlockId> {
+        self.by_name.get(name).copied()
+    }
+
+    #[inline]
+    pub fn is_opaque(&self, id: BlockId) -> bool {
+        self.opaque[id.raw() as usize & (MAX_BLOCKS - 1)]
+    }
+
+    pub fn is_waterloggable(&self, id: BlockId) -> bool {
+        self.get(id).is_some_and(|def| def.waterloggable)
+    }
+
+    #[inline]
+    pub fn is_collidable(&self, id: BlockId) -> bool {
+        self.collidable[id.raw() as usize & (MAX_BLOCKS - 1)]
+    }
+
+    #[inline]
+    pub fn light_emission(&self, id: BlockId) -> u8 {
+        self.light_emission[id.raw() as usize & (MAX_BLOCKS - 1)]
+    }
+
+    pub fn len(&self) -> usize {
+        self.defs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.defs.is_empty()
+    }
+
+    /// In id order.
+    pub fn iter(&self) -> impl Iterator<Item = (BlockId, &BlockDef)> {
+        self.defs.iter().enumerate().map(|(id, def)| (BlockId::from_raw(id as u16), def))
+    }
+
+    /// Depends only on the definitions themselves, not on how the file is formatted.
+    pub fn checksum(&self) -> u64 {
+        self.checksum
+    }
+}
+
+fn parse_definitions(src: &str) -> anyhow::Result<Vec<BlockDef>> {
+    let mut defs: Vec<BlockDef> = Vec::new();
+
+    for (line_idx, line) in src.lines().enumerate() {
+        let line_num = line_idx + 1;
+        let line = line.split('#').next().unwrap().trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        if let Some(name) = line.strip_prefix('[') {
+            let Some(name) = name.strip_suffix(']') else {
+                bail!("line {line_num}: Expected ']'");
+            };
+            let name = name.trim();
+            if name.is_empty() || !name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_') {
+                bail!("line {line_num}: Invalid block name '{name}', only a-z, 0-9 and _ are allowed");
+            }
+            defs.push(BlockDef {
+                name: name.into(),
+                textures: std::array::from_fn(|_| name.into()),
+                opaque: true,
+                collision: CollisionShape::Full,
+                light_emission: 0,
+                hardness: 1.0,
+                waterloggable: false,
+            });
+            continue;
+        }
+
+        let Some((key, value)) = line.split_once('=') else {
+            bail!("line {line_num}: Expected '[block name]' or 'key = value'");
+        };
+        let (key, value) = (key.trim(), value.trim());
+        let Some(def) = defs.last_mut() else {
+            bail!("line {line_num}: '{key}' outside of a block");
+        };
+        parse_property(def, key, value).with_context(|| format!("line {line_num}"))?;
+    }
+
+    Ok(defs)
+}
+
+fn parse_property(def: &mut BlockDef, key: &str, value: &str) -> anyhow::Result<()> {
+    if let Some(faces) = key.strip_prefix("texture") {
+        let faces: &[usize] = match faces {
+            "" => &[0, 1, 2, 3, 4, 5],
+            ".side" => &[0, 1, 4, 5],
+            ".top" | ".+y" => &[2],
+            ".bottom" | ".-y" => &[3],
+            ".+x" => &[0],
+            ".-x" => &[1],
+            ".+z" => &[4],
+            ".-z" => &[5],
+            _ => bail!("Unknown face in '{key}'"),
+        };
+        if value.is_empty() {
+            bail!("Missing texture name");
+        }
+        for &face in faces {
+            def.textures[face] = value.into();
+        }
+        return Ok(());
+    }
+
+    match key {
+        "opaque" => def.opaque = value.parse().context("Expected true or false")?,
+        "collision" => {
+            def.collision = parse_collision(value)?;
+            // Partial blocks leave room for water
+            def.waterloggable = matches!(def.collision, CollisionShape::Box { .. });
+        }
+        "waterloggable" => def.waterloggable = value.parse().context("Expected true or false")?,
+        "light" => {
+            def.light_emission = value.parse().context("Expected a light level")?;
+            if def.light_emission > 15 {
+                bail!("Light level {} is over 15", def.light_emission);
+            }
+        }
+        "hardness" => {
+            def.hardness = value.parse().context("Expected a number")?;
+            if !(def.hardness >= 0.0 && def.hardness.is_finite()) {
+                bail!("Hardness has to be 0 or more");
+            }
+        }
+        _ => bail!("Unknown property '{key}'"),
+    }
+    Ok(())
+}
+
+fn parse_collision(value: &str) -> anyhow::Result<CollisionShape> {
+    let mut parts = value.split_whitespace();
+    match parts.next() {
+        Some("none") => Ok(CollisionShape::None),
+        Some("full") => Ok(CollisionShape::Full),
+        Some("box") => {
+            let coords = parts
+                .map(|part| part.parse::<f32>())
+                .collect::<Result<Vec<_>, _>>()
+                .context("Expected numbers for the box")?;
+            let [x0, y0, z0, x1, y1, z1] = coords[..] else {
+                bail!("Expected 'box x0 y0 z0 x1 y1 z1'");
+            };
+            let (min, max) = (Vec3::new(x0, y0, z0), Vec3::new(x1, y1, z1));
+            if !(min.cmpge(Vec3::ZERO).all() && max.cmple(Vec3::ONE).all() && min.cmplt(max).all()) {
+                bail!("The box has to be within the block, with min < max");
+            }
+            Ok(CollisionShape::Box { min, max })
+        }
+        _ => bail!("Expected none, full or box"),
+    }
+}
+
+// FNV-1a over everything that defines a block
+fn checksum(defs: &[BlockDef]) -> u64 {
+    let mut hash = 0xCBF2_9CE4_8422_2325u64;
+    let mut feed = |bytes: &[u8]| {
+        for &byte in bytes {
+            hash = (hash ^ byte as u64).wrapping_mul(0x100_0000_01B3);
+        }
+    };
+
+    for def in defs {
+        // Lengths first, so that the strings can't run into each other
+        for string in std::iter::once(&def.name).chain(&def.textures) {
+            feed(&(string.len() as u32).to_le_bytes());
+            feed(string.as_bytes());
+        }
+        feed(&[def.opaque as u8, def.light_emission, def.waterloggable as u8]);
+        match def.collision {
+            CollisionShape::None => feed(&[0]),
+            CollisionShape::Full => feed(&[1]),
+            CollisionShape::Box { min, max } => {
+                feed(&[2]);
+                for v in min.to_array().into_iter().chain(max.to_array()) {
+                    feed(&v.to_le_bytes());
+                }
+            }
+        }
+        feed(&def.hardness.to_le_bytes());
+    }
+    hash
+}
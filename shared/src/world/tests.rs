@@ -0,0 +1,522 @@
+#![cfg(test)]
+
+use std::collections::HashMap;
+
+use glam::{IVec3, UVec3};
+
+use crate::{
+    serialization::{ByteReader, ByteWriter},
+    worldgen::TerrainGenerator,
+};
+
+use super::{
+    block::{Block, BlockId},
+    block_entity::{BlockEntity, BlockEntityData, ItemStack, Orientation, MAX_BLOCK_ENTITIES},
+    chunk::{self, Chunk, CHUNK_VOLUME},
+    light::{Light, LightEngine, LightWorld, MAX_LIGHT},
+    registry::{BlockRegistry, CollisionShape},
+};
+
+fn encode(chunk: &Chunk) -> Vec<u8> {
+    let mut buf = vec![0; Chunk::MAX_ENCODED_SIZE];
+    let mut writer = ByteWriter::new(&mut buf);
+    chunk.encode(&mut writer);
+    let len = writer.bytes_written();
+    buf.truncate(len);
+    buf
+}
+
+#[test]
+fn chunk_encoding_roundtrip() {
+    let generator = TerrainGenerator::new(0x5EED);
+
+    let mut checkerboard = Chunk::new();
+    for i in 0..4096u32 {
+        let pos = UVec3::new(i >> 8, i & 15, (i >> 4) & 15);
+        if (pos.x + pos.y + pos.z).is_multiple_of(2) {
+            checkerboard.set_at(pos, Block::STONE);
+        }
+    }
+
+    let chunks = [
+        Chunk::new(),
+        generator.generate_chunk(IVec3::new(0, 3, 0)),
+        generator.generate_chunk(IVec3::new(-5, 2, 9)),
+        checkerboard,
+    ];
+
+    for chunk in &chunks {
+        let bytes = encode(chunk);
+        let decoded = Chunk::decode(&mut ByteReader::new(&bytes)).expect("failed to decode chunk");
+        assert!(chunk.iter().eq(decoded.iter()));
+    }
+}
+
+#[test]
+fn chunk_decoding_rejects_garbage() {
+    let bytes = encode(&TerrainGenerator::new(1).generate_chunk(IVec3::new(0, 3, 0)));
+
+    assert!(Chunk::decode(&mut ByteReader::new(&bytes[..bytes.len() - 1])).is_none());
+    assert!(Chunk::decode(&mut ByteReader::new(&[])).is_none());
+    // 3 bits per block isn't a thing
+    assert!(Chunk::decode(&mut ByteReader::new(&[3, 1, 0, 0, 0])).is_none());
+
+    // Valid layout, but indices point past the end of the palette
+    let mut bad_index = vec![1, 1, 0, 0, 0];
+    bad_index.extend(std::iter::repeat_n(0xFF, 4096 / 8));
+    assert!(Chunk::decode(&mut ByteReader::new(&bad_index)).is_none());
+}
+
+#[test]
+fn every_block_has_its_own_slot() {
+    let mut chunk = Chunk::new();
+    for i in 0..4096u32 {
+        chunk.set_at(UVec3::new(i >> 8, i & 15, (i >> 4) & 15), Block::TEST);
+    }
+    // 4096 positions and 4096 slots, so none were missed only if none were shared
+    assert!(chunk.iter().all(|block| block == Block::TEST));
+}
+
+// The n-th block that isn't complex, those can't be set directly
+fn plain_block(n: u16) -> Block {
+    Block::from_raw((n & 0x3FF) | ((n & !0x3FF) << 1))
+}
+
+// xorshift, to get the same "random" positions every run
+fn pseudo_random(state: &mut u32) -> u32 {
+    *state ^= *state << 13;
+    *state ^= *state >> 17;
+    *state ^= *state << 5;
+    *state
+}
+
+#[test]
+fn palette_matches_flat_array() {
+    let mut chunk = Chunk::new();
+    let mut reference = [[[Block::AIR; 16]; 16]; 16];
+
+    // Grows through every index width, with plenty of overwrites in between
+    let mut rng = 0x1234_5678;
+    for block_types in [2, 3, 5, 17, 300, 5000] {
+        for _ in 0..6000 {
+            let r = pseudo_random(&mut rng);
+            let pos = UVec3::new(r & 15, (r >> 4) & 15, (r >> 8) & 15);
+            let block = plain_block(((r >> 12) % block_types) as u16);
+
+            chunk.set_at(pos, block);
+            reference[pos.x as usize][pos.y as usize][pos.z as usize] = block;
+        }
+
+        for x in 0..16 {
+            for y in 0..16 {
+                for z in 0..16 {
+                    assert_eq!(chunk.get_at(UVec3::new(x, y, z)), reference[x as usize][y as usize][z as usize]);
+                }
+            }
+        }
+    }
+
+    let bytes = encode(&chunk);
+    let decoded = Chunk::decode(&mut ByteReader::new(&bytes)).unwrap();
+    assert!(chunk.iter().eq(decoded.iter()));
+}
+
+#[test]
+fn palette_stays_small() {
+    let mut chunk = Chunk::new();
+    assert_eq!(chunk.uniform_block(), Some(Block::AIR));
+    let uniform_size = chunk.memory_usage();
+
+    // Only ever two different blocks in the chunk at once, so the palette
+    // should get cleaned up rather than widening the indices
+    for i in 0..4096u32 {
+        let pos = UVec3::new(i >> 8, i & 15, (i >> 4) & 15);
+        chunk.set_at(pos, Block::from_raw(1 + (i % 2) as u16));
+        chunk.set_at(pos, plain_block(1000 + i as u16));
+        chunk.set_at(pos, Block::STONE);
+    }
+    assert!(chunk.iter().all(|block| block == Block::STONE));
+    assert!(encode(&chunk).len() < 1100);
+
+    chunk.fill(Block::DIRT);
+    assert_eq!(chunk.uniform_block(), Some(Block::DIRT));
+    assert_eq!(chunk.memory_usage(), uniform_size);
+}
+
+#[test]
+fn portable_block_idx_is_a_permutation() {
+    let mut seen = vec![false; CHUNK_VOLUME];
+    for u in 0..CHUNK_VOLUME as u32 {
+        let idx = chunk::block_idx_portable(u);
+        assert!(!seen[idx], "index {idx} produced twice");
+        seen[idx] = true;
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+#[test]
+fn block_idx_paths_agree() {
+    if !std::arch::is_x86_feature_detected!("bmi2") {
+        eprintln!("BMI2 not supported on this CPU, nothing to compare against");
+        return;
+    }
+
+    for u in 0..CHUNK_VOLUME as u32 {
+        // safe: checked above
+        let bmi2 = unsafe { chunk::block_idx_bmi2(u) };
+        assert_eq!(bmi2, chunk::block_idx_portable(u), "mismatch at x={} y={} z={}", u >> 8, u & 15, (u >> 4) & 15);
+    }
+}
+
+#[test]
+fn builtin_registry_is_valid() {
+    let registry = BlockRegistry::builtin();
+    for (name, id) in BlockId::BUILTIN {
+        assert_eq!(registry.id_of(name), Some(id));
+        assert_eq!(&*registry.get(id).unwrap().name, name);
+    }
+
+    assert!(!registry.is_opaque(BlockId::AIR) && !registry.is_collidable(BlockId::AIR));
+    assert!(!registry.is_opaque(BlockId::WATER));
+    assert!(registry.is_opaque(BlockId::STONE) && registry.is_collidable(BlockId::STONE));
+    assert!(!registry.is_waterloggable(BlockId::STONE));
+    assert!(registry.is_waterloggable(registry.id_of("stone_slab").unwrap()));
+
+    let grass = registry.get(BlockId::GRASS).unwrap();
+    assert_eq!(&*grass.textures[2], "grass_top");
+    assert_eq!(&*grass.textures[3], "dirt");
+    assert_eq!(&*grass.textures[0], "grass_side");
+
+    // Ids nothing is defined for are neither
+    let unknown = BlockId::from_raw(registry.len() as u16 + 10);
+    assert!(registry.get(unknown).is_none());
+    assert!(!registry.is_opaque(unknown) && !registry.is_collidable(unknown));
+}
+
+const MINIMAL_BLOCKS: &str = "
+[air]
+opaque = false
+collision = none
+[test]
+[water]
+[stone]
+[dirt]
+[grass]
+[sand]
+[snow]
+";
+
+#[test]
+fn registry_parses_properties() {
+    let src = format!(
+        "{MINIMAL_BLOCKS}
+        [lamp]   # glows
+        texture = lamp_side
+        texture.top = lamp_top
+        light = 14
+        hardness = 0.25
+        opaque = false
+        collision = box 0.25 0 0.25 0.75 0.5 0.75
+        "
+    );
+    let registry = BlockRegistry::parse(&src).unwrap();
+    let lamp = registry.get(registry.id_of("lamp").unwrap()).unwrap();
+    assert_eq!(lamp.light_emission, 14);
+    assert_eq!(lamp.hardness, 0.25);
+    assert!(lamp.waterloggable);
+    assert!(!lamp.opaque);
+    assert_eq!(
+        lamp.collision,
+        CollisionShape::Box { min: glam::vec3(0.25, 0.0, 0.25), max: glam::vec3(0.75, 0.5, 0.75) }
+    );
+    assert_eq!(lamp.textures.iter().filter(|t| &***t == "lamp_top").count(), 1);
+    assert_eq!(lamp.textures.iter().filter(|t| &***t == "lamp_side").count(), 5);
+}
+
+#[test]
+fn registry_rejects_bad_definitions() {
+    let bad = [
+        "[stone]\n[air]".to_string(),                  // built-in blocks out of order
+        format!("{MINIMAL_BLOCKS}[stone]"),            // duplicate
+        format!("{MINIMAL_BLOCKS}[Bad Name]"),
+        format!("{MINIMAL_BLOCKS}[lamp]\nlight = 16"),
+        format!("{MINIMAL_BLOCKS}[lamp]\nglow = 1"),
+        format!("{MINIMAL_BLOCKS}[lamp]\ntexture.up = x"),
+        format!("{MINIMAL_BLOCKS}[lamp]\ncollision = box 0 0 0 1 2 1"),
+        format!("{MINIMAL_BLOCKS}[lamp]\nopaque"),
+        format!("opaque = true\n{MINIMAL_BLOCKS}"),
+    ];
+    for src in &bad {
+        assert!(BlockRegistry::parse(src).is_err(), "accepted {src:?}");
+    }
+
+    // Errors point at the line
+    let Err(err) = BlockRegistry::parse(&format!("{MINIMAL_BLOCKS}[lamp]\nlight = 16")) else {
+        panic!("accepted light = 16");
+    };
+    assert!(format!("{err:#}").contains("line 13"), "{err:#}");
+}
+
+#[test]
+fn registry_checksum_only_depends_on_definitions() {
+    let a = BlockRegistry::parse(MINIMAL_BLOCKS).unwrap();
+    let reformatted = MINIMAL_BLOCKS.replace("opaque = false", "  opaque=false   # see-through").replace('\n', "\n\n");
+    let b = BlockRegistry::parse(&reformatted).unwrap();
+    assert_eq!(a.checksum(), b.checksum());
+
+    let changed = BlockRegistry::parse(&MINIMAL_BLOCKS.replace("[snow]", "[snow]\nhardness = 2")).unwrap();
+    assert_ne!(a.checksum(), changed.checksum());
+    let renamed = BlockRegistry::parse(&format!("{MINIMAL_BLOCKS}[a]\ntexture = bc")).unwrap();
+    let renamed2 = BlockRegistry::parse(&format!("{MINIMAL_BLOCKS}[ab]\ntexture = c")).unwrap();
+    assert_ne!(renamed.checksum(), renamed2.checksum());
+}
+
+fn sign(text: &str) -> BlockEntity {
+    BlockEntity {
+        block: Block::TEST,
+        orientation: Orientation::Nz,
+        data: BlockEntityData::Sign(text.into()),
+    }
+}
+
+#[test]
+fn block_entities_follow_their_blocks() {
+    let mut chunk = Chunk::new();
+    let (a, b) = (UVec3::new(1, 2, 3), UVec3::new(15, 0, 7));
+    assert!(chunk.set_block_entity_at(a, sign("hello")));
+    assert!(chunk.set_block_entity_at(b, BlockEntity::new(Block::STONE)));
+
+    // Complex blocks read as the block they stand for
+    assert_eq!(chunk.get_at(a), Block::TEST);
+    assert_eq!(chunk.get_at(b), Block::STONE);
+    assert!(chunk.iter().all(|block| !block.is_complex()));
+    assert_eq!(chunk.block_entity_at(a), Some(&sign("hello")));
+    assert_eq!(chunk.block_entity_at(UVec3::ZERO), None);
+
+    chunk.block_entity_at_mut(a).unwrap().data = BlockEntityData::Sign("bye".into());
+    assert_eq!(chunk.block_entity_at(a), Some(&sign("bye")));
+
+    // Overwriting the block drops the entity, removing the entity keeps the block
+    chunk.set_at(a, Block::DIRT);
+    assert_eq!(chunk.block_entity_at(a), None);
+    assert_eq!(chunk.remove_block_entity_at(b), Some(BlockEntity::new(Block::STONE)));
+    assert_eq!(chunk.get_at(b), Block::STONE);
+    assert_eq!(chunk.block_entity_count(), 0);
+
+    // Too long to fit in a message
+    assert!(!chunk.set_block_entity_at(a, sign(&"a".repeat(1000))));
+    assert_eq!(chunk.get_at(a), Block::DIRT);
+}
+
+#[test]
+fn block_entity_table_fills_up() {
+    let mut chunk = Chunk::new();
+    let positions: Vec<UVec3> = (0..CHUNK_VOLUME as u32).map(|i| UVec3::new(i >> 8, i & 15, (i >> 4) & 15)).collect();
+
+    for &pos in &positions[..MAX_BLOCK_ENTITIES] {
+        assert!(chunk.set_block_entity_at(pos, BlockEntity::new(Block::TEST)));
+    }
+    assert!(!chunk.set_block_entity_at(positions[MAX_BLOCK_ENTITIES], BlockEntity::new(Block::TEST)));
+    // Replacing an existing one still works
+    assert!(chunk.set_block_entity_at(positions[0], sign("replaced")));
+
+    // Freed slots get reused
+    chunk.set_at(positions[10], Block::AIR);
+    assert!(chunk.set_block_entity_at(positions[MAX_BLOCK_ENTITIES], sign("new")));
+    assert_eq!(chunk.block_entity_at(positions[MAX_BLOCK_ENTITIES]), Some(&sign("new")));
+    assert_eq!(chunk.block_entity_count(), MAX_BLOCK_ENTITIES);
+
+    chunk.fill(Block::STONE);
+    assert_eq!(chunk.block_entity_count(), 0);
+    assert_eq!(chunk.block_entity_at(positions[0]), None);
+}
+
+#[test]
+fn block_entities_are_encoded() {
+    let mut chunk = TerrainGenerator::new(0x5EED).generate_chunk(IVec3::new(0, 3, 0));
+    let chest = BlockEntity {
+        block: Block::DIRT,
+        orientation: Orientation::Py,
+        data: BlockEntityData::Inventory(vec![ItemStack { item: BlockId::SAND, count: 12 }, ItemStack::EMPTY].into()),
+    };
+    chunk.set_block_entity_at(UVec3::new(4, 5, 6), chest.clone());
+    chunk.set_block_entity_at(UVec3::new(7, 8, 9), sign("Welcome"));
+    chunk.set_block_entity_at(UVec3::new(0, 0, 0), sign("gone"));
+    chunk.set_at(UVec3::new(0, 0, 0), Block::AIR);
+
+    let bytes = encode(&chunk);
+    let decoded = Chunk::decode(&mut ByteReader::new(&bytes)).unwrap();
+    assert!(chunk.iter().eq(decoded.iter()));
+    assert!(chunk.block_entities().eq(decoded.block_entities()));
+    assert_eq!(decoded.block_entity_at(UVec3::new(4, 5, 6)), Some(&chest));
+
+    // Without the entities, or with one more than there are blocks for
+    assert!(Chunk::decode(&mut ByteReader::new(&bytes[..bytes.len() - 1])).is_none());
+    let mut orphan = encode(&Chunk::new());
+    orphan.truncate(orphan.len() - 2);
+    orphan.extend([1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+    assert!(Chunk::decode(&mut ByteReader::new(&orphan)).is_none());
+}
+
+// 2x2 columns of two chunks, x and z from -16 to 15
+#[derive(Default)]
+struct LightTestWorld {
+    chunks: HashMap<IVec3, Box<Chunk>>,
+}
+
+impl LightWorld for LightTestWorld {
+    const HEIGHT_CHUNKS: i32 = 2;
+
+    fn chunk(&self, chunk_pos: IVec3) -> Option<&Chunk> {
+        self.chunks.get(&chunk_pos).map(|chunk| &**chunk)
+    }
+
+    fn chunk_mut(&mut self, chunk_pos: IVec3) -> Option<&mut Chunk> {
+        self.chunks.get_mut(&chunk_pos).map(|chunk| &mut **chunk)
+    }
+}
+
+impl LightTestWorld {
+    const CHUNK_POSITIONS: [IVec3; 8] = [
+        IVec3::new(-1, 0, -1), IVec3::new(0, 0, -1), IVec3::new(-1, 0, 0), IVec3::new(0, 0, 0),
+        IVec3::new(-1, 1, -1), IVec3::new(0, 1, -1), IVec3::new(-1, 1, 0), IVec3::new(0, 1, 0),
+    ];
+
+    // Loads the chunks in the given order, with `block_at` deciding what's in them
+    fn load(order: impl IntoIterator<Item = IVec3>, block_at: impl Fn(IVec3) -> Block, engine: &mut LightEngine) -> Self {
+        let mut world = Self::default();
+        for chunk_pos in order {
+            let mut chunk = Chunk::new();
+            for i in 0..CHUNK_VOLUME as u32 {
+                let pos = UVec3::new(i % 16, (i / 16) % 16, i / 256);
+                chunk.set_at(pos, block_at(chunk_pos * 16 + pos.as_ivec3()));
+            }
+            world.chunks.insert(chunk_pos, chunk);
+            engine.on_chunk_loaded(&mut world, chunk_pos, None, &BlockRegistry::builtin());
+        }
+        world
+    }
+
+    fn set_block(&mut self, pos: IVec3, block: Block, engine: &mut LightEngine) {
+        let chunk = self.chunks.get_mut(&(pos >> 4)).unwrap();
+        let local = (pos & 15).as_uvec3();
+        let old = chunk.get_at(local);
+        chunk.set_at(local, block);
+        engine.on_block_changed(self, pos, old, &BlockRegistry::builtin());
+    }
+
+    fn light_at(&self, pos: IVec3) -> Light {
+        self.chunks[&(pos >> 4)].light_at((pos & 15).as_uvec3())
+    }
+
+    fn all_light(&self) -> Vec<Light> {
+        let mut lights = Vec::new();
+        for x in -16..16 {
+            for y in 0..32 {
+                for z in -16..16 {
+                    lights.push(self.light_at(IVec3::new(x, y, z)));
+                }
+            }
+        }
+        lights
+    }
+}
+
+fn floor(pos: IVec3) -> Block {
+    if pos.y == 0 { Block::STONE } else { Block::AIR }
+}
+
+#[test]
+fn sky_light_reaches_down_to_the_floor() {
+    let mut engine = LightEngine::new();
+    // Bottom up, so the light has to make its way down into chunks that are already there
+    let world = LightTestWorld::load(LightTestWorld::CHUNK_POSITIONS, floor, &mut engine);
+
+    for pos in [IVec3::new(0, 1, 0), IVec3::new(-16, 1, 15), IVec3::new(5, 31, -3)] {
+        assert_eq!(world.light_at(pos), Light::new(MAX_LIGHT, 0));
+    }
+    assert_eq!(world.light_at(IVec3::new(0, 0, 0)), Light::DARK);
+    // Every chunk got some light, so every chunk needs remeshing
+    assert_eq!(engine.take_changed_chunks().filter(|pos| LightTestWorld::CHUNK_POSITIONS.contains(pos)).count(), 8);
+}
+
+#[test]
+fn block_light_fades_and_goes_away() {
+    let mut engine = LightEngine::new();
+    let mut world = LightTestWorld::load(LightTestWorld::CHUNK_POSITIONS, floor, &mut engine);
+    let lamp = Block::new(BlockRegistry::builtin().id_of("lamp").unwrap());
+
+    world.set_block(IVec3::new(0, 5, 0), lamp, &mut engine);
+    assert_eq!(world.light_at(IVec3::new(0, 5, 0)).block(), MAX_LIGHT);
+    assert_eq!(world.light_at(IVec3::new(3, 5, 0)).block(), MAX_LIGHT - 3);
+    // Around a corner, and over into the next chunk
+    assert_eq!(world.light_at(IVec3::new(-2, 6, -1)).block(), MAX_LIGHT - 4);
+    assert_eq!(world.light_at(IVec3::new(0, 0, 0)).block(), 0);
+
+    world.set_block(IVec3::new(0, 5, 0), Block::AIR, &mut engine);
+    assert!(world.all_light().iter().all(|light| light.block() == 0));
+}
+
+#[test]
+fn incremental_updates_match_lighting_from_scratch() {
+    let lamp = Block::new(BlockRegistry::builtin().id_of("lamp").unwrap());
+    // Some caves with a few lamps in them
+    let terrain = |pos: IVec3| {
+        let hash = ((pos.x * 73856093) ^ (pos.y * 19349663) ^ (pos.z * 83492791)) as u32 % 100;
+        match hash {
+            _ if pos.y > 12 => Block::AIR,
+            0 => lamp,
+            1..=59 => Block::STONE,
+            _ => Block::AIR,
+        }
+    };
+
+    let mut engine = LightEngine::new();
+    let mut world = LightTestWorld::load(LightTestWorld::CHUNK_POSITIONS, terrain, &mut engine);
+    let mut changed = HashMap::new();
+    let mut state = 7;
+    for i in 0..200 {
+        let pos = IVec3::new(
+            (pseudo_random(&mut state) % 32) as i32 - 16,
+            (pseudo_random(&mut state) % 16) as i32,
+            (pseudo_random(&mut state) % 32) as i32 - 16,
+        );
+        let block = [Block::AIR, Block::STONE, lamp, Block::WATER][i % 4];
+        world.set_block(pos, block, &mut engine);
+        changed.insert(pos, block);
+    }
+
+    // Top down this time
+    let mut fresh_engine = LightEngine::new();
+    let fresh = LightTestWorld::load(
+        LightTestWorld::CHUNK_POSITIONS.into_iter().rev(),
+        |pos| changed.get(&pos).copied().unwrap_or_else(|| terrain(pos)),
+        &mut fresh_engine,
+    );
+    assert!(world.all_light() == fresh.all_light());
+}
+
+#[test]
+fn replaced_chunks_only_update_what_changed() {
+    let mut engine = LightEngine::new();
+    let mut world = LightTestWorld::load(LightTestWorld::CHUNK_POSITIONS, floor, &mut engine);
+
+    // Like the server sending the chunk again with a roof over part of it
+    let chunk_pos = IVec3::new(0, 0, 0);
+    let mut roofed = Chunk::new();
+    for i in 0..CHUNK_VOLUME as u32 {
+        let pos = UVec3::new(i % 16, (i / 16) % 16, i / 256);
+        let roof = pos.y == 10 && pos.x < 8;
+        roofed.set_at(pos, if roof { Block::STONE } else { floor(pos.as_ivec3()) });
+    }
+    let old = world.chunks.insert(chunk_pos, roofed).unwrap();
+    engine.take_changed_chunks().for_each(drop);
+    engine.on_chunk_loaded(&mut world, chunk_pos, Some(&old), &BlockRegistry::builtin());
+
+    assert_eq!(world.light_at(IVec3::new(9, 5, 3)).sky(), MAX_LIGHT);
+    assert_eq!(world.light_at(IVec3::new(7, 5, 3)).sky(), MAX_LIGHT - 1);
+    assert_eq!(world.light_at(IVec3::new(3, 9, 3)).sky(), MAX_LIGHT - 4);
+    // The light under the roof comes in from both sides, one of them in the next chunk
+    assert_eq!(world.light_at(IVec3::new(0, 5, 0)).sky(), MAX_LIGHT - 1);
+    assert!(engine.take_changed_chunks().any(|pos| pos == chunk_pos));
+}
@@ -0,0 +1,190 @@
+// Terrain generation. Deterministic: the same seed and chunk position always
+// produce the exact same chunk, on any machine, so the client can generate
+// terrain on its own and the result can be verified against the server.
+
+pub mod noise;
+
+mod tests;
+
+use glam::{IVec3, UVec3};
+
+use crate::world::{
+    block::Block,
+    chunk::{Chunk, CHUNK_SIZE},
+};
+
+use self::noise::{derive_seed, Fractal};
+
+pub const SEA_LEVEL: i32 = 62;
+pub const SNOW_LINE: i32 = 118;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Biome {
+    Ocean,
+    Beach,
+    Plains,
+    Desert,
+    Tundra,
+    Mountains,
+}
+
+impl Biome {
+    fn surface_blocks(self, height: i32) -> (/* top */ Block, /* below top */ Block) {
+        match self {
+            Biome::Ocean | Biome::Beach | Biome::Desert => (Block::SAND, Block::SAND),
+            Biome::Plains => (Block::GRASS, Block::DIRT),
+            Biome::Tundra => (Block::SNOW, Block::DIRT),
+            Biome::Mountains if height >= SNOW_LINE => (Block::SNOW, Block::STONE),
+            Biome::Mountains => (Block::STONE, Block::STONE),
+        }
+    }
+}
+
+/// Everything about a single column of blocks that doesn't depend on the y coordinate.
+#[derive(Debug, Clone, Copy)]
+pub struct Column {
+    /// Y coordinate of the topmost solid block (ignoring caves)
+    pub height: i32,
+    pub biome: Biome,
+}
+
+pub struct TerrainGenerator {
+    seed: u64,
+
+    continents: Fractal,
+    mountains: Fractal,
+    hills: Fractal,
+    temperature: Fractal,
+
+    tunnels_a: Fractal,
+    tunnels_b: Fractal,
+    caverns: Fractal,
+}
+
+impl TerrainGenerator {
+    // How deep the biome-specific surface layer goes
+    const SURFACE_DEPTH: i32 = 4;
+
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            continents: Fractal::new(derive_seed(seed, 0), 3, 1.0 / 640.0),
+            mountains: Fractal::new(derive_seed(seed, 1), 2, 1.0 / 320.0),
+            hills: Fractal::new(derive_seed(seed, 2), 4, 1.0 / 72.0),
+            temperature: Fractal::new(derive_seed(seed, 3), 2, 1.0 / 800.0),
+
+            tunnels_a: Fractal::new(derive_seed(seed, 4), 2, 1.0 / 56.0),
+            tunnels_b: Fractal::new(derive_seed(seed, 5), 2, 1.0 / 56.0),
+            caverns: Fractal::new(derive_seed(seed, 6), 2, 1.0 / 96.0),
+        }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn column_at(&self, x: i32, z: i32) -> Column {
+        let (xf, zf) = (x as f32, z as f32);
+
+        let continentalness = self.continents.sample2(xf, zf);
+        let mountainousness = smoothstep(0.05, 0.45, self.mountains.sample2(xf, zf));
+        let hills = self.hills.sample2(xf, zf);
+
+        let height = SEA_LEVEL as f32
+            + 3.0
+            + continentalness * 28.0
+            + mountainousness * 48.0
+            + hills * (5.0 + 36.0 * mountainousness);
+        let height = height.floor() as i32;
+
+        let temperature = self.temperature.sample2(xf, zf);
+        let biome = if height < SEA_LEVEL - 2 {
+            Biome::Ocean
+        } else if height <= SEA_LEVEL + 1 {
+            Biome::Beach
+        } else if mountainousness > 0.5 {
+            Biome::Mountains
+        } else if temperature > 0.2 {
+            Biome::Desert
+        } else if temperature < -0.2 {
+            Biome::Tundra
+        } else {
+            Biome::Plains
+        };
+
+        Column { height, biome }
+    }
+
+    pub fn generate_chunk(&self, chunk_pos: IVec3) -> Box<Chunk> {
+        let mut chunk = Chunk::new();
+
+        let origin = chunk_pos * CHUNK_SIZE as i32;
+        let columns: [[Column; CHUNK_SIZE]; CHUNK_SIZE] = std::array::from_fn(|x| {
+            std::array::from_fn(|z| self.column_at(origin.x + x as i32, origin.z + z as i32))
+        });
+        let max_height = columns.iter().flatten().map(|col| col.height).max().unwrap();
+
+        // Nothing but air up here
+        if origin.y > max_height.max(SEA_LEVEL) {
+            return chunk;
+        }
+
+        for (x, row) in columns.iter().enumerate() {
+            for (z, &column) in row.iter().enumerate() {
+                for y in 0..CHUNK_SIZE {
+                    let world_pos = origin + IVec3::new(x as i32, y as i32, z as i32);
+                    let block = self.block_at(world_pos, column);
+                    if block != Block::AIR {
+                        chunk.set_at(UVec3::new(x as u32, y as u32, z as u32), block);
+                    }
+                }
+            }
+        }
+
+        chunk
+    }
+
+    fn block_at(&self, pos: IVec3, column: Column) -> Block {
+        let depth = column.height - pos.y;
+        if depth < 0 {
+            return if pos.y <= SEA_LEVEL { Block::WATER } else { Block::AIR };
+        }
+
+        if self.is_cave(pos, depth) {
+            return Block::AIR;
+        }
+
+        let (top, below_top) = column.biome.surface_blocks(column.height);
+        match depth {
+            0 => top,
+            d if d < Self::SURFACE_DEPTH => below_top,
+            _ => Block::STONE,
+        }
+    }
+
+    fn is_cave(&self, pos: IVec3, depth: i32) -> bool {
+        // Keep a solid floor at the bottom of the world, and don't poke holes right
+        // below the surface (especially the sea floor)
+        if pos.y <= 4 || depth < 6 {
+            return false;
+        }
+
+        let (x, y, z) = (pos.x as f32, pos.y as f32, pos.z as f32);
+
+        // Where two noise fields are both close to zero, their zero-surfaces intersect,
+        // which produces long, winding tunnels. Vertically squished to make them flatter.
+        const TUNNEL_WIDTH: f32 = 0.06;
+        let a = self.tunnels_a.sample3(x, y * 1.5, z);
+        if a.abs() < TUNNEL_WIDTH && self.tunnels_b.sample3(x, y * 1.5, z).abs() < TUNNEL_WIDTH {
+            return true;
+        }
+
+        // The occasional big open space, deeper down
+        pos.y < SEA_LEVEL - 16 && self.caverns.sample3(x, y * 2.0, z) > 0.45
+    }
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
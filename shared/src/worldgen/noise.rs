@@ -0,0 +1,166 @@
+// Gradient ("Perlin") noise built on integer hashing rather than a permutation
+// table, so that the seed is all the state there is.
+//
+// World generation has to produce the exact same blocks on every machine, so
+// only addition, multiplication and floor() are used on floats in here: those
+// are exactly specified by IEEE 754. Anything from libm (sin, exp, powf...)
+// is not, and must not be introduced.
+
+#[derive(Debug, Clone, Copy)]
+pub struct Noise {
+    seed: u32,
+}
+
+impl Noise {
+    pub const fn new(seed: u32) -> Self {
+        Self { seed }
+    }
+
+    /// Roughly in range -1..1, smooth, and zero at integer coordinates.
+    pub fn sample2(&self, x: f32, z: f32) -> f32 {
+        let (x0, z0) = (x.floor(), z.floor());
+        let (fx, fz) = (x - x0, z - z0);
+        let (ix, iz) = (x0 as i32, z0 as i32);
+
+        let n00 = grad2(hash(self.seed, ix, 0, iz), fx, fz);
+        let n10 = grad2(hash(self.seed, ix + 1, 0, iz), fx - 1.0, fz);
+        let n01 = grad2(hash(self.seed, ix, 0, iz + 1), fx, fz - 1.0);
+        let n11 = grad2(hash(self.seed, ix + 1, 0, iz + 1), fx - 1.0, fz - 1.0);
+
+        let (u, v) = (fade(fx), fade(fz));
+        lerp(v, lerp(u, n00, n10), lerp(u, n01, n11))
+    }
+
+    /// Roughly in range -1..1, smooth, and zero at integer coordinates.
+    pub fn sample3(&self, x: f32, y: f32, z: f32) -> f32 {
+        let (x0, y0, z0) = (x.floor(), y.floor(), z.floor());
+        let (fx, fy, fz) = (x - x0, y - y0, z - z0);
+        let (ix, iy, iz) = (x0 as i32, y0 as i32, z0 as i32);
+
+        let corner = |dx: i32, dy: i32, dz: i32| {
+            let h = hash(self.seed, ix + dx, iy + dy, iz + dz);
+            grad3(h, fx - dx as f32, fy - dy as f32, fz - dz as f32)
+        };
+
+        let (u, v, w) = (fade(fx), fade(fy), fade(fz));
+        lerp(
+            w,
+            lerp(v, lerp(u, corner(0, 0, 0), corner(1, 0, 0)), lerp(u, corner(0, 1, 0), corner(1, 1, 0))),
+            lerp(v, lerp(u, corner(0, 0, 1), corner(1, 0, 1)), lerp(u, corner(0, 1, 1), corner(1, 1, 1))),
+        )
+    }
+}
+
+/// Several octaves of `Noise` layered on top of each other, each with double the
+/// frequency and half the amplitude of the previous. Output is normalized back to
+/// roughly -1..1.
+#[derive(Debug, Clone)]
+pub struct Fractal {
+    octaves: Box<[Noise]>,
+    frequency: f32,
+}
+
+impl Fractal {
+    /// `frequency`: of the first octave, in 1/blocks
+    pub fn new(seed: u32, octaves: u32, frequency: f32) -> Self {
+        debug_assert!(octaves > 0);
+        Self {
+            octaves: (0..octaves)
+                .map(|i| Noise::new(mix(seed ^ i.wrapping_mul(0x9E37_79B9))))
+                .collect(),
+            frequency,
+        }
+    }
+
+    pub fn sample2(&self, x: f32, z: f32) -> f32 {
+        let mut freq = self.frequency;
+        let mut amplitude = 1.0;
+        let mut sum = 0.0;
+        let mut total_amplitude = 0.0;
+        for octave in self.octaves.iter() {
+            sum += octave.sample2(x * freq, z * freq) * amplitude;
+            total_amplitude += amplitude;
+            freq *= 2.0;
+            amplitude *= 0.5;
+        }
+        sum / total_amplitude
+    }
+
+    pub fn sample3(&self, x: f32, y: f32, z: f32) -> f32 {
+        let mut freq = self.frequency;
+        let mut amplitude = 1.0;
+        let mut sum = 0.0;
+        let mut total_amplitude = 0.0;
+        for octave in self.octaves.iter() {
+            sum += octave.sample3(x * freq, y * freq, z * freq) * amplitude;
+            total_amplitude += amplitude;
+            freq *= 2.0;
+            amplitude *= 0.5;
+        }
+        sum / total_amplitude
+    }
+}
+
+/// Derives an independent 32-bit seed from the world seed, so that the
+/// different noise layers don't end up correlated. `salt` identifies the layer.
+pub fn derive_seed(world_seed: u64, salt: u32) -> u32 {
+    // splitmix64
+    let mut z = world_seed.wrapping_add((salt as u64 + 1).wrapping_mul(0x9E37_79B9_7F4A_7C15));
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    (z ^ (z >> 31)) as u32
+}
+
+#[inline]
+fn hash(seed: u32, x: i32, y: i32, z: i32) -> u32 {
+    let mut h = mix(seed ^ (x as u32).wrapping_mul(0x27D4_EB2D));
+    h = mix(h ^ (y as u32).wrapping_mul(0x1656_67B1));
+    mix(h ^ (z as u32).wrapping_mul(0x85EB_CA77))
+}
+
+// murmur3's 32-bit finalizer
+#[inline]
+fn mix(mut h: u32) -> u32 {
+    h ^= h >> 16;
+    h = h.wrapping_mul(0x85EB_CA6B);
+    h ^= h >> 13;
+    h = h.wrapping_mul(0xC2B2_AE35);
+    h ^ (h >> 16)
+}
+
+#[inline]
+fn grad2(hash: u32, x: f32, z: f32) -> f32 {
+    match hash & 7 {
+        0 => x + z,
+        1 => -x + z,
+        2 => x - z,
+        3 => -x - z,
+        4 => x,
+        5 => -x,
+        6 => z,
+        _ => -z,
+    }
+}
+
+// The 12 cube edge directions of improved Perlin noise (with 4 repeated to get to 16)
+#[inline]
+fn grad3(hash: u32, x: f32, y: f32, z: f32) -> f32 {
+    let h = hash & 15;
+    let u = if h < 8 { x } else { y };
+    let v = match h {
+        0..=3 => y,
+        12 | 14 => x,
+        _ => z,
+    };
+    (if h & 1 == 0 { u } else { -u }) + (if h & 2 == 0 { v } else { -v })
+}
+
+#[inline]
+fn fade(t: f32) -> f32 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+#[inline]
+fn lerp(t: f32, a: f32, b: f32) -> f32 {
+    a + t * (b - a)
+}
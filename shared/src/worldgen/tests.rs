@@ -0,0 +1,76 @@
+#![cfg(test)]
+
+use glam::{IVec3, UVec3};
+
+use crate::world::chunk::{Chunk, CHUNK_SIZE};
+
+use super::TerrainGenerator;
+
+// FNV-1a over the blocks in x, y, z order (independent of how the chunk stores them)
+fn checksum(chunk: &Chunk) -> u64 {
+    let mut hash = 0xCBF2_9CE4_8422_2325u64;
+    for x in 0..CHUNK_SIZE as u32 {
+        for y in 0..CHUNK_SIZE as u32 {
+            for z in 0..CHUNK_SIZE as u32 {
+                for byte in chunk.get_at(UVec3::new(x, y, z)).raw().to_le_bytes() {
+                    hash = (hash ^ byte as u64).wrapping_mul(0x100_0000_01B3);
+                }
+            }
+        }
+    }
+    hash
+}
+
+const TEST_CHUNKS: [IVec3; 6] = [
+    IVec3::new(0, 3, 0),
+    IVec3::new(0, 4, 0),
+    IVec3::new(-7, 2, 12),
+    IVec3::new(31, 3, -45),
+    IVec3::new(-200, 5, -3),
+    IVec3::new(1000, 1, 1000),
+];
+
+#[test]
+fn test_generation_is_deterministic() {
+    let a = TerrainGenerator::new(0x1234_5678_9ABC_DEF0);
+    let b = TerrainGenerator::new(0x1234_5678_9ABC_DEF0);
+    for pos in TEST_CHUNKS {
+        assert_eq!(checksum(&a.generate_chunk(pos)), checksum(&b.generate_chunk(pos)));
+    }
+}
+
+#[test]
+fn test_different_seeds_differ() {
+    let a = TerrainGenerator::new(1);
+    let b = TerrainGenerator::new(2);
+    let differing = TEST_CHUNKS
+        .iter()
+        .filter(|&&pos| checksum(&a.generate_chunk(pos)) != checksum(&b.generate_chunk(pos)))
+        .count();
+    assert!(differing > TEST_CHUNKS.len() / 2);
+}
+
+// The same seed must give the same terrain on every machine and every run. If
+// this fails after an intentional change to generation, update the checksums;
+// otherwise something platform-dependent has crept into the generator.
+#[test]
+fn test_generation_matches_reference() {
+    let generator = TerrainGenerator::new(0x5EED);
+    let checksums: Vec<u64> = TEST_CHUNKS
+        .iter()
+        .map(|&pos| checksum(&generator.generate_chunk(pos)))
+        .collect();
+
+    assert_eq!(
+        checksums,
+        [
+            0xBDDC_ABD3_5E49_9335,
+            0x4A01_98CA_5151_2685,
+            0x680F_9807_8F35_E325,
+            0x6166_ECA6_2A88_CC57,
+            0xB9D1_03FD_6854_A325,
+            0xBBB9_FE68_2B66_93CD,
+        ]
+    );
+}
+